@@ -0,0 +1,87 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! renting funds of a HODLer to advertisers
+//!
+//! A HODLer offers ad space, that is funding of amount satoshis for term blocks at a price, with a publication in
+//! the category ADSPACE. An advertiser rents it by submitting an ad to the HODLer's node, that funds the ad from its
+//! wallet once a payment of the price to the address given to the advertiser has RENT_CONFIRMATIONS confirmations.
+//! A rental not paid within RENT_PAYMENT_WINDOW blocks is dropped and at most MAX_OPEN_RENTALS of an offer wait for
+//! payment, so that advertisers can not make the node watch for ever more payments.
+
+use crate::ad::Ad;
+use serde_json::json;
+
+/// category of publications offering ad space
+pub const ADSPACE: &str = "adspace";
+
+/// confirmations of a payment of rent before the rented ad space is funded
+pub const RENT_CONFIRMATIONS: u32 = 6;
+
+/// blocks a rental waits for payment of rent before it is dropped
+pub const RENT_PAYMENT_WINDOW: u32 = 144;
+
+/// rentals of an offer waiting for payment at most
+pub const MAX_OPEN_RENTALS: usize = 16;
+
+/// ad space offered by this node
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Offer {
+    /// id of the publication advertising the offer
+    pub id: String,
+    /// satoshis funding a rented ad
+    pub amount: u64,
+    /// blocks a rented ad is funded for
+    pub term: u16,
+    /// satoshis to pay for the rent
+    pub price: u64
+}
+
+impl Offer {
+    /// publication advertising ad space, its content is the offer as JSON
+    pub fn ad(amount: u64, term: u16, price: u64) -> Ad {
+        let content = json!({"amount": amount, "term": term, "price": price}).to_string();
+        Ad::new(ADSPACE.to_string(), format!("{} satoshis for {} blocks at {} satoshis", amount, term, price), content.as_str())
+    }
+}
+
+/// ad space rented by an advertiser
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Rental {
+    /// id of the publication of the advertiser
+    pub id: String,
+    /// id of the offer rented
+    pub offer: String,
+    /// address to pay the price to
+    pub address: String,
+    /// satoshis to pay for the rent
+    pub price: u64,
+    /// satoshis funding the ad
+    pub amount: u64,
+    /// blocks the ad is funded for
+    pub term: u16,
+    /// height of the block the rental is dropped with if not yet paid
+    pub expiry: u32,
+    /// transaction paying the price once confirmed
+    pub paid: Option<String>,
+    /// block confirming the payment
+    pub block: Option<String>,
+    /// transaction funding the publication
+    pub funded: Option<String>,
+    /// paid with enough confirmations but not funded as the node was restarted since the latest offer,
+    /// it is funded after ad space is offered again
+    pub stalled: bool
+}
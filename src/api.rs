@@ -1,310 +1,1286 @@
-use jsonrpc_http_server::{ServerBuilder};
-use jsonrpc_http_server::jsonrpc_core::{IoHandler, Value, Params, Error};
+use jsonrpc_http_server::{ServerBuilder, ServerHandler, Rpc, RestApi, RequestMiddlewareAction, MetaExtractor, hyper, tokio};
+use jsonrpc_http_server::cors::AccessControlAllowHeaders;
+use jsonrpc_http_server::jsonrpc_core::{MetaIoHandler, Metadata, Value, Params, Error, ErrorCode, BoxFuture};
+use serde::de::DeserializeOwned;
+use serde_json::Map;
+use jsonrpc_http_server::jsonrpc_core::futures::{future, Future, Stream};
+use jsonrpc_http_server::tokio::timer::Delay;
+use jsonrpc_http_server::tokio::io::{AsyncRead, AsyncWrite};
+use jsonrpc_http_server::tokio::net::TcpListener;
+#[cfg(unix)]
+use jsonrpc_http_server::tokio::net::UnixListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{ServerConfig, NoClientAuth};
+use tokio_rustls::rustls::internal::pemfile;
 use std::net::SocketAddr;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
 use std::str::FromStr;
-use crate::store::SharedContentStore;
-use bitcoin::{Address};
-use bitcoin_hashes::sha256;
-
-fn parse_arguments (p: Params, api_key: &str) -> Result<Vec<String>, Error> {
-    let mut result = Vec::new();
-    match p {
-        Params::Array(array) => {
-            for s in &array {
-                match s {
-                    Value::String(s) => result.push(s.clone()),
-                    _ => return Err(Error::invalid_params("expecting strings"))
-                }
-            }
-        }
-        _ => return Err(Error::invalid_params("expecting an array of strings"))
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use crate::store::{SharedContentStore, ContentStore};
+use crate::subscription::Filter;
+use crate::schema::{Schema, Condition};
+use crate::error::Error as CrateError;
+use crate::db::{Page, SortKey, Selection};
+use crate::policy::Rule;
+use crate::token::{Tokens, Scope};
+use crate::openrpc;
+use bitcoin::{Address, PublicKey, OutPoint, Transaction};
+use bitcoin::consensus::{serialize, deserialize};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin_hashes::{sha256, sha256d};
+
+const MAX_REQUEST_BODY_SIZE: usize = 5 * 1024 * 1024;
+
+/// where the api is served
+pub enum Transport {
+    /// plain http on a TCP address
+    Http(SocketAddr),
+    /// https on a TCP address
+    Tls(SocketAddr, Arc<ServerConfig>),
+    /// http on a unix domain socket with file permissions e.g. 0o600
+    #[cfg(unix)]
+    Unix(PathBuf, u32)
+}
+
+/// TLS configuration with certificate chain and private key files in PEM format
+pub fn tls_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, CrateError> {
+    let certs = pemfile::certs(&mut io::BufReader::new(fs::File::open(cert)?))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed certificate file"))?;
+    let mut keys = pemfile::pkcs8_private_keys(&mut io::BufReader::new(fs::File::open(key)?))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed key file"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut io::BufReader::new(fs::File::open(key)?))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed key file"))?;
     }
-    if result.len() < 1 {
-        return Err(Error::invalid_params("missing api key"));
+    if certs.is_empty() || keys.is_empty() {
+        return Err(CrateError::IO(io::Error::new(io::ErrorKind::InvalidData, "no certificate or private key found")));
     }
-    if result [0].as_str() != api_key {
-        return Err(Error::invalid_params("invalid api key"));
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(certs, keys.remove(0))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(Arc::new(config))
+}
+
+// bind a unix domain socket with file permissions, replacing a stale socket but no other file.
+// The socket is bound in a private directory and then moved into place, so it is never accessible with wider permissions
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: u32) -> Result<UnixListener, CrateError> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(CrateError::IO(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is not a socket", path.display()))));
+        }
+        fs::remove_file(path)?;
     }
-    return Ok(result[1..].to_vec());
+    let mut private = path.as_os_str().to_owned();
+    private.push(".bind");
+    let private = PathBuf::from(private);
+    let bound = private.join("socket");
+    // left over by an earlier failure
+    fs::remove_file(&bound).ok();
+    fs::remove_dir(&private).ok();
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, fs::Permissions::from_mode(mode))?;
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    fs::remove_file(&bound).ok();
+    fs::remove_dir(&private)?;
+    Ok(listener?)
+}
+
+// serve http on a connection of the tls or unix transport
+fn serve_connection<I: AsyncRead + AsyncWrite + Send + 'static> (stream: I, rpc: Rpc<Meta>) -> impl Future<Item=(), Error=()> {
+    let service = ServerHandler::new(rpc, None, None, AccessControlAllowHeaders::Any, None,
+        Arc::new(|request| RequestMiddlewareAction::Proceed { should_continue_on_invalid_cors: false, request }),
+        RestApi::Disabled, None, MAX_REQUEST_BODY_SIZE, true);
+    hyper::server::conn::Http::new().serve_connection(stream, service)
+        .map_err(|e| debug!("error serving rpc connection {:?}", e))
+}
+
+/// scopes of the token in the Authorization header of a request
+#[derive(Clone, Default)]
+struct Meta {
+    scopes: Vec<Scope>
+}
+
+impl Metadata for Meta {}
+
+// server error codes in addition to those of JSON-RPC 2.0
+/// the token lacks the scope of the method
+const UNAUTHORIZED: i64 = -32001;
+/// the request was refused e.g. insufficient funds or a duplicate token name
+const REFUSED: i64 = -32002;
+/// the wallet failed e.g. wrong passphrase
+const WALLET_ERROR: i64 = -32003;
+
+fn server_error (code: i64, message: String) -> Error {
+    Error { code: ErrorCode::ServerError(code), message, data: None }
 }
 
-fn parse_wallet_arguments (p: Params, api_key: &str) -> Result<(String, Vec<Value>), Error> {
-    let mut result = Vec::new();
-    match p {
-        Params::Array(array) => {
-            for v in &array {
-                result.push(v.clone());
+impl From<CrateError> for Error {
+    fn from(e: CrateError) -> Error {
+        match e {
+            CrateError::Schema(_) | CrateError::Policy(_) => Error::invalid_params(e.to_string()),
+            CrateError::Unsupported(s) => server_error(REFUSED, s.to_string()),
+            CrateError::Wallet(_) => server_error(WALLET_ERROR, e.to_string()),
+            e => {
+                debug!("rpc call failed {:?}", e);
+                Error::internal_error()
             }
         }
-        _ => return Err(Error::invalid_params("expecting an array of strings"))
     }
-    if result.len() < 2 {
-        return Err(Error::invalid_params("missing api key and wallet passphrase"));
+}
+
+fn authorize (meta: &Meta, scope: Scope) -> Result<(), Error> {
+    if !meta.scopes.contains(&scope) {
+        return Err(server_error(UNAUTHORIZED, format!("token lacks scope {:?}", scope).to_lowercase()));
     }
-    if let Value::String(ref s) = result[0] {
-        if *s != api_key {
-            return Err(Error::invalid_params("invalid api key"));
+    Ok(())
+}
+
+// parameters are named e.g. {"category":"misc"} or positional in the order of names e.g. ["misc"]
+// a name ending with .. collects the remaining positional parameters into an array
+// a trailing object of positional parameters is the page of a listing
+fn parse_params<T: DeserializeOwned> (p: Params, meta: &Meta, scope: Scope, names: &[&str]) -> Result<T, Error> {
+    authorize(meta, scope)?;
+    let map = match p {
+        Params::Map(map) => map,
+        Params::None => Map::new(),
+        Params::Array(mut array) => {
+            let mut map = Map::new();
+            if names.contains(&"page") && array.last().map_or(false, |v| v.is_object()) {
+                map.insert("page".to_string(), array.pop().unwrap());
+            }
+            let mut values = array.into_iter();
+            for name in names.iter().filter(|n| **n != "page") {
+                if name.ends_with("..") {
+                    map.insert(name[..name.len() - 2].to_string(), Value::Array(values.by_ref().collect()));
+                }
+                else if let Some(value) = values.next() {
+                    map.insert(name.to_string(), value);
+                }
+            }
+            if values.next().is_some() {
+                return Err(Error::invalid_params(format!("expect: {}", names.join(", "))));
+            }
+            map
         }
+    };
+    serde_json::from_value(Value::Object(map)).map_err(|e| Error::invalid_params(e.to_string()))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoParams {}
+
+#[derive(Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    Asc,
+    Desc
+}
+
+/// page of a listing e.g. {"sort":"weight", "order":"desc", "cursor":"next of previous page", "limit":100}
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PageParams {
+    sort: Option<SortKey>,
+    order: Option<Order>,
+    cursor: Option<String>,
+    limit: Option<u32>
+}
+
+impl PageParams {
+    fn page (self, ascending: bool) -> Result<Page, Error> {
+        let default = Page::default();
+        let limit = self.limit.unwrap_or(default.limit);
+        if limit == 0 || limit > 1000 {
+            return Err(Error::invalid_params("limit is a number between 1 and 1000"));
+        }
+        Ok(Page {
+            sort: self.sort.unwrap_or(default.sort),
+            ascending: self.order.map_or(ascending, |o| o == Order::Asc),
+            cursor: self.cursor,
+            limit
+        })
     }
-    else {
-        return Err(Error::invalid_params("invalid api key"));
+}
+
+fn check_content_cursor (page: &Page) -> Result<(), Error> {
+    if let Some(ref cursor) = page.cursor {
+        if Page::content_cursor(cursor.as_str()).is_err() {
+            return Err(Error::invalid_params("malformed cursor"));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CategoriesParams {
+    page: Option<PageParams>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListParams {
+    #[serde(default)]
+    categories: Vec<String>,
+    page: Option<PageParams>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadParams {
+    #[serde(default)]
+    ids: Vec<String>,
+    page: Option<PageParams>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SearchParams {
+    #[serde(default)]
+    keywords: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QueryParams {
+    category: String,
+    #[serde(default)]
+    conditions: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CategoryParams {
+    category: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SetSchemaParams {
+    category: String,
+    /// a schema object or its JSON string, None removes the schema
+    schema: Option<Value>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublisherParams {
+    publisher: PublicKey
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscribeParams {
+    category: Option<String>,
+    #[serde(default)]
+    keywords: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PollParams {
+    subscription: String,
+    timeout: Option<u64>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscriptionParams {
+    subscription: String
+}
+
+fn parse_subscription (id: &str) -> Result<u64, Error> {
+    u64::from_str_radix(id, 16).map_err(|_| Error::invalid_params("malformed subscription id"))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrepareParams {
+    category: String,
+    #[serde(rename = "abstract")]
+    abs: String,
+    content: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublicationParams {
+    id: sha256::Hash
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommitmentParams {
+    id: sha256::Hash,
+    publisher: PublicKey,
+    term: u16
+}
+
+/// a prepared publication
+#[derive(Serialize)]
+struct Prepared {
+    id: String,
+    cat: String,
+    abs: String,
+    text: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WithdrawParams {
+    /// not needed if the wallet is watch-only
+    #[serde(default)]
+    passphrase: String,
+    address: Address,
+    fee_per_vbyte: Option<u64>,
+    /// all available if None
+    amount: Option<u64>,
+    target: Option<u16>,
+    /// coins to spend instead of those chosen
+    inputs: Option<Vec<OutPoint>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FundParams {
+    /// not needed if the wallet is watch-only
+    #[serde(default)]
+    passphrase: String,
+    id: sha256::Hash,
+    amount: u64,
+    term: u16,
+    fee_per_vbyte: Option<u64>,
+    target: Option<u16>,
+    /// coins to spend instead of those chosen
+    inputs: Option<Vec<OutPoint>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LockUnspentParams {
+    outpoints: Vec<OutPoint>,
+    #[serde(default)]
+    unlock: bool
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenewParams {
+    passphrase: String,
+    id: sha256::Hash,
+    term: u16,
+    fee_per_vbyte: Option<u64>,
+    target: Option<u16>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RevokeParams {
+    passphrase: String,
+    id: sha256::Hash
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OfferParams {
+    passphrase: String,
+    amount: u64,
+    term: u16,
+    price: u64,
+    fee_per_vbyte: Option<u64>,
+    target: Option<u16>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RentParams {
+    offer: sha256::Hash,
+    category: String,
+    #[serde(rename = "abstract")]
+    abs: String,
+    content: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MnemonicParams {
+    passphrase: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SweepParams {
+    passphrase: String,
+    fee_per_vbyte: Option<u64>,
+    auto: Option<bool>,
+    target: Option<u16>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BumpFeeParams {
+    passphrase: String,
+    txid: sha256d::Hash,
+    fee_per_vbyte: Option<u64>,
+    target: Option<u16>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EstimateFeeParams {
+    target: Option<u16>
+}
+
+/// blocks to confirm within if neither fee per vbyte nor target is given
+const DEFAULT_TARGET: u16 = 6;
+
+fn fee_per_vbyte (store: &ContentStore, fee_per_vbyte: Option<u64>, target: Option<u16>) -> Result<u64, Error> {
+    match (fee_per_vbyte, target) {
+        (Some(_), Some(_)) => Err(Error::invalid_params("give either fee_per_vbyte or target")),
+        (Some(fee_per_vbyte), None) => Ok(std::cmp::min(fee_per_vbyte, 100)),
+        (None, target) => Ok(store.fee_for_target(target.unwrap_or(DEFAULT_TARGET))?)
     }
-    if let Value::String(ref s) = result[1] {
-        return Ok((s.clone(), result[2..].to_vec()));
+}
+
+// txid of a sent transaction or the base64 encoded psbt to be signed elsewhere if the wallet is watch-only
+fn sent (store: &ContentStore, transaction: &Transaction) -> Result<Value, Error> {
+    if store.is_watch_only() {
+        let psbt = store.read_psbt(&transaction.txid())?.ok_or(Error::internal_error())?;
+        return Ok(serde_json::to_value(base64::encode(&serialize(&psbt))).unwrap());
     }
-    return Err(Error::invalid_params("invalid passpharse"));
+    Ok(serde_json::to_value(transaction.txid()).unwrap())
 }
 
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FinalizeParams {
+    /// base64 encoded
+    psbt: String
+}
 
-pub fn start_api (rpc_address: &SocketAddr, store: SharedContentStore, apikey: String) {
-    let mut io = IoHandler::default();
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiscardParams {
+    txid: sha256d::Hash
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImportParams {
+    /// base64 encoded
+    archive: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateTokenParams {
+    name: String,
+    #[serde(default)]
+    scopes: Vec<Scope>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenParams {
+    name: String
+}
+
+
+pub fn start_api (transport: Transport, store: SharedContentStore, tokens: Arc<Tokens>) {
+    let mut io = MetaIoHandler::default();
 
     // call endpoints with:
-    // curl -X POST -H "Content-Type: application/json" -d '{"jsonrpc": "2.0", "method": "METHOD", "params": ["apikey", "ARGUMENTS"...] "id":1}' 127.0.0.1:21767
-    // see defiads.cfg for apikey
+    // curl -X POST -H "Content-Type: application/json" -H "Authorization: Bearer TOKEN" -d '{"jsonrpc": "2.0", "method": "METHOD", "params": {"NAME": VALUE, ...}, "id":1}' 127.0.0.1:21767
+    // parameters may also be given positionally in the order listed, amounts, fees and terms are numbers
+    // see defiads.cfg for apikey, the admin token with all scopes
+    // scopes needed are: read for browsing, publish for preparing publications,
+    // wallet for moving coins and admin for managing tokens, policy and schemas
+    // errors are: -32602 invalid parameters, -32001 token lacks scope, -32002 request refused,
+    // -32003 wallet failed e.g. wrong passphrase, -32603 internal error
 
 
+    // describe all methods, no token needed
+    // METHOD: rpc.discover
+    // ARGUMENTS: none
+    // answer is an OpenRPC document, the scope a method needs is its tag
+    // {"jsonrpc":"2.0","result":{"openrpc":"1.2.6","info":{...},"methods":[...],"components":{...}},"id":1}
+    io.add_method_with_meta("rpc.discover", |_p:Params, _meta: Meta| {
+        Ok(openrpc::document())
+    });
+
     // list known categories
     // METHOD: categories
-    // ARGUMENTS: none
+    // ARGUMENTS: {"page": {"order":"asc|desc", "cursor":"next", "limit":100}}, page is optional
     // answer is:
     // {"jsonrpc":"2.0","result":["category", ...],"id":1}
+    // or if a page is requested (ordered by category name):
+    // {"jsonrpc":"2.0","result":{"total":100,"next":"cursor","items":["category", ...]},"id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("categories", move |p| {
-        parse_arguments(p,moved_apikey.as_str())?;
-        match moved_store.read().unwrap().list_categories() {
-            Ok(result) => return Ok(serde_json::to_value(result).unwrap()),
-            Err(e) => {
-                debug!("failed to retrieve categories {:?}", e);
-                return Err(Error::internal_error());
-            }
-        };
+    io.add_method_with_meta("categories", move |p:Params, meta: Meta| {
+        let params = parse_params::<CategoriesParams>(p, &meta, Scope::Read, &["page"])?;
+        if let Some(page) = params.page {
+            let page = page.page(true)?;
+            return Ok(serde_json::to_value(moved_store.read().unwrap().list_categories_page(&page)?).unwrap());
+        }
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_categories()?).unwrap())
     });
 
     // list ids and abstracts for categories
     // METHOD: list
-    // ARGUMENTS: "category", ...
+    // ARGUMENTS: {"categories": ["category", ...], "page": {"sort":"weight|height|term|length", "order":"asc|desc", "cursor":"next", "limit":100}}
+    // page is optional, positionally: "category", ..., {page}
     // answer is (ordered by category name and weight descending):
-    // {"jsonrpc":"2.0","result":[["id","cat","abstract"]...],"id":1}
-    let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("list", move |p:Params| {
-        let cats = parse_arguments(p,moved_apikey.as_str())?;
-        match moved_store.read().unwrap().list_abstracts(cats) {
-            Ok(result) => return Ok(serde_json::to_value(result).unwrap()),
-            Err(e) => {
-                debug!("failed to retrieve abstracts {:?}", e);
-                return Err(Error::internal_error());
-            }
-        };
+    // {"jsonrpc":"2.0","result":[{"id":"id","cat":"cat","abs":"abstract"}...],"id":1}
+    // or if a page is requested (all categories if none given, ordered by sort key, weight descending by default):
+    // {"jsonrpc":"2.0","result":{"total":100,"next":"cursor","items":[{"id":"id","cat":"cat","abs":"abstract"}...]},"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("list", move |p:Params, meta: Meta| {
+        let params = parse_params::<ListParams>(p, &meta, Scope::Read, &["categories..", "page"])?;
+        if let Some(page) = params.page {
+            let page = page.page(false)?;
+            check_content_cursor(&page)?;
+            let selection = if params.categories.is_empty() { Selection::All } else { Selection::Categories(params.categories) };
+            return Ok(serde_json::to_value(moved_store.read().unwrap().list_abstracts_page(selection, &page)?).unwrap());
+        }
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_abstracts(params.categories)?).unwrap())
+    });
+
+    // search ids and abstracts by keywords in category, abstract and text
+    // METHOD: search
+    // ARGUMENTS: {"keywords": ["keyword", ...]}, positionally: "keyword", ...
+    // answer is (ordered by relevance and weight descending):
+    // {"jsonrpc":"2.0","result":[{"id":"id","cat":"cat","abs":"abstract"}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("search", move |p:Params, meta: Meta| {
+        let params = parse_params::<SearchParams>(p, &meta, Scope::Read, &["keywords.."])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().search(params.keywords)?).unwrap())
+    });
+
+    // list ids and abstracts of a category with structured abstracts satisfying all conditions
+    // METHOD: query
+    // ARGUMENTS: {"category": "category", "conditions": ["condition", ...]}, positionally: "category", "condition", ...
+    // a condition is: field operator value, operators are ==, !=, <, <=, >, >=
+    // e.g. {"category": "exchange", "conditions": ["pair == BTC/USD", "rate < 9000"]}
+    // answer is (ordered by weight descending):
+    // {"jsonrpc":"2.0","result":[{"id":"id","cat":"cat","abs":"abstract"}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("query", move |p:Params, meta: Meta| {
+        let params = parse_params::<QueryParams>(p, &meta, Scope::Read, &["category", "conditions.."])?;
+        let mut conditions = Vec::new();
+        for c in &params.conditions {
+            conditions.push(Condition::from_str(c.as_str())?);
+        }
+        Ok(serde_json::to_value(moved_store.read().unwrap().query(params.category, conditions)?).unwrap())
+    });
+
+    // list rules of the local policy
+    // METHOD: list_policy
+    // ARGUMENTS: none
+    // {"jsonrpc":"2.0","result":[{"target":"category","value":"casino","action":"reject"}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("list_policy", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Admin, &[])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().policy_rules()).unwrap())
+    });
+
+    // add a rule to the local policy, also saved to the policy file
+    // METHOD: add_policy_rule
+    // ARGUMENTS: {"target": "target", "value": "value", "action": "action"}
+    // target is one of digest, publisher, category or abstract (value is a regular expression)
+    // action is hide (stored and relayed but not shown) or reject (neither stored nor relayed)
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("add_policy_rule", move |p:Params, meta: Meta| {
+        let rule = parse_params::<Rule>(p, &meta, Scope::Admin, &["target", "value", "action"])?;
+        moved_store.write().unwrap().add_policy_rule(rule)?;
+        Ok(Value::Bool(true))
+    });
+
+    // remove a rule from the local policy
+    // METHOD: remove_policy_rule
+    // ARGUMENTS: {"target": "target", "value": "value", "action": "action"}
+    // answer is false if there was no such rule
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("remove_policy_rule", move |p:Params, meta: Meta| {
+        let rule = parse_params::<Rule>(p, &meta, Scope::Admin, &["target", "value", "action"])?;
+        Ok(Value::Bool(moved_store.write().unwrap().remove_policy_rule(&rule)?))
+    });
+
+    // all content as archive, snappy compressed stream of CBOR encoded content
+    // METHOD: export
+    // ARGUMENTS: none
+    // {"jsonrpc":"2.0","result":"base64 encoded archive","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("export", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Admin, &[])?;
+        let mut archive = Vec::new();
+        moved_store.read().unwrap().export(&mut archive)?;
+        Ok(Value::String(base64::encode(&archive)))
+    });
+
+    // add content of an archive written by export, content is validated as if received from a peer
+    // METHOD: import
+    // ARGUMENTS: {"archive": "base64 encoded archive"}
+    // unknown is the number of content funded in blocks not yet known, failed is that of content malformed
+    // {"jsonrpc":"2.0","result":{"read":100,"added":97,"unknown":1,"failed":0},"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("import", move |p:Params, meta: Meta| {
+        let params = parse_params::<ImportParams>(p, &meta, Scope::Admin, &["archive"])?;
+        let archive = base64::decode(params.archive.as_str()).map_err(|_| Error::invalid_params("archive is not base64 encoded"))?;
+        Ok(serde_json::to_value(moved_store.write().unwrap().import(archive.as_slice())?).unwrap())
+    });
+
+    // list ids and abstracts funded by a publisher
+    // METHOD: list_by_publisher
+    // ARGUMENTS: {"publisher": "publisher key"}
+    // answer is (ordered by category name and weight descending):
+    // {"jsonrpc":"2.0","result":[{"id":"id","cat":"cat","abs":"abstract"}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("list_by_publisher", move |p:Params, meta: Meta| {
+        let params = parse_params::<PublisherParams>(p, &meta, Scope::Read, &["publisher"])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_by_publisher(&params.publisher)?).unwrap())
+    });
+
+    // summary of content funded by a publisher
+    // METHOD: publisher_stats
+    // ARGUMENTS: {"publisher": "publisher key"}
+    // amount is the total locked in satoshis, earliest is the lowest funding height
+    // {"jsonrpc":"2.0","result":{"publisher":"publisher","amount":1000,"ads":1,"earliest":100},"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("publisher_stats", move |p:Params, meta: Meta| {
+        let params = parse_params::<PublisherParams>(p, &meta, Scope::Read, &["publisher"])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().publisher_stats(&params.publisher)?).unwrap())
     });
 
     // read content
     // METHOD: read
-    // ARGUMENTS: "id", ...
-    // answer is (ordered by category name and weight descending):
+    // ARGUMENTS: {"ids": ["id", ...], "page": {"sort":"weight|height|term|length", "order":"asc|desc", "cursor":"next", "limit":100}}
+    // page is optional, positionally: "id", ..., {page}
+    // answer is (ordered by weight descending):
     // {"jsonrpc":"2.0","result":[{ content }...],"id":1}
+    // or if a page is requested (all content if no id given, ordered by sort key, weight descending by default):
+    // {"jsonrpc":"2.0","result":{"total":100,"next":"cursor","items":[{ content }...]},"id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("read", move |p:Params| {
-        let ids = parse_arguments(p,moved_apikey.as_str())?;
-        match moved_store.read().unwrap().read_contents(ids) {
-            Ok(result) => return Ok(serde_json::to_value(result).unwrap()),
-            Err(e) => {
-                debug!("failed to retrieve content {:?}", e);
-                return Err(Error::internal_error());
-            }
+    io.add_method_with_meta("read", move |p:Params, meta: Meta| {
+        let params = parse_params::<ReadParams>(p, &meta, Scope::Read, &["ids..", "page"])?;
+        if let Some(page) = params.page {
+            let page = page.page(false)?;
+            check_content_cursor(&page)?;
+            let selection = if params.ids.is_empty() { Selection::All } else { Selection::Ids(params.ids) };
+            return Ok(serde_json::to_value(moved_store.read().unwrap().read_contents_page(selection, &page)?).unwrap());
+        }
+        Ok(serde_json::to_value(moved_store.read().unwrap().read_contents(params.ids)?).unwrap())
+    });
+
+    // subscribe to content added, expired, dropped or un-confirmed
+    // METHOD: subscribe
+    // ARGUMENTS: {"category": "category", "keywords": ["keyword", ...]}, positionally: "category", "keyword", ...
+    // missing or empty category matches all, keywords must all appear in the abstract
+    // a subscription not polled for 10 minutes is forgotten
+    // {"jsonrpc":"2.0","result":"subscription id","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("subscribe", move |p:Params, meta: Meta| {
+        let params = parse_params::<SubscribeParams>(p, &meta, Scope::Read, &["category", "keywords.."])?;
+        let cat = params.category.filter(|c| !c.is_empty());
+        let id = moved_store.read().unwrap().subscriptions().lock().unwrap().subscribe(Filter::new(cat, params.keywords));
+        Ok(serde_json::to_value(format!("{:016x}", id)).unwrap())
+    });
+
+    // poll events of a subscription, waits until there is an event or the timeout passed
+    // METHOD: poll
+    // ARGUMENTS: {"subscription": "subscription id", "timeout": seconds}
+    // timeout is 30 seconds if not specified, at most 60
+    // {"jsonrpc":"2.0","result":[{"event":"added|expired|dropped|unconfirmed","id":"id","cat":"cat","abs":"abstract"}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("poll", move |p:Params, meta: Meta| -> BoxFuture<Value> {
+        let params = match parse_params::<PollParams>(p, &meta, Scope::Read, &["subscription", "timeout"]) {
+            Ok(params) => params,
+            Err(e) => return Box::new(future::err(e))
         };
+        let id = match parse_subscription(params.subscription.as_str()) {
+            Ok(id) => id,
+            Err(e) => return Box::new(future::err(e))
+        };
+        let timeout = std::cmp::min(params.timeout.unwrap_or(30), 60);
+        let subscriptions = moved_store.read().unwrap().subscriptions();
+        let waiter;
+        {
+            let mut subs = subscriptions.lock().unwrap();
+            match subs.take_events(id) {
+                Some(events) => {
+                    if !events.is_empty() || timeout == 0 {
+                        return Box::new(future::ok(serde_json::to_value(events).unwrap()));
+                    }
+                },
+                None => return Box::new(future::err(Error::invalid_params("unknown subscription")))
+            }
+            waiter = subs.wait(id).unwrap();
+        }
+        Box::new(waiter.map_err(|_| ())
+            .select(Delay::new(Instant::now() + Duration::from_secs(timeout)).map_err(|_| ()))
+            .then(move |_| {
+                let events = subscriptions.lock().unwrap().take_events(id).unwrap_or_default();
+                Ok(serde_json::to_value(events).unwrap())
+            }))
+    });
+
+    // cancel a subscription
+    // METHOD: unsubscribe
+    // ARGUMENTS: {"subscription": "subscription id"}
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("unsubscribe", move |p:Params, meta: Meta| {
+        let params = parse_params::<SubscriptionParams>(p, &meta, Scope::Read, &["subscription"])?;
+        let id = parse_subscription(params.subscription.as_str())?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().subscriptions().lock().unwrap().unsubscribe(id)).unwrap())
     });
 
     // get balance
     // METHOD: balance
-    // {"jsonrpc":"2.0","result":[balance, confirmed],"id":1}
+    // available is lower than balance if some is committed to funding
+    // {"jsonrpc":"2.0","result":{"balance":5000000000,"available":4000000000},"id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("balance", move |p:Params| {
-        parse_arguments(p,moved_apikey.as_str())?;
+    io.add_method_with_meta("balance", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Wallet, &[])?;
         Ok(serde_json::to_value(moved_store.read().unwrap().balance()).unwrap())
     });
 
+    // words of the BIP39 mnemonic of the wallet's key, to restore the wallet with defiads restore or other wallets,
+    // only with the admin token as the words are the wallet's keys
+    // METHOD: reveal_mnemonic
+    // ARGUMENTS: {"passphrase": "passphrase"}
+    // {"jsonrpc":"2.0","result":"word word ...","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("reveal_mnemonic", move |p:Params, meta: Meta| {
+        let params = parse_params::<MnemonicParams>(p, &meta, Scope::Admin, &["passphrase"])?;
+        Ok(Value::String(moved_store.read().unwrap().reveal_mnemonic(params.passphrase.as_str())?))
+    });
+
+    // list coins of the wallet
+    // METHOD: listunspent
+    // confirmations is 0 for unconfirmed coins, csv is the number of blocks after confirmation a coin funding
+    // the publication id is locked for, available is true if the coin can be spent now,
+    // locked is true if the coin is excluded from automatic selection of inputs by lockunspent
+    // {"jsonrpc":"2.0","result":[{"txid":"txid","vout":0,"value":100000,"confirmations":6,"csv":null,"id":null,"available":true,"locked":false}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("listunspent", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Wallet, &[])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_unspent()).unwrap())
+    });
+
+    // exclude coins from automatic selection of inputs or return them with "unlock": true
+    // METHOD: lockunspent
+    // ARGUMENTS: {"outpoints": ["txid:vout"], "unlock": false}
+    // locks are kept across restarts, coins may still be spent by giving them as inputs
+    // answer is the number of coins locked or unlocked
+    // {"jsonrpc":"2.0","result":1,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("lockunspent", move |p:Params, meta: Meta| {
+        let params = parse_params::<LockUnspentParams>(p, &meta, Scope::Wallet, &["outpoints", "unlock"])?;
+        Ok(serde_json::to_value(moved_store.write().unwrap().lock_coins(&params.outpoints, !params.unlock)?).unwrap())
+    });
+
+    // list transactions of the wallet
+    // METHOD: transactions
+    // unconfirmed first then the latest first, kind is one of deposit, withdrawal, funding or internal,
+    // delta is the change of the balance in satoshis, fee is known for transactions sent by the wallet,
+    // id is the publication funded
+    // {"jsonrpc":"2.0","result":[{"txid":"txid","kind":"funding","height":100,"confirmed":true,"delta":-1410,"fee":1410,"id":"publication"}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("transactions", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Wallet, &[])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().transactions()?).unwrap())
+    });
+
+    // estimate the fee to confirm within a target number of blocks from fees paid in recent blocks
+    // METHOD: estimatefee
+    // ARGUMENTS: {"target": 6}, target is 6 blocks if not given
+    // percentiles are median feerates of recent blocks at the 10th, 25th, 50th, 75th and 90th percentile of their transactions
+    // answer is null if too few blocks were seen yet
+    // {"jsonrpc":"2.0","result":{"target":6,"fee_per_vbyte":12,"blocks":144,"percentiles":[5,8,14,25,60]},"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("estimatefee", move |p:Params, meta: Meta| {
+        let params = parse_params::<EstimateFeeParams>(p, &meta, Scope::Read, &["target"])?;
+        let target = params.target.unwrap_or(DEFAULT_TARGET);
+        if target == 0 {
+            return Err(Error::invalid_params("target is at least 1 block"));
+        }
+        Ok(serde_json::to_value(moved_store.read().unwrap().estimate_fee(target)).unwrap())
+    });
+
     // get deposit address
     // METHOD: deposit
     // {"jsonrpc":"2.0","result":"address","id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("deposit", move |p:Params| {
-        parse_arguments(p,moved_apikey.as_str())?;
+    io.add_method_with_meta("deposit", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Wallet, &[])?;
         Ok(serde_json::to_value(moved_store.write().unwrap().deposit_address().to_string()).unwrap())
     });
 
     // prepare publication
     // METHOD: prepare
-    // ARGUMENTS: category, abstract, content
-    // {"jsonrpc":"2.0","result":"address","id":1}
+    // ARGUMENTS: {"category": "category", "abstract": "abstract", "content": "content"}
+    // {"jsonrpc":"2.0","result":"id","id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("prepare", move |p:Params| {
-        let args = parse_arguments(p,moved_apikey.as_str())?;
-        if args.len () < 3 {
-            return Err(Error::invalid_params("expect: category, abstract, content"));
-        }
-        Ok(serde_json::to_value(moved_store.write().unwrap().prepare_publication(args[0].clone(), args[1].clone(), args[2].clone())).unwrap())
+    io.add_method_with_meta("prepare", move |p:Params, meta: Meta| {
+        let params = parse_params::<PrepareParams>(p, &meta, Scope::Publish, &["category", "abstract", "content"])?;
+        let id = moved_store.write().unwrap().prepare_publication(params.category, params.abs, params.content)?;
+        Ok(serde_json::to_value(id).unwrap())
+    });
+
+    // declare the schema of structured abstracts in a category
+    // prepare rejects abstracts in the category that do not conform
+    // METHOD: set_schema
+    // ARGUMENTS: {"category": "category", "schema": {schema}}, omit schema to remove it
+    // schema is a subset of JSON Schema with field types string, number and boolean e.g.:
+    // {"type":"object","properties":{"pair":{"type":"string"},"rate":{"type":"number"}},"required":["pair"]}
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("set_schema", move |p:Params, meta: Meta| {
+        let params = parse_params::<SetSchemaParams>(p, &meta, Scope::Admin, &["category", "schema"])?;
+        let schema = match params.schema {
+            Some(Value::String(s)) => Some(Schema::from_str(s.as_str())?),
+            Some(Value::Null) | None => None,
+            Some(v) => Some(Schema::from_str(v.to_string().as_str())?)
+        };
+        moved_store.write().unwrap().set_schema(params.category.as_str(), schema)?;
+        Ok(Value::Bool(true))
+    });
+
+    // read the schema of a category
+    // METHOD: read_schema
+    // ARGUMENTS: {"category": "category"}
+    // answer is null if the category has no schema
+    // {"jsonrpc":"2.0","result":{"type":"object","properties":{...},"required":[...]},"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("read_schema", move |p:Params, meta: Meta| {
+        let params = parse_params::<CategoryParams>(p, &meta, Scope::Read, &["category"])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().read_schema(params.category.as_str())?).unwrap())
     });
 
     // list prepared publications
     // METHOD: list_prepared
-    // {"jsonrpc":"2.0","result":"address","id":1}
+    // {"jsonrpc":"2.0","result":["id", ...],"id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("list_prepared", move |p:Params| {
-        parse_arguments(p,moved_apikey.as_str())?;
+    io.add_method_with_meta("list_prepared", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Publish, &[])?;
         Ok(serde_json::to_value(moved_store.read().unwrap().list_prepared().iter().map(|h| h.to_string()).collect::<Vec<String>>()).unwrap())
     });
 
     // read a prepared publication
     // METHOD: read_prepared
-    // {"jsonrpc":"2.0","result":"category", "abstract", "text","id":1}
+    // ARGUMENTS: {"id": "id"}
+    // {"jsonrpc":"2.0","result":{"id":"id","cat":"category","abs":"abstract","text":"text"},"id":1}
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("read_prepared", move |p:Params| {
-        let args = parse_arguments(p,moved_apikey.as_str())?;
-        if args.len () < 1 {
-            return Err(Error::invalid_params("expect: prepared publication id"));
-        }
-        let id = sha256::Hash::from_str(args[0].as_str());
-        if id.is_err() {
-            return Err(Error::invalid_params("expect: prepared publication id"));
-        }
-        if let Some(ad) = moved_store.read().unwrap().read_prepared(&id.unwrap()) {
-            Ok(serde_json::to_value(vec!(ad.cat, ad.abs, ad.content.as_string().unwrap())).unwrap())
+    io.add_method_with_meta("read_prepared", move |p:Params, meta: Meta| {
+        let params = parse_params::<PublicationParams>(p, &meta, Scope::Publish, &["id"])?;
+        if let Some(ad) = moved_store.read().unwrap().read_prepared(&params.id) {
+            Ok(serde_json::to_value(Prepared { id: params.id.to_string(), cat: ad.cat, abs: ad.abs, text: ad.content.as_string().unwrap() }).unwrap())
         }
         else {
-            return Err(Error::invalid_params("unkonwn publication"));
+            Err(Error::invalid_params("unknown publication"))
         }
     });
 
+    // address to fund a prepared publication from an external wallet, the publication is published as the payment confirms
+    // METHOD: commitment_address
+    // ARGUMENTS: {"id": "publication", "publisher": "publisher key", "term": 1008}
+    // the key of the publisher is tweaked with the id of the publication, the payment must be a version 2 transaction
+    // that confirms within 1008 blocks
+    // {"jsonrpc":"2.0","result":"address","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("commitment_address", move |p:Params, meta: Meta| {
+        let params = parse_params::<CommitmentParams>(p, &meta, Scope::Publish, &["id", "publisher", "term"])?;
+        let address = moved_store.write().unwrap().commitment_address(&params.id, &params.publisher, params.term)?;
+        Ok(serde_json::to_value(address.to_string()).unwrap())
+    });
+
     // withdraw
     // METHOD: withdraw
-    // ARGUMENTS: target_address, fee_per_byte, [amount]
+    // ARGUMENTS: {"passphrase": "passphrase", "address": "target address", "fee_per_vbyte": 10, "amount": 100000000}
     // if amount is not specified it withdraws all. Amount is in satoshis, fee is in satoshi/vByte
+    // instead of fee_per_vbyte a confirmation target in blocks may be given e.g. "target": 6, that is the default
+    // coins to spend may be given e.g. "inputs": ["txid:vout"], otherwise available coins not locked are chosen
     // {"jsonrpc":"2.0","result":"txid","id":1}
+    // a watch-only wallet answers the base64 encoded PSBT to be signed elsewhere instead of the txid
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("withdraw", move |p:Params| {
-        let (passpharse, args) = parse_wallet_arguments(p,moved_apikey.as_str())?;
-        if args.len () < 2 {
-            return Err(Error::invalid_params("missing target address and fee per byte"));
-        }
-        let address;
-        let fee_per_vbyte;
-        let mut amount: Option<u64> = None;
-        if let Value::String(ref s) = args[0] {
-            if let Ok(a) = Address::from_str(s.as_str()) {
-                address = a;
-            }
-            else {
-                return Err(Error::invalid_params("malformed address"));
-            }
-        }
-        else {
-            return Err(Error::invalid_params("malformed address"));
-        }
-        if let Value::Number(ref n) = args[1] {
-            if let Some(sats) = n.as_u64() {
-                fee_per_vbyte = std::cmp::min(sats, 100);
-            }
-            else {
-                debug!("malformed fee");
-                return Err(Error::invalid_params("malformed fee"));
-            }
-        }
-        else {
-            debug!("malformed fee");
-            return Err(Error::invalid_params("malformed fee"));
-        }
-        if args.len () > 2 {
-            if let Value::Number(ref n) = args[2] {
-                amount = n.as_u64();
-            }
-        }
-        match moved_store.write().unwrap().withdraw(passpharse, address, fee_per_vbyte, amount) {
-            Ok((t, _)) => Ok(serde_json::to_value(t.txid()).unwrap()),
-            Err(e) => Err(Error::invalid_params(e.to_string().as_str()))
-        }
+    io.add_method_with_meta("withdraw", move |p:Params, meta: Meta| {
+        let params = parse_params::<WithdrawParams>(p, &meta, Scope::Wallet, &["passphrase", "address", "fee_per_vbyte", "amount", "target", "inputs"])?;
+        let mut store = moved_store.write().unwrap();
+        let fee_per_vbyte = fee_per_vbyte(&store, params.fee_per_vbyte, params.target)?;
+        let (t, _) = store.withdraw(params.passphrase, params.address, fee_per_vbyte, params.amount, params.inputs)?;
+        sent(&store, &t)
     });
 
     // fund
     // METHOD: fund
-    // ARGUMENTS: publication, amount, term, fee_per_vbyte
+    // ARGUMENTS: {"passphrase": "passphrase", "id": "publication", "amount": 100000000, "term": 1008, "fee_per_vbyte": 10}
+    // instead of fee_per_vbyte a confirmation target in blocks may be given e.g. "target": 6, that is the default
+    // coins to spend may be given e.g. "inputs": ["txid:vout"], otherwise available coins not locked are chosen
     // {"jsonrpc":"2.0","result":"txid","id":1}
+    // a watch-only wallet answers the base64 encoded PSBT to be signed elsewhere instead of the txid
     let moved_store = store.clone();
-    let moved_apikey = apikey.clone();
-    io.add_method("fund", move |p:Params| {
-        let (passpharse, args) = parse_wallet_arguments(p,moved_apikey.as_str())?;
-        if args.len () < 4 {
-            return Err(Error::invalid_params("missing publication, amount, term and fee per byte"));
-        }
-        let id;
-        if let Value::String(ref s) = args[0] {
-            if let Ok(i) = sha256::Hash::from_str(s.as_str()) {
-                id = i;
-            }
-            else {
-                return Err(Error::invalid_params("malformed publication id"));
-            }
-        }
-        else {
-            return Err(Error::invalid_params("malformed publication id"));
-        }
-        let amount;
-        if let Value::Number(ref n) = args[1] {
-            if let Some(a) = n.as_u64() {
-                amount = a;
-            }
-            else {
-                return Err(Error::invalid_params("malformed amount"));
-            }
+    io.add_method_with_meta("fund", move |p:Params, meta: Meta| {
+        let params = parse_params::<FundParams>(p, &meta, Scope::Wallet, &["passphrase", "id", "amount", "term", "fee_per_vbyte", "target", "inputs"])?;
+        let mut store = moved_store.write().unwrap();
+        let fee_per_vbyte = fee_per_vbyte(&store, params.fee_per_vbyte, params.target)?;
+        let (t, _, _) = store.fund(&params.id, params.term, params.amount, fee_per_vbyte, params.inputs, params.passphrase)?;
+        sent(&store, &t)
+    });
+
+    // renew
+    // METHOD: renew
+    // ARGUMENTS: {"passphrase": "passphrase", "id": "publication", "term": 1008, "fee_per_vbyte": 10}
+    // spends matured funding of the publication into a new funding of the given term,
+    // before the funding matured coins of the wallet of the same amount extend the publication
+    // instead of fee_per_vbyte a confirmation target in blocks may be given e.g. "target": 6, that is the default
+    // {"jsonrpc":"2.0","result":"txid","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("renew", move |p:Params, meta: Meta| {
+        let params = parse_params::<RenewParams>(p, &meta, Scope::Wallet, &["passphrase", "id", "term", "fee_per_vbyte", "target"])?;
+        let mut store = moved_store.write().unwrap();
+        let fee_per_vbyte = fee_per_vbyte(&store, params.fee_per_vbyte, params.target)?;
+        let (t, _, _) = store.renew(&params.id, params.term, fee_per_vbyte, params.passphrase)?;
+        Ok(serde_json::to_value(t.txid()).unwrap())
+    });
+
+    // replace an unconfirmed transaction of the wallet with one paying a higher fee
+    // METHOD: bumpfee
+    // ARGUMENTS: {"passphrase": "passphrase", "txid": "txid", "fee_per_vbyte": 20}
+    // the fee is paid from the change, or from the only output unless that is the commitment of a funding,
+    // the replacement funds the same publication
+    // instead of fee_per_vbyte a confirmation target in blocks may be given e.g. "target": 1, default is 6
+    // {"jsonrpc":"2.0","result":"txid of the replacement","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("bumpfee", move |p:Params, meta: Meta| {
+        let params = parse_params::<BumpFeeParams>(p, &meta, Scope::Wallet, &["passphrase", "txid", "fee_per_vbyte", "target"])?;
+        let mut store = moved_store.write().unwrap();
+        let fee_per_vbyte = fee_per_vbyte(&store, params.fee_per_vbyte, params.target)?;
+        let (t, _) = store.bump_fee(&params.txid, fee_per_vbyte, params.passphrase)?;
+        Ok(serde_json::to_value(t.txid()).unwrap())
+    });
+
+    // finalize and send a transaction of a watch-only wallet signed elsewhere
+    // METHOD: finalize_and_send
+    // ARGUMENTS: {"psbt": "base64 encoded PSBT as answered by fund or withdraw, signed"}
+    // {"jsonrpc":"2.0","result":"txid","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("finalize_and_send", move |p:Params, meta: Meta| {
+        let params = parse_params::<FinalizeParams>(p, &meta, Scope::Wallet, &["psbt"])?;
+        let psbt = base64::decode(params.psbt.as_str()).ok()
+            .and_then(|psbt| deserialize::<PartiallySignedTransaction>(psbt.as_slice()).ok())
+            .ok_or(Error::invalid_params("psbt is not a base64 encoded PSBT"))?;
+        let t = moved_store.write().unwrap().finalize_and_send(psbt)?;
+        Ok(serde_json::to_value(t.txid()).unwrap())
+    });
+
+    // forget a transaction of a watch-only wallet waiting to be signed, its inputs are no longer locked
+    // METHOD: discard_psbt
+    // ARGUMENTS: {"txid": "txid of the PSBT as answered by fund or withdraw"}
+    // answer is false if no transaction waits to be signed with this id
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("discard_psbt", move |p:Params, meta: Meta| {
+        let params = parse_params::<DiscardParams>(p, &meta, Scope::Wallet, &["txid"])?;
+        Ok(Value::Bool(moved_store.write().unwrap().discard_psbt(&params.txid)?))
+    });
+
+    // revoke own funding of a publication before the end of its term, it stays published while co-funded by others
+    // METHOD: revoke
+    // ARGUMENTS: {"passphrase": "passphrase", "id": "publication"}
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("revoke", move |p:Params, meta: Meta| {
+        let params = parse_params::<RevokeParams>(p, &meta, Scope::Wallet, &["passphrase", "id"])?;
+        moved_store.write().unwrap().revoke(&params.id, params.passphrase)?;
+        Ok(Value::Bool(true))
+    });
+
+    // list funding of own publications
+    // METHOD: list_funded
+    // unlock is the height the funding can be spent at, it is swept or re-used for new funding once matured
+    // {"jsonrpc":"2.0","result":[{"id":"publication","txid":"txid","vout":0,"value":1000,"height":100,"unlock":244,"matured":false}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("list_funded", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Wallet, &[])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_funded()).unwrap())
+    });
+
+    // sweep matured funding back to the wallet
+    // METHOD: sweep
+    // ARGUMENTS: {"passphrase": "passphrase", "fee_per_vbyte": 10, "auto": true}
+    // if auto is true matured funding will be also swept as new blocks arrive until the node is restarted,
+    // if auto is false automatic sweeping is turned off
+    // instead of fee_per_vbyte a confirmation target in blocks may be given e.g. "target": 6, that is the default,
+    // automatic sweeping uses the fee estimated now
+    // answer is null if there was nothing to sweep
+    // {"jsonrpc":"2.0","result":"txid","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("sweep", move |p:Params, meta: Meta| {
+        let params = parse_params::<SweepParams>(p, &meta, Scope::Wallet, &["passphrase", "fee_per_vbyte", "auto", "target"])?;
+        let mut store = moved_store.write().unwrap();
+        let fee_per_vbyte = fee_per_vbyte(&store, params.fee_per_vbyte, params.target)?;
+        if let Some(auto) = params.auto {
+            store.set_auto_sweep(if auto { Some((params.passphrase.clone(), fee_per_vbyte)) } else { None })?;
         }
-        else {
-            return Err(Error::invalid_params("malformed amount"));
+        if !store.has_matured_funding() {
+            return Ok(Value::Null);
         }
-        let term;
-        if let Value::Number(ref n) = args[2] {
-            if let Some(t) = n.as_u64() {
-                term = t as u16;
-            }
-            else {
-                return Err(Error::invalid_params("malformed term"));
-            }
+        let (t, _) = store.sweep(params.passphrase, fee_per_vbyte)?;
+        Ok(serde_json::to_value(t.txid()).unwrap())
+    });
+
+    // offer to fund ads of advertisers, answers the id of the publication advertising the offer in the category adspace,
+    // fund it to publish the offer
+    // METHOD: offer_adspace
+    // ARGUMENTS: {"passphrase": "passphrase", "amount": 100000000, "term": 1008, "price": 10000, "fee_per_vbyte": 10}
+    // rented ad space is funded with the passphrase of the latest offer until the node is restarted,
+    // offer the same ad space again after a restart to fund ad space rented meanwhile
+    // instead of fee_per_vbyte a confirmation target in blocks may be given e.g. "target": 6, that is the default,
+    // rented ad space is funded with the fee estimated now
+    // {"jsonrpc":"2.0","result":"id","id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("offer_adspace", move |p:Params, meta: Meta| {
+        let params = parse_params::<OfferParams>(p, &meta, Scope::Wallet, &["passphrase", "amount", "term", "price", "fee_per_vbyte", "target"])?;
+        let mut store = moved_store.write().unwrap();
+        let fee_per_vbyte = fee_per_vbyte(&store, params.fee_per_vbyte, params.target)?;
+        let id = store.offer_adspace(params.amount, params.term, params.price, params.passphrase, fee_per_vbyte)?;
+        Ok(serde_json::to_value(id).unwrap())
+    });
+
+    // withdraw an offer of ad space, ad space rented earlier is still funded as paid
+    // METHOD: cancel_offer
+    // ARGUMENTS: {"id": "offer"}
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("cancel_offer", move |p:Params, meta: Meta| {
+        let params = parse_params::<PublicationParams>(p, &meta, Scope::Wallet, &["id"])?;
+        Ok(Value::Bool(moved_store.write().unwrap().cancel_offer(&params.id)?))
+    });
+
+    // list ad space offered by this node
+    // METHOD: list_offers
+    // {"jsonrpc":"2.0","result":[{"id":"offer","amount":100000000,"term":1008,"price":10000}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("list_offers", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Read, &[])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_offers()?).unwrap())
+    });
+
+    // rent offered ad space for an ad, the ad is funded once the price paid to the address answered has 6 confirmations,
+    // the rental is dropped if not paid until the block at expiry, at most 16 rentals of an offer wait for payment
+    // METHOD: rent_adspace
+    // ARGUMENTS: {"offer": "offer", "category": "category", "abstract": "abstract", "content": "content"}
+    // {"jsonrpc":"2.0","result":{"id":"publication","offer":"offer","address":"address","price":10000,"amount":100000000,"term":1008,"expiry":600144,"paid":null,"block":null,"funded":null,"stalled":false},"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("rent_adspace", move |p:Params, meta: Meta| {
+        let params = parse_params::<RentParams>(p, &meta, Scope::Publish, &["offer", "category", "abstract", "content"])?;
+        let rental = moved_store.write().unwrap().rent_adspace(&params.offer, params.category, params.abs, params.content)?;
+        Ok(serde_json::to_value(rental).unwrap())
+    });
+
+    // list ad space rented, paid and funded are the ids of the transactions paying the price and funding the ad,
+    // block is the id of the block confirming the payment, stalled is true if the payment has 6 confirmations
+    // but the ad is not funded as the node was restarted since the latest offer
+    // METHOD: list_rentals
+    // {"jsonrpc":"2.0","result":[{"id":"publication","offer":"offer","address":"address","price":10000,"amount":100000000,"term":1008,"expiry":600144,"paid":"txid","block":"block","funded":null,"stalled":false}...],"id":1}
+    let moved_store = store.clone();
+    io.add_method_with_meta("list_rentals", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Wallet, &[])?;
+        Ok(serde_json::to_value(moved_store.read().unwrap().list_rentals()?).unwrap())
+    });
+
+    // create a token for a client
+    // METHOD: create_token
+    // ARGUMENTS: {"name": "name", "scopes": ["scope", ...]}, scopes are read, publish, wallet or admin
+    // answer is the token, it can not be retrieved later
+    // {"jsonrpc":"2.0","result":"token","id":1}
+    let moved_tokens = tokens.clone();
+    io.add_method_with_meta("create_token", move |p:Params, meta: Meta| {
+        let params = parse_params::<CreateTokenParams>(p, &meta, Scope::Admin, &["name", "scopes.."])?;
+        if params.scopes.is_empty() {
+            return Err(Error::invalid_params("expect: name, scopes"));
         }
-        else {
-            return Err(Error::invalid_params("malformed term"));
+        Ok(serde_json::to_value(moved_tokens.create(params.name.as_str(), params.scopes)?).unwrap())
+    });
+
+    // revoke a token
+    // METHOD: revoke_token
+    // ARGUMENTS: {"name": "name"}
+    // answer is false if there was no token of that name
+    // {"jsonrpc":"2.0","result":true,"id":1}
+    let moved_tokens = tokens.clone();
+    io.add_method_with_meta("revoke_token", move |p:Params, meta: Meta| {
+        let params = parse_params::<TokenParams>(p, &meta, Scope::Admin, &["name"])?;
+        Ok(Value::Bool(moved_tokens.revoke(params.name.as_str())?))
+    });
+
+    // list tokens
+    // METHOD: list_tokens
+    // ARGUMENTS: none
+    // {"jsonrpc":"2.0","result":[{"name":"name","scopes":["read",...]}...],"id":1}
+    let moved_tokens = tokens.clone();
+    io.add_method_with_meta("list_tokens", move |p:Params, meta: Meta| {
+        parse_params::<NoParams>(p, &meta, Scope::Admin, &[])?;
+        Ok(serde_json::to_value(moved_tokens.list()?).unwrap())
+    });
+
+    let extractor = move |request: &hyper::Request<hyper::Body>| {
+        let scopes = request.headers().get(hyper::header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| if h.starts_with("Bearer ") { Some(tokens.scopes(h["Bearer ".len()..].trim())) } else { None })
+            .unwrap_or_default();
+        Meta { scopes }
+    };
+
+    serve(transport, io, extractor);
+}
+
+// serve the api on the transport until it fails
+fn serve<E: MetaExtractor<Meta>> (transport: Transport, io: MetaIoHandler<Meta>, extractor: E) {
+    match transport {
+        Transport::Http(rpc_address) => {
+            let server = ServerBuilder::with_meta_extractor(io, extractor)
+                .start_http(&rpc_address)
+                .expect("Unable to start RPC server");
+
+            server.wait();
+        },
+        Transport::Tls(rpc_address, config) => {
+            let listener = TcpListener::bind(&rpc_address).expect("Unable to start RPC server");
+            let acceptor = TlsAcceptor::from(config);
+            let rpc = Rpc { handler: Arc::new(io), extractor: Arc::new(extractor) };
+            tokio::run(listener.incoming()
+                .map_err(|e| warn!("rpc server stopped {:?}", e))
+                .for_each(move |socket| {
+                    let rpc = rpc.clone();
+                    tokio::spawn(acceptor.accept(socket)
+                        .map_err(|e| debug!("rpc tls handshake failed {:?}", e))
+                        .and_then(move |stream| serve_connection(stream, rpc)));
+                    Ok(())
+                }));
+        },
+        #[cfg(unix)]
+        Transport::Unix(path, mode) => {
+            let listener = bind_unix(&path, mode).expect("Unable to start RPC server");
+            let rpc = Rpc { handler: Arc::new(io), extractor: Arc::new(extractor) };
+            tokio::run(listener.incoming()
+                .map_err(|e| warn!("rpc server stopped {:?}", e))
+                .for_each(move |socket| {
+                    tokio::spawn(serve_connection(socket, rpc.clone()));
+                    Ok(())
+                }));
         }
-        let fee_per_vbyte;
-        if let Value::Number(ref n) = args[3] {
-            if let Some(sats) = n.as_u64() {
-                fee_per_vbyte = std::cmp::min(sats, 100);
-            }
-            else {
-                debug!("malformed fee");
-                return Err(Error::invalid_params("malformed fee"));
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{Client, Endpoint, tls_client_config};
+
+    #[test]
+    fn test_params () {
+        let meta = Meta { scopes: vec!(Scope::Read, Scope::Wallet) };
+        let named = serde_json::from_str::<Params>(r#"{"categories":["misc"],"page":{"sort":"height","limit":10}}"#).unwrap();
+        let params = parse_params::<ListParams>(named, &meta, Scope::Read, &["categories..", "page"]).unwrap();
+        assert_eq!(params.categories, vec!("misc".to_string()));
+        let page = params.page.unwrap().page(false).unwrap();
+        assert_eq!((page.sort, page.ascending, page.limit), (SortKey::Height, false, 10));
+
+        let positional = serde_json::from_str::<Params>(r#"["misc", "alt", {"order":"asc"}]"#).unwrap();
+        let params = parse_params::<ListParams>(positional, &meta, Scope::Read, &["categories..", "page"]).unwrap();
+        assert_eq!(params.categories, vec!("misc".to_string(), "alt".to_string()));
+        assert!(params.page.unwrap().page(false).unwrap().ascending);
+        let params = parse_params::<ListParams>(Params::None, &meta, Scope::Read, &["categories..", "page"]).unwrap();
+        assert!(params.categories.is_empty() && params.page.is_none());
+
+        let positional = serde_json::from_str::<Params>(r#"["horse battery staple correct", 10]"#).unwrap();
+        let params = parse_params::<SweepParams>(positional, &meta, Scope::Wallet, &["passphrase", "fee_per_vbyte", "auto"]).unwrap();
+        assert_eq!((params.fee_per_vbyte, params.auto, params.target), (Some(10), None, None));
+        let malformed = serde_json::from_str::<Params>(r#"{"passphrase":"horse battery staple correct","fee_per_vbyte":"10"}"#).unwrap();
+        assert_eq!(parse_params::<SweepParams>(malformed, &meta, Scope::Wallet, &["passphrase", "fee_per_vbyte", "auto"]).err().unwrap().code, ErrorCode::InvalidParams);
+        let unknown = serde_json::from_str::<Params>(r#"{"passphrase":"horse battery staple correct","fee":10}"#).unwrap();
+        assert!(parse_params::<SweepParams>(unknown, &meta, Scope::Wallet, &["passphrase", "fee_per_vbyte", "auto"]).is_err());
+        let surplus = serde_json::from_str::<Params>(r#"["misc", "more"]"#).unwrap();
+        assert!(parse_params::<CategoryParams>(surplus, &meta, Scope::Read, &["category"]).is_err());
+        assert_eq!(parse_params::<NoParams>(Params::None, &meta, Scope::Admin, &[]).err().unwrap().code, ErrorCode::ServerError(UNAUTHORIZED));
+
+        assert_eq!(Error::from(CrateError::Unsupported("insufficient funds")).code, ErrorCode::ServerError(REFUSED));
+        assert_eq!(Error::from(CrateError::Schema("malformed schema".to_string())).code, ErrorCode::InvalidParams);
+    }
+
+    fn test_data(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("data").join(name)
+    }
+
+    // serve a method answering the scopes of the token in a thread
+    fn serve_scopes(transport: Transport) {
+        let mut io = MetaIoHandler::default();
+        io.add_method_with_meta("scopes", |_: Params, meta: Meta| Ok(serde_json::to_value(meta.scopes).unwrap()));
+        let extractor = |request: &hyper::Request<hyper::Body>| Meta {
+            scopes: if request.headers().get(hyper::header::AUTHORIZATION).is_some() { vec!(Scope::Read) } else { vec!() }
+        };
+        std::thread::spawn(move || serve(transport, io, extractor));
+    }
+
+    // call once the server is listening
+    fn call_scopes(client: &Client) -> Vec<Scope> {
+        for _ in 0..100 {
+            if let Ok(scopes) = client.call("scopes", serde_json::json!({})) {
+                return scopes;
             }
+            std::thread::sleep(Duration::from_millis(20));
         }
-        else {
-            debug!("malformed fee");
-            return Err(Error::invalid_params("malformed fee"));
-        }
+        panic!("rpc server is not answering");
+    }
 
-        match moved_store.write().unwrap().fund(&id, term, amount, fee_per_vbyte, passpharse) {
-            Ok((t,_,_)) => Ok(serde_json::to_value(t.txid()).unwrap()),
-            Err(e) => Err(Error::invalid_params(e.to_string().as_str()))
-        }
-    });
+    #[test]
+    fn test_tls () {
+        assert!(tls_config(&test_data("localhost.pem"), &test_data("localhost.key")).is_ok());
+        assert!(tls_config(&test_data("localhost.pem"), &test_data("localhost.pem")).is_err());
+        assert!(tls_config(&test_data("localhost.key"), &test_data("localhost.key")).is_err());
+        assert!(tls_config(&test_data("missing.pem"), &test_data("localhost.key")).is_err());
 
-    let server = ServerBuilder::new(io)
-        .start_http(rpc_address)
-        .expect("Unable to start RPC server");
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        serve_scopes(Transport::Tls(address, tls_config(&test_data("localhost.pem"), &test_data("localhost.key")).unwrap()));
+        let config = tls_client_config(&test_data("localhost.pem")).unwrap();
+        let client = Client::new(Endpoint::Tls(address, "localhost".to_string(), config.clone()), "token");
+        assert_eq!(call_scopes(&client), vec!(Scope::Read));
+        // the certificate is not for this name
+        let client = Client::new(Endpoint::Tls(address, "example.com".to_string(), config), "token");
+        assert!(client.call::<Vec<Scope>>("scopes", serde_json::json!({})).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix () {
+        let path = std::env::temp_dir().join(format!("defiads-test-{}.sock", std::process::id()));
+        fs::write(&path, "not a socket").unwrap();
+        assert!(bind_unix(&path, 0o600).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+        fs::remove_file(&path).unwrap();
 
-    server.wait();
-}
\ No newline at end of file
+        drop(bind_unix(&path, 0o600).unwrap());
+        // a stale socket is replaced
+        serve_scopes(Transport::Unix(path.clone(), 0o600));
+        let client = Client::new(Endpoint::Unix(path.clone()), "token");
+        assert_eq!(call_scopes(&client), vec!(Scope::Read));
+        let metadata = fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,429 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! typed client of the rpc api
+//!
+//! Calls are blocking, each on a new connection. Errors answered by the api are Error::Rpc with
+//! the JSON-RPC error code, see src/api.rs for their meaning.
+
+use jsonrpc_http_server::{hyper, tokio};
+use jsonrpc_http_server::jsonrpc_core::{Output, Value};
+use jsonrpc_http_server::jsonrpc_core::futures::{Future, Stream};
+use jsonrpc_http_server::tokio::io::{AsyncRead, AsyncWrite};
+use jsonrpc_http_server::tokio::net::TcpStream;
+#[cfg(unix)]
+use jsonrpc_http_server::tokio::net::UnixStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::webpki::DNSNameRef;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use bitcoin::{Address, PublicKey, OutPoint};
+use bitcoin_hashes::{sha256, sha256d};
+use crate::ad::Ad;
+use crate::adspace::{Offer, Rental};
+use crate::db::{Page, Paged, ListedAbstract, PublisherStats};
+use crate::error::Error;
+use crate::fee::FeeEstimate;
+use crate::policy::Rule;
+use crate::schema::Schema;
+use crate::store::{Balance, Imported, Readable, WalletTransaction};
+use crate::subscription::Event;
+use crate::token::{Scope, TokenInfo};
+use crate::wallet::{Funding, Unspent};
+
+/// where the api is served
+pub enum Endpoint {
+    /// plain http on a TCP address
+    Http(SocketAddr),
+    /// https on a TCP address, with the domain name of the server certificate
+    Tls(SocketAddr, String, Arc<ClientConfig>),
+    /// http on a unix domain socket
+    #[cfg(unix)]
+    Unix(PathBuf)
+}
+
+/// TLS configuration trusting the certificate authorities of a file in PEM format,
+/// that is the server certificate itself if it is self signed
+pub fn tls_client_config(ca: &Path) -> Result<Arc<ClientConfig>, Error> {
+    let mut config = ClientConfig::new();
+    let (added, _) = config.root_store.add_pem_file(&mut io::BufReader::new(fs::File::open(ca)?))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed certificate file"))?;
+    if added == 0 {
+        return Err(Error::IO(io::Error::new(io::ErrorKind::InvalidData, "no certificate found")));
+    }
+    Ok(Arc::new(config))
+}
+
+fn hyper_error(e: hyper::Error) -> Error {
+    Error::IO(io::Error::new(io::ErrorKind::Other, e.to_string()))
+}
+
+// send a request on a connection and read the body of the answer
+fn exchange<I: AsyncRead + AsyncWrite + Send + 'static> (stream: I, request: hyper::Request<hyper::Body>) -> impl Future<Item=hyper::Chunk, Error=Error> {
+    hyper::client::conn::handshake(stream)
+        .and_then(move |(mut sender, connection)| {
+            tokio::spawn(connection.map_err(|e| debug!("rpc client connection failed {:?}", e)));
+            sender.send_request(request)
+        })
+        .map_err(hyper_error)
+        .and_then(|response| {
+            let status = response.status();
+            response.into_body().concat2().map_err(hyper_error).and_then(move |body|
+                if status.is_success() {
+                    Ok(body)
+                } else {
+                    Err(Error::IO(io::Error::new(io::ErrorKind::Other, format!("rpc api answered {}", status))))
+                })
+        })
+}
+
+/// fee of a transaction
+#[derive(Clone, Copy, Debug)]
+pub enum Fee {
+    /// satoshi per vbyte
+    PerVbyte(u64),
+    /// estimated to confirm within a number of blocks
+    Target(u16)
+}
+
+/// answer of fund and withdraw
+#[derive(Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Sent {
+    /// id of the transaction sent
+    Transaction(sha256d::Hash),
+    /// base64 encoded PSBT to be signed elsewhere, as the wallet is watch-only
+    Psbt(String)
+}
+
+impl Fee {
+    // add to named parameters
+    fn add_to(self, params: &mut Value) {
+        match self {
+            Fee::PerVbyte(fee_per_vbyte) => params["fee_per_vbyte"] = json!(fee_per_vbyte),
+            Fee::Target(target) => params["target"] = json!(target)
+        }
+    }
+}
+
+/// client of the rpc api authorized with a token
+pub struct Client {
+    endpoint: Endpoint,
+    token: String,
+    next_id: AtomicU64
+}
+
+impl Client {
+    pub fn new(endpoint: Endpoint, token: &str) -> Client {
+        Client { endpoint, token: token.to_string(), next_id: AtomicU64::new(1) }
+    }
+
+    /// call a method with named parameters
+    pub fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({"jsonrpc": "2.0", "method": method, "params": params, "id": id});
+        let request = hyper::Request::post("/")
+            .header(hyper::header::HOST, "localhost")
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .header(hyper::header::AUTHORIZATION, format!("Bearer {}", self.token))
+            .header(hyper::header::CONNECTION, "close")
+            .body(hyper::Body::from(body.to_string()))
+            .expect("can not build rpc request");
+        let mut runtime = tokio::runtime::current_thread::Runtime::new()?;
+        let body = match self.endpoint {
+            Endpoint::Http(ref address) =>
+                runtime.block_on(TcpStream::connect(address).map_err(Error::from)
+                    .and_then(|stream| exchange(stream, request)))?,
+            Endpoint::Tls(ref address, ref domain, ref config) => {
+                let domain = DNSNameRef::try_from_ascii_str(domain.as_str())
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "malformed domain name"))?.to_owned();
+                let connector = TlsConnector::from(config.clone());
+                runtime.block_on(TcpStream::connect(address)
+                    .and_then(move |stream| connector.connect(domain.as_ref(), stream)).map_err(Error::from)
+                    .and_then(|stream| exchange(stream, request)))?
+            },
+            #[cfg(unix)]
+            Endpoint::Unix(ref path) =>
+                runtime.block_on(UnixStream::connect(path).map_err(Error::from)
+                    .and_then(|stream| exchange(stream, request)))?
+        };
+        match serde_json::from_slice::<Output>(&body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))? {
+            Output::Success(success) => Ok(serde_json::from_value(success.result)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?),
+            Output::Failure(failure) => Err(Error::Rpc(failure.error.code.code(), failure.error.message))
+        }
+    }
+
+    fn page(page: &Page) -> Value {
+        json!({"sort": page.sort, "order": if page.ascending { "asc" } else { "desc" }, "cursor": page.cursor, "limit": page.limit})
+    }
+
+    /// the OpenRPC description of the api
+    pub fn discover(&self) -> Result<Value, Error> {
+        self.call("rpc.discover", json!({}))
+    }
+
+    pub fn categories(&self) -> Result<Vec<String>, Error> {
+        self.call("categories", json!({}))
+    }
+
+    pub fn categories_page(&self, page: &Page) -> Result<Paged<String>, Error> {
+        self.call("categories", json!({"page": Self::page(page)}))
+    }
+
+    pub fn list(&self, categories: &[String]) -> Result<Vec<ListedAbstract>, Error> {
+        self.call("list", json!({"categories": categories}))
+    }
+
+    /// a page of abstracts, of all categories if none given
+    pub fn list_page(&self, categories: &[String], page: &Page) -> Result<Paged<ListedAbstract>, Error> {
+        self.call("list", json!({"categories": categories, "page": Self::page(page)}))
+    }
+
+    pub fn search(&self, keywords: &[String]) -> Result<Vec<ListedAbstract>, Error> {
+        self.call("search", json!({"keywords": keywords}))
+    }
+
+    /// abstracts of a category satisfying all conditions e.g. rate < 9000
+    pub fn query(&self, category: &str, conditions: &[String]) -> Result<Vec<ListedAbstract>, Error> {
+        self.call("query", json!({"category": category, "conditions": conditions}))
+    }
+
+    pub fn list_policy(&self) -> Result<Vec<Rule>, Error> {
+        self.call("list_policy", json!({}))
+    }
+
+    pub fn add_policy_rule(&self, rule: &Rule) -> Result<(), Error> {
+        self.call::<bool>("add_policy_rule", json!(rule))?;
+        Ok(())
+    }
+
+    /// false if there was no such rule
+    pub fn remove_policy_rule(&self, rule: &Rule) -> Result<bool, Error> {
+        self.call("remove_policy_rule", json!(rule))
+    }
+
+    /// all content as archive, written as export of the node would
+    pub fn export(&self) -> Result<Vec<u8>, Error> {
+        let archive = self.call::<String>("export", json!({}))?;
+        Ok(base64::decode(archive.as_str()).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "archive is not base64 encoded"))?)
+    }
+
+    pub fn import(&self, archive: &[u8]) -> Result<Imported, Error> {
+        self.call("import", json!({"archive": base64::encode(archive)}))
+    }
+
+    pub fn list_by_publisher(&self, publisher: &PublicKey) -> Result<Vec<ListedAbstract>, Error> {
+        self.call("list_by_publisher", json!({"publisher": publisher}))
+    }
+
+    pub fn publisher_stats(&self, publisher: &PublicKey) -> Result<PublisherStats, Error> {
+        self.call("publisher_stats", json!({"publisher": publisher}))
+    }
+
+    pub fn read(&self, ids: &[String]) -> Result<Vec<Readable>, Error> {
+        self.call("read", json!({"ids": ids}))
+    }
+
+    /// a page of content, of all content if no id given
+    pub fn read_page(&self, ids: &[String], page: &Page) -> Result<Paged<Readable>, Error> {
+        self.call("read", json!({"ids": ids, "page": Self::page(page)}))
+    }
+
+    /// subscribe to events of content with all keywords in the abstract, of all categories if None
+    pub fn subscribe(&self, category: Option<&str>, keywords: &[String]) -> Result<String, Error> {
+        self.call("subscribe", json!({"category": category, "keywords": keywords}))
+    }
+
+    /// wait for events of a subscription, at most timeout seconds
+    pub fn poll(&self, subscription: &str, timeout: Option<u64>) -> Result<Vec<Event>, Error> {
+        self.call("poll", json!({"subscription": subscription, "timeout": timeout}))
+    }
+
+    pub fn unsubscribe(&self, subscription: &str) -> Result<bool, Error> {
+        self.call("unsubscribe", json!({"subscription": subscription}))
+    }
+
+    pub fn balance(&self) -> Result<Balance, Error> {
+        self.call("balance", json!({}))
+    }
+
+    pub fn list_unspent(&self) -> Result<Vec<Unspent>, Error> {
+        self.call("listunspent", json!({}))
+    }
+
+    /// lock or unlock coins for automatic selection of inputs, returns the number of coins changed
+    pub fn lock_unspent(&self, outpoints: &[OutPoint], unlock: bool) -> Result<usize, Error> {
+        self.call("lockunspent", json!({"outpoints": outpoints, "unlock": unlock}))
+    }
+
+    pub fn transactions(&self) -> Result<Vec<WalletTransaction>, Error> {
+        self.call("transactions", json!({}))
+    }
+
+    /// fee estimate to confirm within target blocks, None if the node saw too few blocks yet
+    pub fn estimate_fee(&self, target: u16) -> Result<Option<FeeEstimate>, Error> {
+        self.call("estimatefee", json!({"target": target}))
+    }
+
+    /// words of the BIP39 mnemonic of the wallet's key
+    pub fn reveal_mnemonic(&self, passphrase: &str) -> Result<String, Error> {
+        self.call("reveal_mnemonic", json!({"passphrase": passphrase}))
+    }
+
+    pub fn deposit(&self) -> Result<Address, Error> {
+        self.call("deposit", json!({}))
+    }
+
+    /// prepare a publication, returns its id
+    pub fn prepare(&self, category: &str, abs: &str, content: &str) -> Result<sha256::Hash, Error> {
+        self.call("prepare", json!({"category": category, "abstract": abs, "content": content}))
+    }
+
+    /// declare the schema of abstracts in a category, None removes it
+    pub fn set_schema(&self, category: &str, schema: Option<&Schema>) -> Result<(), Error> {
+        self.call::<bool>("set_schema", json!({"category": category, "schema": schema}))?;
+        Ok(())
+    }
+
+    pub fn read_schema(&self, category: &str) -> Result<Option<Schema>, Error> {
+        self.call("read_schema", json!({"category": category}))
+    }
+
+    pub fn list_prepared(&self) -> Result<Vec<sha256::Hash>, Error> {
+        self.call("list_prepared", json!({}))
+    }
+
+    pub fn read_prepared(&self, id: &sha256::Hash) -> Result<Ad, Error> {
+        #[derive(Deserialize)]
+        struct Prepared {
+            cat: String,
+            abs: String,
+            text: String
+        }
+        let prepared = self.call::<Prepared>("read_prepared", json!({"id": id}))?;
+        Ok(Ad::new(prepared.cat, prepared.abs, prepared.text.as_str()))
+    }
+
+    /// address to fund a prepared publication by publisher from an external wallet
+    pub fn commitment_address(&self, id: &sha256::Hash, publisher: &PublicKey, term: u16) -> Result<Address, Error> {
+        self.call("commitment_address", json!({"id": id, "publisher": publisher, "term": term}))
+    }
+
+    /// withdraw amount satoshis or all available if None
+    pub fn withdraw(&self, passphrase: &str, address: &Address, fee: Fee, amount: Option<u64>, inputs: Option<&[OutPoint]>) -> Result<Sent, Error> {
+        let mut params = json!({"passphrase": passphrase, "address": address, "amount": amount, "inputs": inputs});
+        fee.add_to(&mut params);
+        self.call("withdraw", params)
+    }
+
+    /// fund a prepared publication for term blocks
+    pub fn fund(&self, passphrase: &str, id: &sha256::Hash, amount: u64, term: u16, fee: Fee, inputs: Option<&[OutPoint]>) -> Result<Sent, Error> {
+        let mut params = json!({"passphrase": passphrase, "id": id, "amount": amount, "term": term, "inputs": inputs});
+        fee.add_to(&mut params);
+        self.call("fund", params)
+    }
+
+    /// spend matured funding of a publication into a new funding or extend it from the wallet before the funding matured,
+    /// returns the transaction id
+    pub fn renew(&self, passphrase: &str, id: &sha256::Hash, term: u16, fee: Fee) -> Result<sha256d::Hash, Error> {
+        let mut params = json!({"passphrase": passphrase, "id": id, "term": term});
+        fee.add_to(&mut params);
+        self.call("renew", params)
+    }
+
+    /// replace an unconfirmed transaction with one paying a higher fee, returns the id of the replacement
+    pub fn bump_fee(&self, passphrase: &str, txid: &sha256d::Hash, fee: Fee) -> Result<sha256d::Hash, Error> {
+        let mut params = json!({"passphrase": passphrase, "txid": txid});
+        fee.add_to(&mut params);
+        self.call("bumpfee", params)
+    }
+
+    /// finalize and send a base64 encoded PSBT of a watch-only wallet signed elsewhere, returns the transaction id
+    pub fn finalize_and_send(&self, psbt: &str) -> Result<sha256d::Hash, Error> {
+        self.call("finalize_and_send", json!({"psbt": psbt}))
+    }
+
+    /// forget a transaction of a watch-only wallet waiting to be signed, returns false if there was none with this id
+    pub fn discard_psbt(&self, txid: &sha256d::Hash) -> Result<bool, Error> {
+        self.call("discard_psbt", json!({"txid": txid}))
+    }
+
+    pub fn revoke(&self, passphrase: &str, id: &sha256::Hash) -> Result<(), Error> {
+        self.call::<bool>("revoke", json!({"passphrase": passphrase, "id": id}))?;
+        Ok(())
+    }
+
+    pub fn list_funded(&self) -> Result<Vec<Funding>, Error> {
+        self.call("list_funded", json!({}))
+    }
+
+    /// sweep matured funding, returns the transaction id or None if there was nothing to sweep
+    pub fn sweep(&self, passphrase: &str, fee: Fee, auto: Option<bool>) -> Result<Option<sha256d::Hash>, Error> {
+        let mut params = json!({"passphrase": passphrase, "auto": auto});
+        fee.add_to(&mut params);
+        self.call("sweep", params)
+    }
+
+    /// offer to fund ads of advertisers, returns the id of the publication advertising the offer
+    pub fn offer_adspace(&self, passphrase: &str, amount: u64, term: u16, price: u64, fee: Fee) -> Result<sha256::Hash, Error> {
+        let mut params = json!({"passphrase": passphrase, "amount": amount, "term": term, "price": price});
+        fee.add_to(&mut params);
+        self.call("offer_adspace", params)
+    }
+
+    /// withdraw an offer of ad space, returns false if there was no such offer
+    pub fn cancel_offer(&self, id: &sha256::Hash) -> Result<bool, Error> {
+        self.call("cancel_offer", json!({"id": id}))
+    }
+
+    pub fn list_offers(&self) -> Result<Vec<Offer>, Error> {
+        self.call("list_offers", json!({}))
+    }
+
+    /// rent offered ad space for an ad, returns the address to pay the price to
+    pub fn rent_adspace(&self, offer: &sha256::Hash, category: &str, abs: &str, content: &str) -> Result<Rental, Error> {
+        self.call("rent_adspace", json!({"offer": offer, "category": category, "abstract": abs, "content": content}))
+    }
+
+    pub fn list_rentals(&self) -> Result<Vec<Rental>, Error> {
+        self.call("list_rentals", json!({}))
+    }
+
+    /// create a token for a client, returns its secret
+    pub fn create_token(&self, name: &str, scopes: &[Scope]) -> Result<String, Error> {
+        self.call("create_token", json!({"name": name, "scopes": scopes}))
+    }
+
+    /// false if there was no token of that name
+    pub fn revoke_token(&self, name: &str) -> Result<bool, Error> {
+        self.call("revoke_token", json!({"name": name}))
+    }
+
+    pub fn list_tokens(&self) -> Result<Vec<TokenInfo>, Error> {
+        self.call("list_tokens", json!({}))
+    }
+}
@@ -17,6 +17,7 @@
 //! distributed content
 use crate::bitcoin::PublicKey;
 use crate::bitcoin_hashes::hex::ToHex;
+use crate::bitcoin_hashes::{sha256, Hash, HashEngine};
 use crate::bitcoin_wallet::{
     proved::ProvedTransaction
 };
@@ -66,6 +67,14 @@ impl ContentKey {
         digest.copy_from_slice(&hash[..]);
         ContentKey{digest}
     }
+
+    /// key of the funding of content by a funder, content is synchronized with its digest and its funding
+    pub fn funding (digest: &sha256::Hash, funder: &PublicKey) -> ContentKey {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&digest[..]);
+        engine.input(funder.to_bytes().as_slice());
+        ContentKey::new(&sha256::Hash::from_engine(engine)[..])
+    }
 }
 
 /// replicated content
@@ -27,7 +27,7 @@ use crate::error::Error;
 use std::time::SystemTime;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::collections::{HashSet};
+use std::collections::{HashSet, HashMap};
 use rand::{thread_rng, Rng, RngCore};
 use crate::content::Content;
 use serde_cbor;
@@ -41,6 +41,7 @@ use std::time::UNIX_EPOCH;
 use crate::discovery::NetAddress;
 use bitcoin_wallet::account::{AccountAddressType, Account, MasterAccount, KeyDerivation};
 use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::network::constants::Network;
 use bitcoin_wallet::coins::{Coin, Coins};
 use bitcoin_wallet::proved::ProvedTransaction;
@@ -48,12 +49,34 @@ use siphasher::sip::SipHasher;
 use byteorder::LittleEndian;
 use bitcoin::consensus::{serialize, deserialize};
 use crate::ad::Ad;
+use crate::schema::Schema;
+use crate::token::{Scope, TokenInfo};
+use crate::fee::BlockFeerates;
+use crate::adspace::{Offer, Rental};
 use rusqlite::types::ValueRef;
 use rusqlite::types::Null;
 
 
 pub type SharedDB = Arc<Mutex<DB>>;
 
+/// a transaction of the wallet as stored
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StoredTxOut {
+    pub transaction: bitcoin::Transaction,
+    /// block hash if confirmed
+    pub confirmed: Option<sha256d::Hash>,
+    /// publisher, id and term of the publication it funds
+    pub funding: Option<(PublicKey, sha256::Hash, u16)>,
+    /// outputs spent by its inputs if sent by this wallet and known
+    pub spent: Option<Vec<TxOut>>
+}
+
+/// outgoing transaction, publisher, id and term of the publication it funds, outputs it spends if known
+pub type UnconfirmedTxOut = (bitcoin::Transaction, Option<(PublicKey, sha256::Hash, u16)>, Option<Vec<TxOut>>);
+
+/// transaction to be signed elsewhere, publisher, id and term of the publication it funds
+pub type PendingPsbt = (PartiallySignedTransaction, Option<(PublicKey, sha256::Hash, u16)>);
+
 // number of hash functions
 const NH:usize = 4;
 // random, I swear
@@ -121,9 +144,27 @@ impl<'db> TX<'db> {
                 publisher blob,
                 term number,
                 weight number,
-                length number
+                length number,
+                amount number,
+                key text
+            ) without rowid;
+
+            create index if not exists content_publisher on content (publisher);
+
+            create table if not exists cofunding (
+                id text,
+                funder blob,
+                block_id text,
+                height number,
+                proof blob,
+                term number,
+                amount number,
+                key text,
+                primary key (id, funder)
             ) without rowid;
 
+            create index if not exists cofunding_key on cofunding (key);
+
             create table if not exists publication (
                 id text primary key,
                 cat text,
@@ -165,9 +206,140 @@ impl<'db> TX<'db> {
                 confirmed text,
                 publisher blob,
                 id text,
+                term number,
+                spent blob
+            ) without rowid;
+
+            create table if not exists txin (
+                txid text primary key,
+                tx blob,
+                confirmed text
+            ) without rowid;
+
+            create table if not exists token (
+                name text primary key,
+                hash text,
+                scopes text
+            ) without rowid;
+
+            create index if not exists token_hash on token (hash);
+
+            create table if not exists hidden (
+                id text primary key
+            ) without rowid;
+
+            create table if not exists locked (
+                txid text,
+                vout number,
+                primary key (txid, vout)
+            ) without rowid;
+
+            create table if not exists offer (
+                id text primary key,
+                amount number,
+                term number,
+                price number
+            ) without rowid;
+
+            create table if not exists rental (
+                id text primary key,
+                offer text,
+                address text,
+                price number,
+                amount number,
+                term number,
+                expiry number,
+                paid text,
+                block text,
+                funded text
+            ) without rowid;
+
+            create table if not exists commitment (
+                script blob primary key,
+                id text,
+                publisher blob,
+                term number,
+                expiry number,
+                confirmed text
+            ) without rowid;
+
+            create table if not exists psbt (
+                txid text primary key,
+                psbt blob,
+                publisher blob,
+                id text,
                 term number
             ) without rowid;
+
+            create table if not exists schema (
+                cat text primary key,
+                schema text
+            ) without rowid;
+
+            create table if not exists feerate (
+                height number primary key,
+                p10 number,
+                p25 number,
+                p50 number,
+                p75 number,
+                p90 number
+            ) without rowid;
+
+            create table if not exists tombstone (
+                id text,
+                funder blob,
+                signature blob,
+                expiry number,
+                key text,
+                primary key (id, funder)
+            ) without rowid;
+
+            create index if not exists tombstone_key on tombstone (key);
+
+            create virtual table if not exists content_search using fts5 (
+                id unindexed,
+                cat,
+                abs,
+                text
+            );
+
+            insert into content_search (id, cat, abs, text)
+                select id, cat, abs, ad from content where id not in (select id from content_search);
         "#).expect("failed to create db tables");
+        // content stored by earlier versions does not have the funded amount
+        if self.tx.prepare("select amount from content").is_err() {
+            self.tx.execute_batch(r#"
+                alter table content add column amount number;
+                update content set amount = weight * length;
+            "#).expect("failed to add amount to content table");
+        }
+        // content stored by earlier versions does not have the key of its funding
+        if self.tx.prepare("select key from content").is_err() {
+            self.tx.execute_batch(r#"
+                alter table content add column key text;
+            "#).expect("failed to add key to content table");
+            let funders = {
+                let mut query = self.tx.prepare("select id, publisher from content").expect("failed to read content");
+                let funders = query.query_map(NO_PARAMS, |r| Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, Vec<u8>>(1))))
+                    .expect("failed to read content").filter_map(|r| r.ok()).collect::<Vec<_>>();
+                funders
+            };
+            for (id, publisher) in funders {
+                let key = ContentKey::funding(&sha256::Hash::from_hex(id.as_str()).expect("stored id of content not hex"),
+                                              &PublicKey::from_slice(publisher.as_slice()).expect("stored publisher of content not a pubkey"));
+                self.tx.execute("update content set key = ?2 where id = ?1", &[&id as &dyn ToSql, &key_hex(&key)])
+                    .expect("failed to store key of content");
+            }
+        }
+        self.tx.execute_batch(r#"
+            create index if not exists content_key on content (key);
+        "#).expect("failed to index keys of content");
+        // outgoing transactions stored by earlier versions do not have the outputs they spend
+        if self.tx.prepare("select spent from txout").is_err() {
+            self.tx.execute_batch(r#"
+                alter table txout add column spent blob;
+            "#).expect("failed to add spent to txout table");
+        }
     }
 
     pub fn read_publication(&self, id: &sha256::Hash) -> Result<Option<Ad>, Error> {
@@ -206,6 +378,120 @@ impl<'db> TX<'db> {
         Ok(id)
     }
 
+    pub fn store_token(&mut self, name: &str, hash: &sha256::Hash, scopes: &[Scope]) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            insert into token (name, hash, scopes) values (?1, ?2, ?3)
+        "#, &[&name as &dyn ToSql, &hash.to_hex(), &serde_json::to_string(scopes).expect("can not serialize scopes")])?)
+    }
+
+    pub fn read_token_scopes(&self, hash: &sha256::Hash) -> Result<Option<Vec<Scope>>, Error> {
+        Ok(self.tx.query_row(r#"
+            select scopes from token where hash = ?1
+        "#, &[&hash.to_hex() as &dyn ToSql], |r| {
+            Ok(serde_json::from_str::<Vec<Scope>>(r.get_unwrap::<usize, String>(0).as_str()).expect("can not deserialize stored scopes"))
+        }).optional()?)
+    }
+
+    pub fn delete_token(&mut self, name: &str) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from token where name = ?1
+        "#, &[&name as &dyn ToSql])?)
+    }
+
+    pub fn list_tokens(&self) -> Result<Vec<TokenInfo>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select name, scopes from token order by name
+        "#)?;
+        let mut result = Vec::new();
+        for r in statement.query_map(NO_PARAMS, |r| {
+            Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, String>(1)))
+        })? {
+            let (name, scopes) = r?;
+            result.push(TokenInfo { name, scopes: serde_json::from_str(scopes.as_str()).expect("can not deserialize stored scopes") });
+        }
+        Ok(result)
+    }
+
+    pub fn store_locked(&mut self, point: &OutPoint) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            insert or ignore into locked (txid, vout) values (?1, ?2)
+        "#, &[&point.txid.to_string() as &dyn ToSql, &point.vout])?)
+    }
+
+    pub fn delete_locked(&mut self, point: &OutPoint) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from locked where txid = ?1 and vout = ?2
+        "#, &[&point.txid.to_string() as &dyn ToSql, &point.vout])?)
+    }
+
+    /// coins excluded from automatic selection of inputs
+    pub fn read_locked(&self) -> Result<Vec<OutPoint>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select txid, vout from locked
+        "#)?;
+        let result = statement.query_map(NO_PARAMS, |r| {
+            Ok(OutPoint {
+                txid: sha256d::Hash::from_hex(r.get_unwrap::<usize, String>(0).as_str()).expect("stored txid of locked coin not hex"),
+                vout: r.get_unwrap::<usize, u32>(1)
+            })
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    pub fn store_schema(&mut self, cat: &str, schema: &Schema) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            insert or replace into schema (cat, schema) values (?1, ?2)
+        "#, &[&cat as &dyn ToSql, &serde_json::to_string(schema).expect("can not serialize schema")])?)
+    }
+
+    pub fn read_schema(&self, cat: &str) -> Result<Option<Schema>, Error> {
+        Ok(self.tx.query_row(r#"
+            select schema from schema where cat = ?1
+        "#, &[&cat as &dyn ToSql], |r| {
+            Ok(Schema::from_str(r.get_unwrap::<usize, String>(0).as_str()).expect("can not deserialize stored schema"))
+        }).optional()?)
+    }
+
+    pub fn delete_schema(&mut self, cat: &str) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from schema where cat = ?1
+        "#, &[&cat as &dyn ToSql])?)
+    }
+
+    /// store feerates of a block, replacing those of the same or higher blocks re-organized out
+    pub fn store_feerates(&mut self, feerates: &BlockFeerates) -> Result<(), Error> {
+        self.tx.execute(r#"
+            delete from feerate where height >= ?1
+        "#, &[&feerates.height as &dyn ToSql])?;
+        let p = &feerates.percentiles;
+        self.tx.execute(r#"
+            insert into feerate (height, p10, p25, p50, p75, p90) values (?1, ?2, ?3, ?4, ?5, ?6)
+        "#, &[&feerates.height as &dyn ToSql, &(p[0] as i64), &(p[1] as i64), &(p[2] as i64), &(p[3] as i64), &(p[4] as i64)])?;
+        Ok(())
+    }
+
+    pub fn delete_feerates_before(&mut self, height: u32) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from feerate where height < ?1
+        "#, &[&height as &dyn ToSql])?)
+    }
+
+    pub fn read_feerates(&self) -> Result<Vec<BlockFeerates>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select height, p10, p25, p50, p75, p90 from feerate order by height
+        "#)?;
+        let result = statement.query_map(NO_PARAMS, |r| {
+            Ok(BlockFeerates {
+                height: r.get_unwrap::<usize, u32>(0),
+                percentiles: [r.get_unwrap::<usize, i64>(1) as u64, r.get_unwrap::<usize, i64>(2) as u64,
+                    r.get_unwrap::<usize, i64>(3) as u64, r.get_unwrap::<usize, i64>(4) as u64, r.get_unwrap::<usize, i64>(5) as u64]
+            })
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
     pub fn rescan(&mut self, after: &sha256d::Hash) -> Result<(), Error> {
         self.tx.execute(r#"
             update processed set block = ?1
@@ -213,29 +499,252 @@ impl<'db> TX<'db> {
         self.tx.execute(r#"
             delete from txout
         "#, NO_PARAMS)?;
+        self.tx.execute(r#"
+            delete from txin
+        "#, NO_PARAMS)?;
         self.tx.execute(r#"
             delete from coins
         "#, NO_PARAMS)?;
         Ok(())
     }
 
-    pub fn store_txout (&mut self, tx: &bitcoin::Transaction, funding: Option<(&PublicKey, &sha256::Hash, u16)>) -> Result<(), Error> {
+    pub fn store_offer (&mut self, offer: &Offer) -> Result<(), Error> {
+        self.tx.execute(r#"
+            insert or replace into offer (id, amount, term, price) values (?1, ?2, ?3, ?4)
+        "#, &[&offer.id as &dyn ToSql, &(offer.amount as i64), &offer.term, &(offer.price as i64)])?;
+        Ok(())
+    }
+
+    pub fn read_offers (&self) -> Result<Vec<Offer>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select id, amount, term, price from offer
+        "#)?;
+        let result = statement.query_map(NO_PARAMS, |r| {
+            Ok(Offer {
+                id: r.get_unwrap(0),
+                amount: r.get_unwrap::<usize, i64>(1) as u64,
+                term: r.get_unwrap(2),
+                price: r.get_unwrap::<usize, i64>(3) as u64
+            })
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    pub fn delete_offer (&mut self, id: &str) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from offer where id = ?1
+        "#, &[&id as &dyn ToSql])?)
+    }
+
+    pub fn store_rental (&mut self, rental: &Rental) -> Result<(), Error> {
+        self.tx.execute(r#"
+            insert or replace into rental (id, offer, address, price, amount, term, expiry, paid, block, funded) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        "#, &[&rental.id as &dyn ToSql, &rental.offer, &rental.address, &(rental.price as i64), &(rental.amount as i64), &rental.term,
+            &rental.expiry, &rental.paid, &rental.block, &rental.funded])?;
+        Ok(())
+    }
+
+    /// payments of rent not yet funded are no longer confirmed if their block is unwound,
+    /// they are waited for again until expiry at least
+    pub fn unconfirm_rentals (&mut self, block_id: &sha256d::Hash, expiry: u32) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            update rental set paid = null, block = null, expiry = max(expiry, ?2) where block = ?1 and funded is null
+        "#, &[&block_id.to_hex() as &dyn ToSql, &expiry])?)
+    }
+
+    /// drop rentals not paid until height
+    pub fn delete_unpaid_rentals (&mut self, height: u32) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from rental where paid is null and expiry <= ?1
+        "#, &[&height as &dyn ToSql])?)
+    }
+
+    pub fn read_rentals (&self) -> Result<Vec<Rental>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select id, offer, address, price, amount, term, expiry, paid, block, funded from rental
+        "#)?;
+        let result = statement.query_map(NO_PARAMS, |r| {
+            Ok(Rental {
+                id: r.get_unwrap(0),
+                offer: r.get_unwrap(1),
+                address: r.get_unwrap(2),
+                price: r.get_unwrap::<usize, i64>(3) as u64,
+                amount: r.get_unwrap::<usize, i64>(4) as u64,
+                term: r.get_unwrap(5),
+                expiry: r.get_unwrap(6),
+                paid: r.get_unwrap(7),
+                block: r.get_unwrap(8),
+                funded: r.get_unwrap(9),
+                stalled: false
+            })
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    /// watch until expiry height for a payment to a commitment script funding a prepared publication from outside the wallet
+    pub fn store_commitment (&mut self, script: &Script, id: &sha256::Hash, publisher: &PublicKey, term: u16, expiry: u32) -> Result<(), Error> {
+        self.tx.execute(r#"
+            insert or replace into commitment (script, id, publisher, term, expiry) values (?1, ?2, ?3, ?4, ?5)
+        "#, &[&script.to_bytes() as &dyn ToSql, &id.to_string(), &publisher.to_bytes(), &term, &expiry])?;
+        Ok(())
+    }
+
+    /// commitment scripts watched for, with id of the publication, publisher and term
+    pub fn read_commitments (&self) -> Result<Vec<(Script, sha256::Hash, PublicKey, u16)>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select script, id, publisher, term from commitment where confirmed is null
+        "#)?;
+        let result = statement.query_map(NO_PARAMS, |r| {
+            Ok((Script::from(r.get_unwrap::<usize, Vec<u8>>(0)),
+                sha256::Hash::from_hex(r.get_unwrap::<usize, String>(1).as_str()).expect("stored id of commitment not hex"),
+                PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(2).as_slice()).expect("stored publisher of commitment not a pubkey"),
+                r.get_unwrap::<usize, u16>(3)))
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    /// stop watching a commitment paid in a block, it is kept until expiry in case the block is unwound
+    pub fn confirm_commitment (&mut self, script: &Script, block_id: &sha256d::Hash, expiry: u32) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            update commitment set confirmed = ?2, expiry = max(expiry, ?3) where script = ?1
+        "#, &[&script.to_bytes() as &dyn ToSql, &block_id.to_string(), &expiry])?)
+    }
+
+    /// watch again for commitments paid in an unwound block
+    pub fn unconfirm_commitments (&mut self, block_id: &sha256d::Hash) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            update commitment set confirmed = null where confirmed = ?1
+        "#, &[&block_id.to_string() as &dyn ToSql])?)
+    }
+
+    /// store a transaction of a watch-only wallet to be signed elsewhere, keyed by the id of the unsigned transaction
+    pub fn store_psbt (&mut self, psbt: &PartiallySignedTransaction, funding: Option<(&PublicKey, &sha256::Hash, u16)>) -> Result<(), Error> {
+        let txid = psbt.global.unsigned_tx.txid().to_string();
+        if let Some((publisher, id, term)) = funding {
+            self.tx.execute(r#"
+            insert or replace into psbt (txid, psbt, publisher, id, term) values (?1, ?2, ?3, ?4, ?5)
+        "#, &[&txid as &dyn ToSql, &serialize(psbt), &publisher.to_bytes(), &id.to_string(), &term])?;
+        }
+        else {
+            self.tx.execute(r#"
+            insert or replace into psbt (txid, psbt) values (?1, ?2)
+        "#, &[&txid as &dyn ToSql, &serialize(psbt)])?;
+        }
+        Ok(())
+    }
+
+    pub fn read_psbt (&self, txid: &sha256d::Hash) -> Result<Option<PendingPsbt>, Error> {
+        Ok(self.tx.query_row(r#"
+            select psbt, publisher, id, term from psbt where txid = ?1
+        "#, &[&txid.to_string() as &dyn ToSql], |r| {
+            let funding = match (r.get_raw(1), r.get_raw(2), r.get_raw(3)) {
+                (ValueRef::Blob(publisher), ValueRef::Text(id), ValueRef::Integer(term)) =>
+                    Some((PublicKey::from_slice(publisher).expect("stored publisher in psbt not a pubkey"),
+                        sha256::Hash::from_hex(std::str::from_utf8(id).unwrap()).expect("stored id in psbt not hex"),
+                        term as u16)),
+                _ => None
+            };
+            Ok((deserialize::<PartiallySignedTransaction>(r.get_unwrap::<usize, Vec<u8>>(0).as_slice()).expect("can not deserialize stored psbt"),
+                funding))
+        }).optional()?)
+    }
+
+    pub fn delete_psbt (&mut self, txid: &sha256d::Hash) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from psbt where txid = ?1
+        "#, &[&txid.to_string() as &dyn ToSql])?)
+    }
+
+    /// store an outgoing transaction with the outputs spent by its inputs, in the order of inputs
+    pub fn store_txout (&mut self, tx: &bitcoin::Transaction, funding: Option<(&PublicKey, &sha256::Hash, u16)>, spent: &[TxOut]) -> Result<(), Error> {
         if let Some((publisher, id, term)) = funding {
             self.tx.execute(r#"
-            insert or replace into txout (txid, tx, publisher, id, term) values (?1, ?2, ?3, ?4, ?5)
+            insert or replace into txout (txid, tx, publisher, id, term, spent) values (?1, ?2, ?3, ?4, ?5, ?6)
         "#, &[&tx.txid().to_string() as &dyn ToSql,
                 &serialize(tx),
-                &publisher.to_bytes(), &id.to_string(), &term])?;
+                &publisher.to_bytes(), &id.to_string(), &term, &serialize(&spent.to_vec())])?;
         }
         else {
             self.tx.execute(r#"
-            insert or replace into txout (txid, tx) values (?1, ?2)
+            insert or replace into txout (txid, tx, spent) values (?1, ?2, ?3)
         "#, &[&tx.txid().to_string() as &dyn ToSql,
-                &serialize(tx)])?;
+                &serialize(tx), &serialize(&spent.to_vec())])?;
+        }
+        Ok(())
+    }
+
+    /// an unconfirmed outgoing transaction with its funding and the outputs it spends if known
+    pub fn read_unconfirmed_txout (&self, txid: &sha256d::Hash) -> Result<Option<UnconfirmedTxOut>, Error> {
+        Ok(self.tx.query_row(r#"
+            select tx, publisher, id, term, spent from txout where txid = ?1 and confirmed is null
+        "#, &[&txid.to_string() as &dyn ToSql], |r| {
+            let funding = match (r.get_raw(1), r.get_raw(2), r.get_raw(3)) {
+                (ValueRef::Blob(publisher), ValueRef::Text(id), ValueRef::Integer(term)) =>
+                    Some((PublicKey::from_slice(publisher).expect("stored publisher in txout not a pubkey"),
+                        sha256::Hash::from_hex(std::str::from_utf8(id).unwrap()).expect("stored id in txout not hex"),
+                        term as u16)),
+                _ => None
+            };
+            let spent = match r.get_raw(4) {
+                ValueRef::Blob(spent) => Some(deserialize::<Vec<TxOut>>(spent).expect("can not deserialize stored spent outputs")),
+                _ => None
+            };
+            Ok((deserialize::<bitcoin::Transaction>(r.get_unwrap::<usize, Vec<u8>>(0).as_slice()).expect("can not deserialize stored transaction"),
+                funding, spent))
+        }).optional()?)
+    }
+
+    /// record a transaction of the wallet confirmed by a block, a transaction not sent by the wallet is received,
+    /// received transactions are stored apart so they are neither sent nor spend coins of the wallet
+    pub fn confirm_txout (&mut self, tx: &bitcoin::Transaction, block_id: &sha256d::Hash) -> Result<(), Error> {
+        let sent = self.tx.execute(r#"
+            update txout set confirmed = ?2 where txid = ?1
+        "#, &[&tx.txid().to_string() as &dyn ToSql, &block_id.to_string()])? > 0;
+        if !sent {
+            self.tx.execute(r#"
+                insert or replace into txin (txid, tx, confirmed) values (?1, ?2, ?3)
+            "#, &[&tx.txid().to_string() as &dyn ToSql, &serialize(tx), &block_id.to_string()])?;
         }
         Ok(())
     }
 
+    /// all transactions of the wallet, sent and received
+    pub fn read_txouts (&self) -> Result<Vec<StoredTxOut>, Error> {
+        // received transactions spent none of the coins of the wallet
+        let mut query = self.tx.prepare(r#"
+            select tx, confirmed, publisher, id, term, spent from txout
+            union all select tx, confirmed, null, null, null, ?1 from txin
+        "#)?;
+        let result = query.query_map(&[&serialize(&Vec::<TxOut>::new())], |r| {
+            Ok(StoredTxOut {
+                transaction: deserialize::<bitcoin::Transaction>(r.get_unwrap::<usize, Vec<u8>>(0).as_slice()).expect("can not deserialize stored transaction"),
+                confirmed: r.get_unwrap::<usize, Option<String>>(1).map(|c| sha256d::Hash::from_hex(c.as_str()).expect("stored block hash in txout not hex")),
+                funding: match (r.get_raw(2), r.get_raw(3), r.get_raw(4)) {
+                    (ValueRef::Blob(publisher), ValueRef::Text(id), ValueRef::Integer(term)) =>
+                        Some((PublicKey::from_slice(publisher).expect("stored publisher in txout not a pubkey"),
+                            sha256::Hash::from_hex(std::str::from_utf8(id).unwrap()).expect("stored id in txout not hex"),
+                            term as u16)),
+                    _ => None
+                },
+                spent: match r.get_raw(5) {
+                    ValueRef::Blob(spent) => Some(deserialize::<Vec<TxOut>>(spent).expect("can not deserialize stored spent outputs")),
+                    _ => None
+                }
+            })
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    pub fn delete_txout (&mut self, txid: &sha256d::Hash) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from txout where txid = ?1
+        "#, &[&txid.to_string() as &dyn ToSql])?)
+    }
+
     pub fn read_unconfirmed (&self) -> Result<Vec<(bitcoin::Transaction, Option<(PublicKey, sha256::Hash, u16)>)>, Error> {
         let mut result = Vec::new();
         // remove unconfirmed spend
@@ -439,31 +948,49 @@ impl<'db> TX<'db> {
         })?)
     }
 
-    pub fn compute_content_iblt(&mut self, len: u32) -> Result<IBLT<ContentKey>, Error> {
-        let mut iblt = IBLT::new(len, NH, K0, K1);
-
+    // keys of content synchronized with peers, the digest of content and each of its funding
+    fn read_content_keys(&self) -> Result<Vec<ContentKey>, Error> {
+        let mut keys = Vec::new();
         let mut query = self.tx.prepare(r#"
-            select id from content
+            select id from content union all select key from content union all select key from cofunding
         "#)?;
         for r in query.query_map::<String,&[&dyn ToSql],_>(NO_PARAMS,
                                                               |r| Ok(r.get(0)?))? {
-            if let Ok(id) = r {
-                iblt.insert(&ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]));
+            if let Ok(key) = r {
+                keys.push(ContentKey::new(&hex::decode(key.as_str()).expect("stored key of content not hex")));
             }
         }
+        Ok(keys)
+    }
+
+    pub fn compute_content_iblt(&mut self, len: u32) -> Result<IBLT<ContentKey>, Error> {
+        let mut iblt = IBLT::new(len, NH, K0, K1);
+        for key in self.read_content_keys()? {
+            iblt.insert(&key);
+        }
         Ok(iblt)
     }
 
     pub fn compute_content_sketch(&mut self, len: usize) -> Result<(Vec<u64>, Vec<(u64, u64)>, u32), Error> {
-        let mut query = self.tx.prepare(r#"
-            select id from content
-        "#)?;
-        let mut key_iterator = query.query_map::<String,&[&dyn ToSql],_>(NO_PARAMS,
-                                                     |r| Ok(r.get(0)?))?
-            .filter_map(|r| if let Ok(id) = r {
-                Some(ContentKey::new(&sha256::Hash::from_hex(id.as_str()).unwrap()[..]))}else{None});
+        let keys = self.read_content_keys()?;
+        Ok(min_sketch(len, K0, K1, &mut keys.into_iter()))
+    }
 
-        Ok(min_sketch(len, K0, K1, &mut key_iterator))
+    /// funding or co-funding of content by its key as content
+    pub fn read_funding(&self, key: &ContentKey) -> Result<Option<Content>, Error> {
+        let key = key_hex(key);
+        if let Some(id) = self.tx.query_row(r#"
+            select id from content where key = ?1
+        "#, &[&key], |r| Ok(r.get_unwrap::<usize, String>(0))).optional()? {
+            return self.read_content(&sha256::Hash::from_hex(id.as_str())?);
+        }
+        if let Some((id, funder)) = self.tx.query_row(r#"
+            select id, funder from cofunding where key = ?1
+        "#, &[&key], |r| Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, Vec<u8>>(1)))).optional()? {
+            let funder = PublicKey::from_slice(funder.as_slice()).expect("stored funder of content not a pubkey");
+            return Ok(self.read_cofundings(&sha256::Hash::from_hex(id.as_str())?)?.into_iter().find(|c| c.funder == funder));
+        }
+        Ok(None)
     }
 
 
@@ -503,15 +1030,108 @@ impl<'db> TX<'db> {
         let proof = serde_cbor::ser::to_vec(&c.funding).unwrap();
         let publisher = c.funder.to_bytes();
         let length = c.length();
+        let text = c.ad.content.as_string().expect("can not decompress ad content");
         debug!("store content {}", id);
-        Ok(self.tx.execute(r#"
-            insert or replace into content (id, cat, abs, ad, block_id, height, proof, publisher, term, weight, length)
-            values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+        self.tx.execute(r#"
+            delete from content_search where id = ?1
+        "#, &[&id.to_hex() as &dyn ToSql])?;
+        self.tx.execute(r#"
+            insert into content_search (id, cat, abs, text) values (?1, ?2, ?3, ?4)
+        "#, &[&id.to_hex() as &dyn ToSql, &c.ad.cat, &c.ad.abs, &text])?;
+        let stored = self.tx.execute(r#"
+            insert or replace into content (id, cat, abs, ad, block_id, height, proof, publisher, term, weight, length, amount, key)
+            values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
         "#, &[&id.to_hex() as &dyn ToSql,
-            &c.ad.cat, &c.ad.abs, &c.ad.content.as_string().expect("can not decompress ad content"),
+            &c.ad.cat, &c.ad.abs, &text,
             &block_id.to_hex(), &height, &proof, &publisher, &c.term,
-            &((amount / length as u64) as u32), &length]
-        )?)
+            &((amount / length as u64) as u32), &length, &(amount as i64), &key_hex(&ContentKey::funding(&id, &c.funder))]
+        )?;
+        self.update_weight(&id.to_hex())?;
+        Ok(stored)
+    }
+
+    /// store funding of known content by a funder other than its publisher, replacing earlier funding by the same funder
+    pub fn store_cofunding(&mut self, height: u32, block_id: &sha256d::Hash, c: &Content, amount: u64) -> Result<usize, Error> {
+        let id = c.ad.digest().to_hex();
+        debug!("store co-funding of content {} by {}", id, c.funder);
+        let stored = self.tx.execute(r#"
+            insert or replace into cofunding (id, funder, block_id, height, proof, term, amount, key)
+            values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        "#, &[&id as &dyn ToSql, &c.funder.to_bytes(), &block_id.to_hex(), &height,
+            &serde_cbor::ser::to_vec(&c.funding).unwrap(), &c.term, &(amount as i64), &key_hex(&ContentKey::funding(&c.ad.digest(), &c.funder))])?;
+        self.update_weight(&id)?;
+        Ok(stored)
+    }
+
+    /// co-funding of content as content, the ad is that of the content
+    pub fn read_cofundings(&self, digest: &sha256::Hash) -> Result<Vec<Content>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select c.cat, c.abs, c.ad, f.proof, f.funder, f.term
+            from cofunding f join content c on f.id = c.id where f.id = ?1
+        "#)?;
+        let result = statement.query_map(&[digest.to_hex()], |r| Ok(
+            Content {
+                ad: Ad::new(r.get_unwrap(0), r.get_unwrap(1), r.get_unwrap::<usize, String>(2).as_str()),
+                funding: serde_cbor::from_reader(std::io::Cursor::new(r.get_unwrap::<usize, Vec<u8>>(3))).unwrap(),
+                funder: PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(4).as_slice()).unwrap(),
+                term: r.get_unwrap(5)
+            }))?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    /// publisher of content
+    pub fn read_content_publisher(&self, digest: &sha256::Hash) -> Result<Option<PublicKey>, Error> {
+        Ok(self.tx.query_row(r#"
+            select publisher from content where id = ?1
+        "#, &[digest.to_hex()], |r| Ok(PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(0).as_slice()).unwrap())).optional()?)
+    }
+
+    /// last block of co-funding of content by a funder
+    pub fn read_cofunding_expiry(&self, digest: &sha256::Hash, funder: &PublicKey) -> Result<Option<u32>, Error> {
+        Ok(self.tx.query_row(r#"
+            select height + term from cofunding where id = ?1 and funder = ?2
+        "#, &[&digest.to_hex() as &dyn ToSql, &funder.to_bytes()], |r| Ok(r.get_unwrap::<usize, u32>(0))).optional()?)
+    }
+
+    // weight of content is the sum of its funding and co-funding per length
+    fn update_weight(&mut self, id: &str) -> Result<(), Error> {
+        self.tx.execute(r#"
+            update content set weight = (amount + coalesce((select sum(f.amount) from cofunding f where f.id = content.id), 0)) / length
+            where id = ?1
+        "#, &[&id as &dyn ToSql])?;
+        Ok(())
+    }
+
+    // co-funding with the latest expiry takes the place of the funding of content matching the condition,
+    // co-funding itself matching the condition should be deleted before, returns the number of content promoted
+    fn promote_cofunding(&mut self, condition: &str, param: &dyn ToSql) -> Result<usize, Error> {
+        let ids = {
+            let mut statement = self.tx.prepare(format!(r#"
+                select id from content where {} and id in (select id from cofunding)
+            "#, condition).as_str())?;
+            let ids = statement.query_map(&[param], |r| Ok(r.get_unwrap::<usize, String>(0)))?
+                .filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+                .collect::<Vec<_>>();
+            ids
+        };
+        let promoted = ids.len();
+        for id in ids {
+            debug!("co-funding takes the place of funding of content {}", id);
+            let funder = self.tx.query_row(r#"
+                select funder from cofunding where id = ?1 order by height + term desc limit 1
+            "#, &[&id as &dyn ToSql], |r| Ok(r.get_unwrap::<usize, Vec<u8>>(0)))?;
+            self.tx.execute(r#"
+                update content set (block_id, height, proof, publisher, term, amount, key) =
+                    (select block_id, height, proof, funder, term, amount, key from cofunding where id = ?1 and funder = ?2)
+                where id = ?1
+            "#, &[&id as &dyn ToSql, &funder])?;
+            self.tx.execute(r#"
+                delete from cofunding where id = ?1 and funder = ?2
+            "#, &[&id as &dyn ToSql, &funder])?;
+            self.update_weight(&id)?;
+        }
+        Ok(promoted)
     }
 
     pub fn read_content(&self, digest: &sha256::Hash) -> Result<Option<Content>, Error> {
@@ -519,63 +1139,253 @@ impl<'db> TX<'db> {
             select cat, abs, ad, proof, publisher, term
             from content where id = ?1
         "#, &[digest.to_hex()], |r| Ok(
-            Some(Content {
+            Content {
                 ad: Ad::new(r.get_unwrap(0), r.get_unwrap(1), r.get_unwrap::<usize, String>(2).as_str()),
                 funding: serde_cbor::from_reader(std::io::Cursor::new(r.get_unwrap::<usize, Vec<u8>>(3))).unwrap(),
                 funder: PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(4).as_slice()).unwrap(),
                 term: r.get_unwrap(5)
-            })
-        ))?)
+            }
+        )).optional()?)
+    }
+
+    /// all content followed by all co-funding of it, as content
+    pub fn read_all_contents(&self) -> Result<Vec<Content>, Error> {
+        let mut result = Vec::new();
+        for query in &[r#"
+            select cat, abs, ad, proof, publisher, term
+            from content order by height
+        "#, r#"
+            select c.cat, c.abs, c.ad, f.proof, f.funder, f.term
+            from cofunding f join content c on f.id = c.id order by f.height
+        "#] {
+            let mut statement = self.tx.prepare(query)?;
+            result.extend(statement.query_map(NO_PARAMS, |r| Ok(
+                Content {
+                    ad: Ad::new(r.get_unwrap(0), r.get_unwrap(1), r.get_unwrap::<usize, String>(2).as_str()),
+                    funding: serde_cbor::from_reader(std::io::Cursor::new(r.get_unwrap::<usize, Vec<u8>>(3))).unwrap(),
+                    funder: PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(4).as_slice()).unwrap(),
+                    term: r.get_unwrap(5)
+                }))?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None}));
+        }
+        Ok(result)
+    }
+
+    pub fn delete_content(&mut self, digest: &sha256::Hash) -> Result<Option<DeletedContent>, Error> {
+        let id = digest.to_hex();
+        let deleted = self.tx.query_row(r#"
+            select cat, abs from content where id = ?1
+        "#, &[&id as &dyn ToSql], |r| Ok(
+            DeletedContent {
+                key: ContentKey::new(&digest[..]),
+                id: id.clone(),
+                cat: r.get_unwrap(0),
+                abs: r.get_unwrap(1)
+            })).optional()?;
+        if deleted.is_some() {
+            debug!("drop content {}", id);
+            self.tx.execute(r#"
+                delete from content where id = ?1
+            "#, &[&id as &dyn ToSql])?;
+            self.tx.execute(r#"
+                delete from content_search where id = ?1
+            "#, &[&id as &dyn ToSql])?;
+            self.tx.execute(r#"
+                delete from hidden where id = ?1
+            "#, &[&id as &dyn ToSql])?;
+            self.tx.execute(r#"
+                delete from cofunding where id = ?1
+            "#, &[&id as &dyn ToSql])?;
+        }
+        Ok(deleted)
+    }
+
+    /// id, publisher, category and abstract of all content, to evaluate policy
+    pub fn list_policy_subjects(&self) -> Result<Vec<(sha256::Hash, PublicKey, String, String)>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select id, publisher, cat, abs from content
+        "#)?;
+        let mut result = Vec::new();
+        for r in statement.query_map(NO_PARAMS, |r| {
+            Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, Vec<u8>>(1),
+                r.get_unwrap::<usize, String>(2), r.get_unwrap::<usize, String>(3)))
+        })? {
+            let (id, publisher, cat, abs) = r?;
+            result.push((sha256::Hash::from_hex(id.as_str())?,
+                         PublicKey::from_slice(publisher.as_slice()).expect("can not deserialize stored publisher"), cat, abs));
+        }
+        Ok(result)
+    }
+
+    pub fn store_hidden(&mut self, digest: &sha256::Hash) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            insert or replace into hidden (id) values (?1)
+        "#, &[&digest.to_hex() as &dyn ToSql])?)
+    }
+
+    pub fn clear_hidden(&mut self) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            delete from hidden
+        "#, NO_PARAMS)?)
+    }
+
+    pub fn store_tombstone(&mut self, digest: &sha256::Hash, funder: &PublicKey, signature: &[u8], expiry: u32) -> Result<usize, Error> {
+        Ok(self.tx.execute(r#"
+            insert or replace into tombstone (id, funder, signature, expiry, key) values (?1, ?2, ?3, ?4, ?5)
+        "#, &[&digest.to_hex() as &dyn ToSql, &funder.to_bytes(), &signature.to_vec(), &expiry, &key_hex(&ContentKey::funding(digest, funder))])?)
+    }
+
+    pub fn read_tombstone(&self, digest: &sha256::Hash, funder: &PublicKey) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.tx.query_row(r#"
+            select signature from tombstone where id = ?1 and funder = ?2
+        "#, &[&digest.to_hex() as &dyn ToSql, &funder.to_bytes()], |r| Ok(r.get_unwrap::<usize, Vec<u8>>(0))).optional()?)
+    }
+
+    /// id and signature of revocations of content or of its funding by its key
+    pub fn read_tombstones(&self, key: &ContentKey) -> Result<Vec<(sha256::Hash, Vec<u8>)>, Error> {
+        // the key of content is its digest
+        let mut statement = self.tx.prepare(r#"
+            select id, signature from tombstone where id = ?1 or key = ?1
+        "#)?;
+        let result = statement.query_map(&[&key_hex(key)], |r| Ok((
+                sha256::Hash::from_hex(r.get_unwrap::<usize, String>(0).as_str()).expect("stored id of tombstone not hex"),
+                r.get_unwrap::<usize, Vec<u8>>(1))))?
+            .filter_map(|r| r.ok())
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    /// funder and last block of funding and co-funding of content, the publisher first
+    pub fn read_content_funders(&self, digest: &sha256::Hash) -> Result<Vec<(PublicKey, u32)>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select publisher, height + term from content where id = ?1
+            union all select funder, height + term from cofunding where id = ?1
+        "#)?;
+        let result = statement.query_map(&[digest.to_hex()], |r| Ok((
+                PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(0).as_slice()).expect("stored funder of content not a pubkey"),
+                r.get_unwrap::<usize, u32>(1))))?
+            .filter_map(|r| r.ok())
+            .collect::<Vec<_>>();
+        Ok(result)
+    }
+
+    /// delete the funding of content by a funder, co-funding takes the place of a publisher's funding,
+    /// content without other funding is deleted and returned
+    pub fn delete_funding(&mut self, digest: &sha256::Hash, funder: &PublicKey) -> Result<Option<DeletedContent>, Error> {
+        let id = digest.to_hex();
+        if self.read_content_publisher(digest)? == Some(*funder) {
+            let promote = self.tx.query_row(r#"
+                select count(*) from cofunding where id = ?1
+            "#, &[&id as &dyn ToSql], |r| Ok(r.get_unwrap::<usize, i64>(0)))? > 0;
+            if promote {
+                // the condition matches the content, there is no co-funding by the publisher
+                self.promote_cofunding("id = ?1", &id)?;
+                return Ok(None);
+            }
+            return self.delete_content(digest);
+        }
+        self.tx.execute(r#"
+            delete from cofunding where id = ?1 and funder = ?2
+        "#, &[&id as &dyn ToSql, &funder.to_bytes()])?;
+        self.update_weight(&id)?;
+        Ok(None)
     }
 
-    pub fn truncate_content(&mut self, limit: u64) -> Result<Vec<ContentKey>, Error> {
+    pub fn read_content_expiry(&self, digest: &sha256::Hash) -> Result<Option<u32>, Error> {
+        Ok(self.tx.query_row(r#"
+            select height + term from content where id = ?1
+        "#, &[digest.to_hex()], |r| Ok(r.get_unwrap::<usize, u32>(0))).optional()?)
+    }
+
+    /// delete content of least weight exceeding the limit. Categories with a quota first fill their quota,
+    /// then compete with others for the shared remainder of the limit
+    pub fn truncate_content(&mut self, limit: u64, quotas: &HashMap<String, u64>) -> Result<Vec<DeletedContent>, Error> {
         let mut statement = self.tx.prepare(r#"
-            select id, length from content order by weight desc
+            select id, length, cat, abs from content order by weight desc
         "#)?;
 
+        let shared = limit.saturating_sub(quotas.values().sum());
+        let mut quota_used = HashMap::new();
         let mut to_delete = Vec::new();
         let mut size = 0u64;
         for result in statement.query_map(NO_PARAMS,
                                                 |r|
                                                     Ok((r.get_unwrap::<usize, String>(0),
-                                                        r.get_unwrap::<usize, i64>(1))))? {
-            if let Ok((id, length)) = result {
+                                                        r.get_unwrap::<usize, i64>(1),
+                                                        r.get_unwrap::<usize, String>(2),
+                                                        r.get_unwrap::<usize, String>(3))))? {
+            if let Ok((id, length, cat, abs)) = result {
+                if let Some(quota) = quotas.get(&cat) {
+                    let used = quota_used.entry(cat.clone()).or_insert(0u64);
+                    *used += length as u64;
+                    if *used <= *quota {
+                        continue;
+                    }
+                }
                 size += length as u64;
-                if size > limit {
-                    to_delete.push(id);
+                if size > shared {
+                    to_delete.push((id, cat, abs));
                 }
             }
         }
-        let mut keys = Vec::new();
-        for id in &to_delete {
-            keys.push(ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]));
+        let mut deleted = Vec::new();
+        for (id, cat, abs) in to_delete {
             debug!("drop content due to strorage limit {}", id);
             self.tx.execute(r#"
                 delete from content where id = ?1
-                            "#, &[id as &dyn ToSql])?;
+                            "#, &[&id as &dyn ToSql])?;
+            self.tx.execute(r#"
+                delete from content_search where id = ?1
+                            "#, &[&id as &dyn ToSql])?;
+            self.tx.execute(r#"
+                delete from hidden where id = ?1
+                            "#, &[&id as &dyn ToSql])?;
+            self.tx.execute(r#"
+                delete from cofunding where id = ?1
+                            "#, &[&id as &dyn ToSql])?;
+            deleted.push(DeletedContent { key: ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]), id, cat, abs });
         }
-        Ok(keys)
+        Ok(deleted)
     }
 
-    pub fn delete_expired(&mut self, height: u32) -> Result<Vec<ContentKey>, Error> {
-        let mut keys = Vec::new();
+    /// delete content and co-funding expired at height, returns deleted content and the number of
+    /// co-fundings expired or taking the place of expired funding
+    pub fn delete_expired(&mut self, height: u32) -> Result<(Vec<DeletedContent>, usize), Error> {
+        let mut deleted = Vec::new();
+        let ids = {
+            let mut statement = self.tx.prepare(r#"
+                select distinct id from cofunding where height + term <= ?1
+            "#)?;
+            let ids = statement.query_map(&[&height as &dyn ToSql], |r| Ok(r.get_unwrap::<usize, String>(0)))?
+                .filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+                .collect::<Vec<_>>();
+            ids
+        };
+        let mut changed = self.tx.execute(r#"
+            delete from cofunding where height + term <= ?1
+        "#, &[&height as &dyn ToSql])?;
+        for id in &ids {
+            self.update_weight(id)?;
+        }
+        changed += self.promote_cofunding("height + term <= ?1", &height)?;
         self.tx.execute(r#"
             create temp table ids (
-                id text
+                id text,
+                cat text,
+                abs text
             );
         "#, NO_PARAMS)?;
         self.tx.execute(r#"
-            insert into temp.ids (id) select id from content where height + term <= ?1
+            insert into temp.ids (id, cat, abs) select id, cat, abs from content where height + term <= ?1
         "#, &[&height as &dyn ToSql])?;
 
         let mut query = self.tx.prepare(r#"
-            select id from temp.ids
+            select id, cat, abs from temp.ids
         "#)?;
 
-        for r in query.query_map::<String,&[&dyn ToSql],_>(NO_PARAMS,
-                                                               |r| Ok(r.get(0)?))? {
-            if let Ok(id) = r {
-                keys.push(ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]));
+        for r in query.query_map::<(String, String, String),&[&dyn ToSql],_>(NO_PARAMS,
+                                                               |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))? {
+            if let Ok((id, cat, abs)) = r {
+                deleted.push(DeletedContent { key: ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]), id, cat, abs });
             }
         }
 
@@ -593,31 +1403,60 @@ impl<'db> TX<'db> {
 
         self.tx.execute_batch(r#"
             delete from content where id in (select id from temp.ids);
+            delete from content_search where id in (select id from temp.ids);
+            delete from hidden where id in (select id from temp.ids);
+            delete from cofunding where id in (select id from temp.ids);
             drop table temp.ids;
         "#)?;
+        self.tx.execute(r#"
+            delete from tombstone where expiry <= ?1
+        "#, &[&height as &dyn ToSql])?;
+        self.tx.execute(r#"
+            delete from commitment where expiry <= ?1
+        "#, &[&height as &dyn ToSql])?;
 
-        Ok(keys)
+        Ok((deleted, changed))
     }
 
-    pub fn delete_confirmed(&mut self, block_id: &sha256d::Hash) -> Result<Vec<ContentKey>, Error> {
-        let mut keys = Vec::new();
+    /// delete content and co-funding confirmed in the block, returns deleted content and the number of
+    /// co-fundings deleted or taking the place of deleted funding
+    pub fn delete_confirmed(&mut self, block_id: &sha256d::Hash) -> Result<(Vec<DeletedContent>, usize), Error> {
+        let mut deleted = Vec::new();
+        let ids = {
+            let mut statement = self.tx.prepare(r#"
+                select distinct id from cofunding where block_id = ?1
+            "#)?;
+            let ids = statement.query_map(&[&block_id.to_hex() as &dyn ToSql], |r| Ok(r.get_unwrap::<usize, String>(0)))?
+                .filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+                .collect::<Vec<_>>();
+            ids
+        };
+        let mut changed = self.tx.execute(r#"
+            delete from cofunding where block_id = ?1
+        "#, &[&block_id.to_hex() as &dyn ToSql])?;
+        for id in &ids {
+            self.update_weight(id)?;
+        }
+        changed += self.promote_cofunding("block_id = ?1", &block_id.to_hex())?;
         self.tx.execute(r#"
             create temp table ids (
-                id text
+                id text,
+                cat text,
+                abs text
             );
         "#, NO_PARAMS)?;
         self.tx.execute(r#"
-            insert into temp.ids (id) select id from content where block_id = ?1
+            insert into temp.ids (id, cat, abs) select id, cat, abs from content where block_id = ?1
         "#, &[&block_id.to_hex() as &dyn ToSql])?;
 
         let mut query = self.tx.prepare(r#"
-            select id from temp.ids
+            select id, cat, abs from temp.ids
         "#)?;
 
-        for r in query.query_map::<String,&[&dyn ToSql],_>(NO_PARAMS,
-                                                              |r| Ok(r.get(0)?))? {
-            if let Ok(id) = r {
-                keys.push(ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]));
+        for r in query.query_map::<(String, String, String),&[&dyn ToSql],_>(NO_PARAMS,
+                                                              |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))? {
+            if let Ok((id, cat, abs)) = r {
+                deleted.push(DeletedContent { key: ContentKey::new(&sha256::Hash::from_hex(id.as_str())?[..]), id, cat, abs });
             }
         }
 
@@ -635,10 +1474,13 @@ impl<'db> TX<'db> {
 
         self.tx.execute_batch(r#"
             delete from content where id in (select id from temp.ids);
+            delete from content_search where id in (select id from temp.ids);
+            delete from hidden where id in (select id from temp.ids);
+            delete from cofunding where id in (select id from temp.ids);
             drop table temp.ids;
         "#)?;
 
-        Ok(keys)
+        Ok((deleted, changed))
     }
 
     pub fn store_address(&mut self, network: &str, address: &SocketAddr, mut connected: u64, mut last_seen: u64, mut banned: u64) -> Result<usize, Error>  {
@@ -726,7 +1568,7 @@ impl<'db> TX<'db> {
 
     pub fn list_categories(&mut self) -> Result<Vec<String>, Error> {
         let mut statement = self.tx.prepare(r#"
-            select distinct cat from content order by cat
+            select distinct cat from content where id not in (select id from hidden) order by cat
         "#)?;
 
         let result = statement.query_map(NO_PARAMS, |r| {
@@ -737,7 +1579,7 @@ impl<'db> TX<'db> {
         Ok(result)
     }
 
-    pub fn list_abstracts(&mut self, cats: Vec<String>) -> Result<Vec<Vec<String>>, Error> {
+    pub fn list_abstracts(&mut self, cats: Vec<String>) -> Result<Vec<ListedAbstract>, Error> {
         // mut &self because using temp table
         self.tx.execute(r#"
             create temp table cats (
@@ -750,12 +1592,13 @@ impl<'db> TX<'db> {
             "#, &[c as &dyn ToSql])?;
         }
         let mut statement = self.tx.prepare(r#"
-            select id, cat, abs from content where cat in (select cat from temp.cats) order by cat, weight desc
+            select id, cat, abs from content where cat in (select cat from temp.cats) and id not in (select id from hidden)
+            order by cat, weight desc
         "#)?;
 
         let result = statement.query_map(NO_PARAMS, |r| {
             Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, String>(1), r.get_unwrap::<usize, String>(2)))
-        })?.filter_map(|r| if let Ok((i,c, a)) = r { Some(vec![i,c,a]) } else {None})
+        })?.filter_map(|r| if let Ok((id, cat, abs)) = r { Some(ListedAbstract { id, cat, abs }) } else {None})
             .collect::<Vec<_>>();
 
         self.tx.execute(r#"
@@ -765,6 +1608,142 @@ impl<'db> TX<'db> {
         Ok(result)
     }
 
+    pub fn search(&self, keywords: Vec<String>) -> Result<Vec<ListedAbstract>, Error> {
+        // quote each keyword so user input is not interpreted as fts query syntax
+        let query = keywords.iter()
+            .map(|k| format!("\"{}\"", k.replace("\"", "\"\"")))
+            .collect::<Vec<_>>().join(" OR ");
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut statement = self.tx.prepare(r#"
+            select content.id, content.cat, content.abs from content_search
+            join content on content.id = content_search.id
+            where content_search match ?1 and content.id not in (select id from hidden)
+            order by content_search.rank, content.weight desc
+        "#)?;
+
+        let result = statement.query_map(&[&query as &dyn ToSql], |r| {
+            Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, String>(1), r.get_unwrap::<usize, String>(2)))
+        })?.filter_map(|r| if let Ok((id, cat, abs)) = r { Some(ListedAbstract { id, cat, abs }) } else {None})
+            .collect::<Vec<_>>();
+
+        Ok(result)
+    }
+
+    pub fn list_by_publisher(&self, publisher: &PublicKey) -> Result<Vec<ListedAbstract>, Error> {
+        let mut statement = self.tx.prepare(r#"
+            select id, cat, abs from content where publisher = ?1 and id not in (select id from hidden) order by cat, weight desc
+        "#)?;
+
+        let result = statement.query_map(&[&publisher.to_bytes() as &dyn ToSql], |r| {
+            Ok((r.get_unwrap::<usize, String>(0), r.get_unwrap::<usize, String>(1), r.get_unwrap::<usize, String>(2)))
+        })?.filter_map(|r| if let Ok((id, cat, abs)) = r { Some(ListedAbstract { id, cat, abs }) } else {None})
+            .collect::<Vec<_>>();
+
+        Ok(result)
+    }
+
+    pub fn publisher_stats(&self, publisher: &PublicKey) -> Result<PublisherStats, Error> {
+        Ok(self.tx.query_row(r#"
+            select coalesce(sum(amount), 0), count(*), min(height) from content where publisher = ?1
+        "#, &[&publisher.to_bytes() as &dyn ToSql], |r| Ok(
+            PublisherStats {
+                publisher: publisher.to_string(),
+                amount: r.get_unwrap::<usize, i64>(0) as u64,
+                ads: r.get_unwrap::<usize, u32>(1),
+                earliest: r.get_unwrap::<usize, Option<u32>>(2)
+            }))?)
+    }
+
+    pub fn list_categories_page(&self, page: &Page) -> Result<Paged<String>, Error> {
+        let total = self.tx.query_row(r#"
+            select count(distinct cat) from content where id not in (select id from hidden)
+        "#, NO_PARAMS, |r| Ok(r.get_unwrap::<usize, u32>(0)))?;
+
+        let mut statement = self.tx.prepare(format!(r#"
+            select distinct cat from content where id not in (select id from hidden) and (?1 is null or cat {} ?1)
+            order by cat {} limit ?2
+        "#, if page.ascending { ">" } else { "<" }, if page.ascending { "asc" } else { "desc" }).as_str())?;
+
+        let items = statement.query_map(&[&page.cursor as &dyn ToSql, &page.limit], |r| {
+            Ok(r.get_unwrap::<usize, String>(0))
+        })?.filter_map(|r| if let Ok(c) = r { Some(c) } else {None})
+            .collect::<Vec<_>>();
+
+        let next = if items.len() as u32 == page.limit { items.last().cloned() } else { None };
+        Ok(Paged { total, next, items })
+    }
+
+    pub fn retrieve_contents_page(&mut self, selection: Selection, page: &Page) -> Result<Paged<RetrievedContent>, Error> {
+        let cursor = if let Some(ref c) = page.cursor { Some(Page::content_cursor(c.as_str())?) } else { None };
+        // mut &self because using temp table
+        self.tx.execute(r#"
+            create temp table selection (
+                v text
+            );
+        "#, NO_PARAMS)?;
+        let filter = match selection {
+            Selection::All => "1",
+            Selection::Categories(cats) => {
+                for c in &cats {
+                    self.tx.execute(r#"
+                        insert into temp.selection (v) values (?1)
+                    "#, &[c as &dyn ToSql])?;
+                }
+                "cat in (select v from temp.selection)"
+            },
+            Selection::Ids(ids) => {
+                for id in &ids {
+                    self.tx.execute(r#"
+                        insert into temp.selection (v) values (?1)
+                    "#, &[id as &dyn ToSql])?;
+                }
+                "id in (select v from temp.selection)"
+            }
+        };
+
+        let total = self.tx.query_row(format!(r#"
+            select count(*) from content where {} and id not in (select id from hidden)
+        "#, filter).as_str(), NO_PARAMS, |r| Ok(r.get_unwrap::<usize, u32>(0)))?;
+
+        let (key, (cmp, order)) = (page.sort.column(), if page.ascending { (">", "asc") } else { ("<", "desc") });
+        let mut statement = self.tx.prepare(format!(r#"
+            select id, cat, abs, ad, publisher, height, term, length, weight from content
+            where {} and id not in (select id from hidden) and (?1 is null or {} {} ?1 or ({} = ?1 and id {} ?2))
+            order by {} {}, id {} limit ?3
+        "#, filter, key, cmp, key, cmp, key, order, order).as_str())?;
+
+        let (value, id) = match cursor {
+            Some((value, id)) => (Some(value), Some(id)),
+            None => (None, None)
+        };
+        let items = statement.query_map(&[&value as &dyn ToSql, &id, &page.limit], |r| {
+            Ok(RetrievedContent{
+                id: r.get_unwrap::<usize, String>(0),
+                cat: r.get_unwrap::<usize, String>(1),
+                abs: r.get_unwrap::<usize, String>(2),
+                text: r.get_unwrap::<usize, String>(3),
+                publisher: PublicKey::from_slice(r.get_unwrap::<usize, Vec<u8>>(4).as_slice()).unwrap().to_string(),
+                height: r.get_unwrap::<usize, u32>(5),
+                term: r.get_unwrap::<usize, u16>(6),
+                length: r.get_unwrap::<usize, u32>(7),
+                weight: r.get_unwrap::<usize, u32>(8)
+            })
+        })?.filter_map(|r| if let Ok(r) = r { Some(r) } else {None})
+            .collect::<Vec<_>>();
+        drop(statement);
+
+        self.tx.execute(r#"
+            drop table temp.selection
+        "#, NO_PARAMS)?;
+
+        let next = if items.len() as u32 == page.limit {
+            items.last().map(|c| format!("{}:{}", page.sort.value(c), c.id))
+        } else { None };
+        Ok(Paged { total, next, items })
+    }
+
     pub fn retrieve_contents(&mut self, ids: Vec<String>) -> Result<Vec<RetrievedContent>, Error> {
         // mut &self because using temp table
         self.tx.execute(r#"
@@ -779,7 +1758,8 @@ impl<'db> TX<'db> {
         }
 
         let mut statement = self.tx.prepare(r#"
-            select id, cat, abs, ad, publisher, height, term, length, weight from content where id in (select id from temp.ids) order by weight desc
+            select id, cat, abs, ad, publisher, height, term, length, weight from content
+            where id in (select id from temp.ids) and id not in (select id from hidden) order by weight desc
         "#)?;
 
         let result = statement.query_map(NO_PARAMS, |r| {
@@ -804,6 +1784,110 @@ impl<'db> TX<'db> {
     }
 }
 
+/// key to order paged content by
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Weight,
+    Height,
+    /// remaining term, that is the height funding expires
+    Term,
+    Length
+}
+
+impl SortKey {
+    fn column(self) -> &'static str {
+        match self {
+            SortKey::Weight => "weight",
+            SortKey::Height => "height",
+            SortKey::Term => "height + term",
+            SortKey::Length => "length"
+        }
+    }
+
+    fn value(self, c: &RetrievedContent) -> i64 {
+        match self {
+            SortKey::Weight => c.weight as i64,
+            SortKey::Height => c.height as i64,
+            SortKey::Term => c.height as i64 + c.term as i64,
+            SortKey::Length => c.length as i64
+        }
+    }
+}
+
+/// selects a page of a listing
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub sort: SortKey,
+    pub ascending: bool,
+    /// next of a previous page, start with the first page if None
+    pub cursor: Option<String>,
+    pub limit: u32
+}
+
+impl Default for Page {
+    fn default() -> Page {
+        Page { sort: SortKey::Weight, ascending: false, cursor: None, limit: 100 }
+    }
+}
+
+impl Page {
+    /// split a content cursor into sort key value and id
+    pub fn content_cursor(cursor: &str) -> Result<(i64, String), Error> {
+        let mut parts = cursor.splitn(2, ':');
+        if let (Some(value), Some(id)) = (parts.next(), parts.next()) {
+            if let Ok(value) = i64::from_str(value) {
+                return Ok((value, id.to_string()));
+            }
+        }
+        Err(Error::IO(std::io::Error::from(std::io::ErrorKind::InvalidInput)))
+    }
+}
+
+/// a page of a listing
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Paged<T> {
+    /// number of items in all pages
+    pub total: u32,
+    /// cursor to the next page, None if this is the last
+    pub next: Option<String>,
+    pub items: Vec<T>
+}
+
+/// content to page through
+pub enum Selection {
+    All,
+    Categories(Vec<String>),
+    Ids(Vec<String>)
+}
+
+/// id, category and abstract of listed content
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ListedAbstract {
+    pub id: String,
+    pub cat: String,
+    pub abs: String
+}
+
+/// content removed from the store
+pub struct DeletedContent {
+    pub key: ContentKey,
+    pub id: String,
+    pub cat: String,
+    pub abs: String
+}
+
+/// summary of content funded by a publisher key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PublisherStats {
+    pub publisher: String,
+    /// total amount locked in satoshis
+    pub amount: u64,
+    /// number of ads stored
+    pub ads: u32,
+    /// lowest height of funding
+    pub earliest: Option<u32>
+}
 
 pub struct RetrievedContent {
     pub id: String,
@@ -817,6 +1901,11 @@ pub struct RetrievedContent {
     pub weight: u32
 }
 
+// keys of content are stored as hex
+fn key_hex(key: &ContentKey) -> String {
+    hex::encode(&key.digest[..])
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -899,16 +1988,121 @@ mod test {
                 term: 1
             };
             tx.store_content(0, &block.bitcoin_hash(), &content, 5000000000).unwrap();
+            assert_eq!(tx.read_content_expiry(&ad.digest()).unwrap(), Some(1));
+            assert_eq!(tx.list_by_publisher(&satoshi_key).unwrap().len(), 1);
+            let stats = tx.publisher_stats(&satoshi_key).unwrap();
+            assert_eq!((stats.amount, stats.ads, stats.earliest), (5000000000, 1, Some(0)));
+            tx.store_tombstone(&ad.digest(), &satoshi_key, &[1u8, 2u8], 1).unwrap();
+            assert_eq!(tx.read_tombstone(&ad.digest(), &satoshi_key).unwrap(), Some(vec!(1u8, 2u8)));
+            assert_eq!(tx.read_tombstones(&ContentKey::funding(&ad.digest(), &satoshi_key)).unwrap(), vec!((ad.digest(), vec!(1u8, 2u8))));
+            assert_eq!(tx.delete_content(&ad.digest()).unwrap().unwrap().cat, "a".to_string());
+            assert!(tx.read_content(&ad.digest()).unwrap().is_none());
+            tx.store_content(0, &block.bitcoin_hash(), &content, 5000000000).unwrap();
+            assert_eq!(tx.search(vec!("c".to_string())).unwrap(), vec!(ListedAbstract { id: ad.digest().to_hex(), cat: "a".to_string(), abs: "b".to_string() }));
+            assert!(tx.search(vec!("d".to_string())).unwrap().is_empty());
+            let secp = secp256k1::Secp256k1::new();
+            let cofunder = PublicKey { compressed: true, key: secp256k1::PublicKey::from_secret_key(&secp, &secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap()) };
+            tx.store_cofunding(1, &block.bitcoin_hash(), &Content { funder: cofunder, term: 3, .. content.clone() }, 5000000000).unwrap();
+            assert_eq!(tx.read_cofunding_expiry(&ad.digest(), &cofunder).unwrap(), Some(4));
+            assert_eq!(tx.read_cofundings(&ad.digest()).unwrap()[0].funder, cofunder);
+            assert_eq!(tx.read_funding(&ContentKey::funding(&ad.digest(), &cofunder)).unwrap().unwrap().funder, cofunder);
+            assert_eq!(tx.read_content_funders(&ad.digest()).unwrap(), vec!((satoshi_key, 1), (cofunder, 4)));
+            assert_eq!(tx.read_all_contents().unwrap().iter().map(|c| c.funder).collect::<Vec<_>>(), vec!(satoshi_key, cofunder));
+            let weight = |tx: &mut TX| tx.retrieve_contents_page(Selection::All, &Page::default()).unwrap().items[0].weight;
+            assert_eq!(weight(&mut tx), (10000000000 / content.length() as u64) as u32);
+            // co-funding takes the place of expired funding
+            let (deleted, changed) = tx.delete_expired(1).unwrap();
+            assert_eq!((deleted.is_empty(), changed), (true, 1));
+            assert_eq!(tx.read_content_publisher(&ad.digest()).unwrap(), Some(cofunder));
+            assert_eq!(tx.read_content_expiry(&ad.digest()).unwrap(), Some(4));
+            assert!(tx.read_cofundings(&ad.digest()).unwrap().is_empty());
+            assert_eq!(weight(&mut tx), (5000000000 / content.length() as u64) as u32);
+            tx.store_content(0, &block.bitcoin_hash(), &content, 5000000000).unwrap();
+            assert_eq!(tx.list_policy_subjects().unwrap(), vec!((ad.digest(), satoshi_key, "a".to_string(), "b".to_string())));
+            tx.store_hidden(&ad.digest()).unwrap();
+            assert!(tx.search(vec!("c".to_string())).unwrap().is_empty());
+            assert!(tx.list_categories().unwrap().is_empty());
+            tx.clear_hidden().unwrap();
+            let other = Content{ ad: Ad::new("b".to_string(), "b".to_string(), "d"), .. content.clone() };
+            tx.store_content(0, &block.bitcoin_hash(), &other, 6000000000).unwrap();
+            let page = Page { limit: 1, .. Page::default() };
+            let first = tx.retrieve_contents_page(Selection::All, &page).unwrap();
+            assert_eq!((first.total, first.items[0].cat.as_str()), (2, "b"));
+            let second = tx.retrieve_contents_page(Selection::All, &Page { cursor: first.next, .. page.clone() }).unwrap();
+            assert_eq!(second.items[0].cat.as_str(), "a");
+            assert!(tx.retrieve_contents_page(Selection::All, &Page { cursor: second.next, .. page.clone() }).unwrap().items.is_empty());
+            assert_eq!(tx.retrieve_contents_page(Selection::Categories(vec!("a".to_string())), &page).unwrap().total, 1);
+            let categories = tx.list_categories_page(&Page { ascending: true, .. page.clone() }).unwrap();
+            assert_eq!((categories.total, categories.items, categories.next), (2, vec!("a".to_string()), Some("a".to_string())));
+            let limit = (content.length() + other.length() - 1) as u64;
+            let mut quotas = HashMap::new();
+            quotas.insert("a".to_string(), content.length() as u64);
+            assert_eq!(tx.truncate_content(limit, &quotas).unwrap()[0].cat, "b".to_string());
             tx.delete_confirmed(&block.bitcoin_hash()).unwrap();
             tx.delete_expired(1).unwrap();
-            tx.truncate_content(1024).unwrap();
+            assert!(tx.read_tombstone(&ad.digest(), &satoshi_key).unwrap().is_none());
+            tx.truncate_content(1024, &HashMap::new()).unwrap();
+            assert!(tx.read_content_expiry(&ad.digest()).unwrap().is_none());
+            assert!(tx.search(vec!("c".to_string())).unwrap().is_empty());
 
             assert!(tx.read_processed().unwrap().is_none());
             tx.store_processed(&sha256d::Hash::default()).unwrap();
             tx.store_processed(&block.bitcoin_hash()).unwrap();
             assert_eq!(tx.read_processed().unwrap().unwrap(), block.bitcoin_hash());
             assert_eq!(tx.read_seed().unwrap(), tx.read_seed().unwrap());
-            tx.store_txout(&block.txdata[0], None).unwrap();
+            tx.store_txout(&block.txdata[0], None, &[]).unwrap();
+            let (stored, funding, spent) = tx.read_unconfirmed_txout(&block.txdata[0].txid()).unwrap().unwrap();
+            assert_eq!((stored, funding, spent), (block.txdata[0].clone(), None, Some(Vec::new())));
+            assert_eq!(tx.delete_txout(&block.txdata[0].txid()).unwrap(), 1);
+            assert!(tx.read_unconfirmed_txout(&block.txdata[0].txid()).unwrap().is_none());
+            tx.store_txout(&block.txdata[0], None, &[]).unwrap();
+            tx.confirm_txout(&block.txdata[0], &block.bitcoin_hash()).unwrap();
+            assert!(tx.read_unconfirmed_txout(&block.txdata[0].txid()).unwrap().is_none());
+            assert_eq!(tx.read_txouts().unwrap(), vec!(StoredTxOut { transaction: block.txdata[0].clone(), confirmed: Some(block.bitcoin_hash()), funding: None, spent: Some(Vec::new()) }));
+            // received is not an outgoing transaction
+            let received = bitcoin::Transaction { lock_time: 1, .. block.txdata[0].clone() };
+            tx.confirm_txout(&received, &block.bitcoin_hash()).unwrap();
+            assert_eq!(tx.read_txouts().unwrap()[1], StoredTxOut { transaction: received.clone(), confirmed: Some(block.bitcoin_hash()), funding: None, spent: Some(Vec::new()) });
+            assert_eq!(tx.delete_txout(&received.txid()).unwrap(), 0);
+            let point = OutPoint { txid: block.txdata[0].txid(), vout: 0 };
+            assert_eq!(tx.store_locked(&point).unwrap(), 1);
+            assert_eq!(tx.read_locked().unwrap(), vec!(point));
+            assert_eq!(tx.delete_locked(&point).unwrap(), 1);
+            assert!(tx.read_locked().unwrap().is_empty());
+            let offer = Offer { id: "offer".to_string(), amount: 100000, term: 1008, price: 1000 };
+            tx.store_offer(&offer).unwrap();
+            assert_eq!(tx.read_offers().unwrap(), vec!(offer));
+            assert_eq!(tx.delete_offer("offer").unwrap(), 1);
+            assert!(tx.read_offers().unwrap().is_empty());
+            let mut rental = Rental { id: "ad".to_string(), offer: "offer".to_string(), address: "address".to_string(), price: 1000, amount: 100000, term: 1008, expiry: 144, paid: None, block: None, funded: None, stalled: false };
+            tx.store_rental(&rental).unwrap();
+            rental.paid = Some("txid".to_string());
+            rental.block = Some(block.bitcoin_hash().to_hex());
+            tx.store_rental(&rental).unwrap();
+            assert_eq!(tx.read_rentals().unwrap(), vec!(rental.clone()));
+            assert_eq!(tx.delete_unpaid_rentals(144).unwrap(), 0);
+            assert_eq!(tx.unconfirm_rentals(&block.bitcoin_hash(), 145).unwrap(), 1);
+            assert_eq!(tx.read_rentals().unwrap(), vec!(Rental { paid: None, block: None, expiry: 145, .. rental }));
+            assert_eq!(tx.delete_unpaid_rentals(144).unwrap(), 0);
+            assert_eq!(tx.delete_unpaid_rentals(145).unwrap(), 1);
+            assert!(tx.read_rentals().unwrap().is_empty());
+            let commitment = Script::from(vec!(0u8; 34));
+            tx.store_commitment(&commitment, &sha256::Hash::default(), &satoshi_key, 1008, 10).unwrap();
+            assert_eq!(tx.read_commitments().unwrap(), vec!((commitment.clone(), sha256::Hash::default(), satoshi_key, 1008)));
+            assert_eq!(tx.confirm_commitment(&commitment, &sha256d::Hash::default(), 5).unwrap(), 1);
+            assert!(tx.read_commitments().unwrap().is_empty());
+            assert_eq!(tx.unconfirm_commitments(&sha256d::Hash::default()).unwrap(), 1);
+            assert_eq!(tx.read_commitments().unwrap().len(), 1);
+            tx.delete_expired(10).unwrap();
+            assert!(tx.read_commitments().unwrap().is_empty());
+            let mut unsigned = block.txdata[0].clone();
+            unsigned.input[0].script_sig = Script::new();
+            unsigned.input[0].witness.clear();
+            let psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned.clone()).unwrap();
+            tx.store_psbt(&psbt, None).unwrap();
+            assert_eq!(tx.read_psbt(&unsigned.txid()).unwrap(), Some((psbt, None)));
+            assert_eq!(tx.delete_psbt(&unsigned.txid()).unwrap(), 1);
+            assert!(tx.read_psbt(&unsigned.txid()).unwrap().is_none());
             tx.rescan(&block.header.bitcoin_hash()).unwrap();
             let ad = Ad::new("cat".to_string(), "abs".to_string(), "content");
             tx.prepare_publication(&ad).unwrap();
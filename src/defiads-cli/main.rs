@@ -0,0 +1,296 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! command line client of a running defiads node
+
+#[macro_use]extern crate serde_derive;
+extern crate clap;
+extern crate toml;
+use clap::{Arg, App, ArgMatches, SubCommand};
+
+use bitcoin::Address;
+use bitcoin::network::constants::Network;
+use bitcoin_hashes::sha256;
+use defiads::client::{tls_client_config, Client, Endpoint, Fee, Sent};
+use defiads::error::Error;
+use defiads::find_peers::BIADNET_PORT;
+use serde::Serialize;
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+const HTTP_RPC: &str = "127.0.0.1";
+const DEFAULT_TARGET: &str = "6";
+const TLS_DOMAIN: &str = "localhost";
+
+// the part of the node's config the client needs
+#[derive(Deserialize)]
+struct Config {
+    apikey: String,
+    // empty if the wallet is watch-only
+    encryptedwalletkey: Option<String>
+}
+
+pub fn main () {
+    let http_rpc = (HTTP_RPC.to_string() + ":") + ((BIADNET_PORT + 1).to_string().as_str());
+
+    let fee_args = || vec!(
+        Arg::with_name("fee-per-vbyte")
+            .long("fee-per-vbyte")
+            .value_name("n")
+            .help("Fee in satoshi/vbyte")
+            .takes_value(true)
+            .conflicts_with("target"),
+        Arg::with_name("target")
+            .long("target")
+            .value_name("n")
+            .help("Fee estimated to confirm within n blocks")
+            .takes_value(true)
+            .default_value(DEFAULT_TARGET),
+        Arg::with_name("passphrase")
+            .long("passphrase")
+            .value_name("PASSPHRASE")
+            .help("Passphrase of the wallet, read from the terminal if not given, not needed for a watch-only wallet")
+            .takes_value(true));
+
+    let matches = App::new("defiads-cli").version("0.2.2").author("tamas.blummer@protonmail.com")
+        .about("Command line client of a running defiads node")
+        .arg(Arg::with_name("bitcoin-network")
+            .long("bitcoin-network")
+            .value_name("NETWORK")
+            .help("Bitcoin network of the node, selects its config file and default port")
+            .takes_value(true)
+            .possible_values(&["bitcoin", "testnet", "regtest"])
+            .default_value("bitcoin"))
+        .arg(Arg::with_name("http-rpc")
+            .long("http-rpc")
+            .value_name("ADDRESS")
+            .help("http-rpc address of the node, as given to the node")
+            .takes_value(true)
+            .default_value(http_rpc.as_str()))
+        .arg(Arg::with_name("rpc-unix-socket")
+            .long("rpc-unix-socket")
+            .value_name("PATH")
+            .help("Talk to the node on this unix domain socket instead of http-rpc")
+            .takes_value(true))
+        .arg(Arg::with_name("rpc-tls-ca")
+            .long("rpc-tls-ca")
+            .value_name("FILE")
+            .help("Talk to the node over TLS on http-rpc, trusting the certificates of this file (PEM), that is the node's certificate if self signed")
+            .takes_value(true)
+            .conflicts_with("rpc-unix-socket"))
+        .arg(Arg::with_name("rpc-tls-domain")
+            .long("rpc-tls-domain")
+            .value_name("NAME")
+            .help("Domain name of the node's TLS certificate")
+            .takes_value(true)
+            .default_value(TLS_DOMAIN))
+        .arg(Arg::with_name("token")
+            .long("token")
+            .value_name("TOKEN")
+            .help("Api token. Default: apikey of the node's defiads.cfg")
+            .takes_value(true))
+        .arg(Arg::with_name("json")
+            .long("json")
+            .help("Print answers as JSON")
+            .global(true)
+            .takes_value(false))
+        .subcommand(SubCommand::with_name("categories")
+            .about("List categories"))
+        .subcommand(SubCommand::with_name("list")
+            .about("List ids and abstracts of categories, of all if none given")
+            .arg(Arg::with_name("category").multiple(true)))
+        .subcommand(SubCommand::with_name("read")
+            .about("Read content")
+            .arg(Arg::with_name("id").required(true).multiple(true)))
+        .subcommand(SubCommand::with_name("prepare")
+            .about("Prepare a publication, print its id")
+            .arg(Arg::with_name("category").required(true))
+            .arg(Arg::with_name("abstract").required(true))
+            .arg(Arg::with_name("content").required(true)))
+        .subcommand(SubCommand::with_name("fund")
+            .about("Fund a prepared publication")
+            .arg(Arg::with_name("id").required(true))
+            .arg(Arg::with_name("amount").required(true).help("satoshis"))
+            .arg(Arg::with_name("term").required(true).help("blocks"))
+            .args(&fee_args()))
+        .subcommand(SubCommand::with_name("withdraw")
+            .about("Withdraw from the wallet, all available if no amount given")
+            .arg(Arg::with_name("address").required(true))
+            .arg(Arg::with_name("amount").help("satoshis"))
+            .args(&fee_args()))
+        .subcommand(SubCommand::with_name("balance")
+            .about("Balance of the wallet"))
+        .subcommand(SubCommand::with_name("deposit")
+            .about("Address to deposit to the wallet"))
+        .get_matches();
+
+    let bitcoin_network = matches.value_of("bitcoin-network").unwrap().parse::<Network>().unwrap();
+
+    let endpoint = if let Some(endpoint) = unix_endpoint(&matches) {
+        endpoint
+    }
+    else {
+        let mut sock = SocketAddr::from_str(matches.value_of("http-rpc").unwrap()).unwrap_or_else(|_| fail("invalid socket address"));
+        // as the node does
+        if bitcoin_network != Network::Bitcoin {
+            sock.set_port(sock.port() + 100);
+        }
+        if let Some(ca) = matches.value_of("rpc-tls-ca") {
+            let config = tls_client_config(Path::new(ca)).unwrap_or_else(|e| fail(format!("can not load {}: {}", ca, e).as_str()));
+            Endpoint::Tls(sock, matches.value_of("rpc-tls-domain").unwrap().to_string(), config)
+        }
+        else {
+            Endpoint::Http(sock)
+        }
+    };
+
+    let config = read_config(bitcoin_network);
+    let token = matches.value_of("token").map(|t| t.to_string()).unwrap_or_else(|| {
+        config.as_ref().unwrap_or_else(|e| fail(e.as_str())).apikey.clone()
+    });
+    // a node's watch-only wallet needs no passphrase
+    let watch_only = config.ok().and_then(|c| c.encryptedwalletkey).map_or(false, |k| k.is_empty());
+
+    let client = Client::new(endpoint, token.as_str());
+    let (command, sub) = matches.subcommand();
+    let sub = sub.unwrap_or_else(|| fail("expecting a command, see --help"));
+    let json = matches.is_present("json") || sub.is_present("json");
+    if let Err(e) = run(&client, command, sub, json, watch_only) {
+        fail(e.to_string().as_str());
+    }
+}
+
+fn read_config(bitcoin_network: Network) -> Result<Config, String> {
+    let mut config_path = dirs::home_dir().ok_or_else(|| "unknown home directory".to_string())?;
+    config_path.push(".defiads");
+    config_path.push(bitcoin_network.to_string());
+    config_path.push("defiads.cfg");
+    let config_string = fs::read_to_string(config_path.as_path())
+        .map_err(|_| format!("can not read config file {}, give --token", config_path.display()))?;
+    toml::from_str::<Config>(config_string.as_str()).map_err(|_| "can not parse config file".to_string())
+}
+
+#[cfg(unix)]
+fn unix_endpoint(matches: &ArgMatches) -> Option<Endpoint> {
+    matches.value_of("rpc-unix-socket").map(|path| Endpoint::Unix(PathBuf::from(path)))
+}
+
+#[cfg(not(unix))]
+fn unix_endpoint(matches: &ArgMatches) -> Option<Endpoint> {
+    if matches.is_present("rpc-unix-socket") {
+        fail("unix domain sockets are not supported on this platform");
+    }
+    None
+}
+
+fn run(client: &Client, command: &str, sub: &ArgMatches, json: bool, watch_only: bool) -> Result<(), Error> {
+    match command {
+        "categories" => {
+            let categories = client.categories()?;
+            print(json, &categories, || categories.iter().for_each(|c| println!("{}", c)));
+        },
+        "list" => {
+            let categories = sub.values_of("category").unwrap_or_default().map(|c| c.to_string()).collect::<Vec<_>>();
+            let listed = client.list(&categories)?;
+            print(json, &listed, || listed.iter().for_each(|l| println!("{} {} {}", l.id, l.cat, l.abs)));
+        },
+        "read" => {
+            let ids = sub.values_of("id").unwrap().map(|id| id.to_string()).collect::<Vec<_>>();
+            let contents = client.read(&ids)?;
+            print(json, &contents, || for c in &contents {
+                println!("{}\ncategory: {}\nabstract: {}\npublisher: {}\nfunded at block {} for {} blocks, weight {}\n\n{}\n",
+                         c.id, c.cat, c.abs, c.publisher, c.height, c.term, c.weight, c.text);
+            });
+        },
+        "prepare" => {
+            let id = client.prepare(sub.value_of("category").unwrap(), sub.value_of("abstract").unwrap(), sub.value_of("content").unwrap())?;
+            print(json, &id, || println!("{}", id));
+        },
+        "fund" => {
+            let id = parse::<sha256::Hash>(sub, "id");
+            let sent = client.fund(passphrase(sub, watch_only).as_str(), &id, parse(sub, "amount"), parse(sub, "term"), fee(sub), None)?;
+            print_sent(json, &sent);
+        },
+        "withdraw" => {
+            let address = parse::<Address>(sub, "address");
+            let amount = sub.value_of("amount").map(|_| parse(sub, "amount"));
+            let sent = client.withdraw(passphrase(sub, watch_only).as_str(), &address, fee(sub), amount, None)?;
+            print_sent(json, &sent);
+        },
+        "balance" => {
+            let balance = client.balance()?;
+            print(json, &balance, || println!("{} satoshis, {} available", balance.balance, balance.available));
+        },
+        "deposit" => {
+            let address = client.deposit()?;
+            print(json, &address, || println!("{}", address));
+        },
+        _ => unreachable!()
+    }
+    Ok(())
+}
+
+// print the answer as JSON or human readable
+fn print<T: Serialize, F: FnOnce()>(json: bool, answer: &T, human: F) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(answer).unwrap());
+    }
+    else {
+        human();
+    }
+}
+
+fn print_sent(json: bool, sent: &Sent) {
+    match sent {
+        Sent::Transaction(txid) => print(json, txid, || println!("sent transaction {}", txid)),
+        Sent::Psbt(psbt) => print(json, psbt, || println!("the wallet is watch-only, sign this PSBT elsewhere and send it with finalize_and_send, or unlock its inputs with discard_psbt:\n{}", psbt))
+    }
+}
+
+fn fee(sub: &ArgMatches) -> Fee {
+    if sub.is_present("fee-per-vbyte") {
+        Fee::PerVbyte(parse(sub, "fee-per-vbyte"))
+    }
+    else {
+        Fee::Target(parse(sub, "target"))
+    }
+}
+
+// read without echo unless given or the wallet is watch-only
+fn passphrase(sub: &ArgMatches, watch_only: bool) -> String {
+    if let Some(passphrase) = sub.value_of("passphrase") {
+        return passphrase.to_string();
+    }
+    if watch_only {
+        return String::new();
+    }
+    rpassword::read_password_from_tty(Some("passphrase: ")).unwrap_or_else(|_| fail("can not read passphrase"))
+}
+
+fn parse<T: FromStr>(sub: &ArgMatches, name: &str) -> T {
+    sub.value_of(name).unwrap().parse::<T>().unwrap_or_else(|_| fail(format!("invalid {}", name).as_str()))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
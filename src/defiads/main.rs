@@ -20,7 +20,7 @@ extern crate clap;
 extern crate toml;
 extern crate base64;
 extern crate hex;
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches, SubCommand};
 
 use simplelog;
 
@@ -34,6 +34,8 @@ use defiads::p2p_bitcoin::{ChainDBTrunk, P2PBitcoin};
 use defiads::p2p_defiads::P2PBiadNet;
 use defiads::db::DB;
 use defiads::store::ContentStore;
+use defiads::policy::Policy;
+use defiads::token::{Tokens, Scope};
 use defiads::wallet::{Wallet, KEY_LOOK_AHEAD};
 use murmel::chaindb::ChainDB;
 
@@ -41,8 +43,10 @@ use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::{Arc,RwLock, Mutex};
 use std::thread;
-use defiads::api::start_api;
+use defiads::api::{start_api, tls_config, Transport};
 use std::fs;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 use rand::{thread_rng, RngCore};
 use log_panics;
 use defiads::find_peers::BIADNET_PORT;
@@ -53,11 +57,18 @@ use defiads::trunk::Trunk;
 
 const HTTP_RPC: &str = "127.0.0.1";
 const BIADNET_LISTEN: &str = "0.0.0.0"; // this also implies ipv6 [::]
+// seconds the latest block header may be older than now for the node to import content
+const IMPORT_MAX_HEADER_AGE: u64 = 24*60*60;
 
 #[derive(Serialize, Deserialize)]
 struct Config {
     apikey: String,
+    /// the wallet is watch-only if empty, transactions are then signed elsewhere
+    #[serde(default)]
     encryptedwalletkey: String,
+    /// BIP39 mnemonic of the wallet's key encrypted with the passphrase, empty if created by an earlier version
+    #[serde(default)]
+    encryptedmnemonic: String,
     keyroot: String,
     lookahead: u32,
     birth: u64
@@ -119,6 +130,30 @@ pub fn main () {
             .takes_value(true)
             .default_value(http_rpc.as_str())
             .min_values(1))
+        .arg(Arg::with_name("rpc-tls-cert")
+            .long("rpc-tls-cert")
+            .value_name("FILE")
+            .help("Serve rpc over TLS on the http-rpc address with this certificate chain (PEM)")
+            .takes_value(true)
+            .requires("rpc-tls-key"))
+        .arg(Arg::with_name("rpc-tls-key")
+            .long("rpc-tls-key")
+            .value_name("FILE")
+            .help("Private key (PEM) of the rpc TLS certificate")
+            .takes_value(true)
+            .requires("rpc-tls-cert"))
+        .arg(Arg::with_name("rpc-unix-socket")
+            .long("rpc-unix-socket")
+            .value_name("PATH")
+            .help("Serve rpc on this unix domain socket instead of http-rpc")
+            .takes_value(true)
+            .conflicts_with("rpc-tls-cert"))
+        .arg(Arg::with_name("rpc-unix-mode")
+            .long("rpc-unix-mode")
+            .value_name("MODE")
+            .help("File permissions of the rpc unix domain socket (octal)")
+            .takes_value(true)
+            .default_value("600"))
         .arg(Arg::with_name("listen")
             .long("listen")
             .value_name("ADDRESS")
@@ -134,6 +169,13 @@ pub fn main () {
             .help("Storage limit in GB")
             .takes_value(true)
             .default_value("1"))
+        .arg(Arg::with_name("storage-quota")
+            .value_name("CATEGORY=n")
+            .long("storage-quota")
+            .help("Part of the storage limit reserved for a category in GB")
+            .multiple(true)
+            .use_delimiter(true)
+            .takes_value(true))
         .arg(Arg::with_name("bitcoin-discovery")
             .long("bitcoin-discovery")
             .help("Enable/Disable bitcoin network discovery")
@@ -148,10 +190,43 @@ pub fn main () {
             .possible_values(&["ON", "OFF"])
             .case_insensitive(true)
             .default_value("ON"))
+        .arg(Arg::with_name("policy")
+            .long("policy")
+            .value_name("FILE")
+            .help("Local policy on content stored, relayed and shown. Default: policy.toml in the work directory")
+            .takes_value(true))
         .arg(Arg::with_name("rescan")
             .long("rescan")
             .help("Re-scan blockchain, forget unconfirmed transactions")
             .takes_value(false))
+        .subcommand(SubCommand::with_name("token")
+            .about("Manage api tokens of clients")
+            .subcommand(SubCommand::with_name("create")
+                .about("Create a token, print its secret")
+                .arg(Arg::with_name("name").required(true))
+                .arg(Arg::with_name("scope")
+                    .required(true)
+                    .multiple(true)
+                    .possible_values(&["read", "publish", "wallet", "admin"])))
+            .subcommand(SubCommand::with_name("revoke")
+                .about("Revoke a token")
+                .arg(Arg::with_name("name").required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("List tokens")))
+        .subcommand(SubCommand::with_name("restore")
+            .about("Restore the wallet from the words of its key, if there is no config file yet, then re-scan")
+            .arg(Arg::with_name("birth")
+                .long("birth")
+                .value_name("YYYY-MM-DD")
+                .help("Date the wallet was created, or earlier")
+                .required(true)
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("export")
+            .about("Write all content to a compressed archive")
+            .arg(Arg::with_name("file").required(true)))
+        .subcommand(SubCommand::with_name("import")
+            .about("Read content from an archive once block headers are in sync, content of blocks not yet known is rejected")
+            .arg(Arg::with_name("file").required(true)))
         .get_matches();
 
     let bitcoin_network = matches.value_of("bitcoin-network").unwrap().parse::<Network>().unwrap();
@@ -231,18 +306,34 @@ pub fn main () {
 
     let storage_limit = matches.value_of("storage-limit").unwrap().parse::<u64>().expect("expecting number of GB") * 1000*1000;
 
+    let storage_quotas = matches.values_of("storage-quota").unwrap_or_default().map(|s| {
+        let pos = s.rfind('=').expect("expecting CATEGORY=n");
+        (s[..pos].to_string(), s[pos+1..].parse::<u64>().expect("expecting number of GB") * 1000*1000)
+    }).collect::<HashMap<String, u64>>();
+
     let mut config_path = workdir.clone();
     config_path.push("defiads.cfg");
 
+    let restore = matches.subcommand_matches("restore").map(|restore|
+        days_since_epoch(restore.value_of("birth").unwrap()).expect("expecting birth as YYYY-MM-DD") * 24 * 60 * 60);
+
     let mut bitcoin_wallet;
     let config;
     if let Ok(config_string) = fs::read_to_string( config_path.clone()) {
+        assert!(restore.is_none(), "can not restore into existing config file {}, move it away first", config_path.display());
         config = toml::from_str::<Config>(config_string.as_str()).expect("can not parse config file");
-        let mut master_account = MasterAccount::from_encrypted(
-            hex::decode(config.encryptedwalletkey).expect("encryptedwalletkey is not hex").as_slice(),
-            ExtendedPubKey::from_str(config.keyroot.as_str()).expect("keyroot is malformed"),
-            config.birth
-        );
+        let keyroot = ExtendedPubKey::from_str(config.keyroot.as_str()).expect("keyroot is malformed");
+        // the mnemonic would reveal the keys of a watch-only wallet
+        assert!(!config.encryptedwalletkey.is_empty() || config.encryptedmnemonic.is_empty(),
+                "remove encryptedmnemonic from config file {} for a watch-only wallet", config_path.display());
+        let mut master_account = if config.encryptedwalletkey.is_empty() {
+            info!("wallet is watch-only, transactions are to be signed elsewhere");
+            MasterAccount::watch_only(keyroot, config.birth)
+        } else {
+            MasterAccount::from_encrypted(
+                hex::decode(config.encryptedwalletkey.as_str()).expect("encryptedwalletkey is not hex").as_slice(),
+                keyroot, config.birth)
+        };
         assert_eq!(bitcoin_network, master_account.master_public().network);
         {
             let mut tx = db.transaction();
@@ -254,15 +345,21 @@ pub fn main () {
             master_account.add_account(account);
             let coins = tx.read_coins(&mut master_account).expect ("can not read coins");
             bitcoin_wallet = Wallet::from_storage(coins,master_account);
+            bitcoin_wallet.set_encrypted_mnemonic(hex::decode(config.encryptedmnemonic.as_str()).expect("encryptedmnemonic is not hex"));
         }
     } else {
 
-        bitcoin_wallet = Wallet::new(bitcoin_network);
+        bitcoin_wallet = if let Some(birth) = restore {
+            Wallet::restore(bitcoin_network, birth)
+        } else {
+            Wallet::new(bitcoin_network)
+        };
         let mut apikey = [0u8;12];
         thread_rng().fill_bytes(&mut apikey);
         config = Config {
             apikey: base64::encode(&apikey),
             encryptedwalletkey: hex::encode(bitcoin_wallet.encrypted().as_slice()),
+            encryptedmnemonic: hex::encode(bitcoin_wallet.encrypted_mnemonic().as_slice()),
             keyroot: bitcoin_wallet.master_public().to_string(),
             birth: bitcoin_wallet.birth(),
             lookahead: KEY_LOOK_AHEAD
@@ -283,7 +380,29 @@ pub fn main () {
 
     let db = Arc::new(Mutex::new(db));
 
-    if matches.is_present("rescan") {
+    let tokens = Arc::new(Tokens::new(db.clone(), config.apikey.as_str()));
+    if let Some(token) = matches.subcommand_matches("token") {
+        match token.subcommand() {
+            ("create", Some(create)) => {
+                let scopes = create.values_of("scope").unwrap().map(|s|
+                    serde_json::from_value::<Scope>(serde_json::Value::String(s.to_string())).unwrap()).collect();
+                println!("{}", tokens.create(create.value_of("name").unwrap(), scopes).expect("can not create token"));
+            },
+            ("revoke", Some(revoke)) => {
+                if !tokens.revoke(revoke.value_of("name").unwrap()).expect("can not revoke token") {
+                    eprintln!("no such token");
+                }
+            },
+            _ => {
+                for t in tokens.list().expect("can not list tokens") {
+                    println!("{} {}", t.name, serde_json::to_string(&t.scopes).unwrap());
+                }
+            }
+        }
+        return;
+    }
+
+    if matches.is_present("rescan") || restore.is_some() {
         let chaindb = chaindb.read().unwrap();
         let mut after = None;
         for t in chaindb.iter_trunk_rev(None) {
@@ -312,12 +431,57 @@ pub fn main () {
                               bitcoin_wallet)
             .expect("can not initialize content store")));
 
-    if let Some(http) = http_rpc {
-        let address = http.clone();
+    content_store.write().unwrap().set_quotas(storage_quotas).expect("can not set storage quotas");
+
+    let policy_path = matches.value_of("policy").map(std::path::PathBuf::from).unwrap_or_else(|| {
+        let mut policy_path = workdir.clone();
+        policy_path.push("policy.toml");
+        policy_path
+    });
+    let policy = Policy::load(policy_path.as_path()).expect("can not load policy");
+    content_store.write().unwrap().set_policy(policy).expect("can not apply policy");
+
+    if let Some(export) = matches.subcommand_matches("export") {
+        let file = fs::File::create(export.value_of("file").unwrap()).expect("can not create archive");
+        println!("exported {} content", content_store.read().unwrap().export(file).expect("can not export content"));
+        return;
+    }
+    if let Some(import) = matches.subcommand_matches("import") {
+        // content is validated with block headers, those are only synchronized while the node runs
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let synced = chaindb.read().unwrap().header_tip().map_or(false, |tip| tip.stored.header.time as u64 + IMPORT_MAX_HEADER_AGE >= now);
+        if !synced {
+            eprintln!("block headers are not in sync, run the node until it is in sync with the network, then import");
+            return;
+        }
+        let file = fs::File::open(import.value_of("file").unwrap()).expect("can not open archive");
+        let imported = content_store.write().unwrap().import(file).expect("can not import content");
+        println!("imported {} of {} content", imported.added, imported.read);
+        if imported.unknown > 0 {
+            eprintln!("{} content is funded in blocks not yet known", imported.unknown);
+        }
+        if imported.failed > 0 {
+            eprintln!("{} content is malformed or could not be stored", imported.failed);
+        }
+        return;
+    }
+
+    let transport = if let Some(transport) = unix_transport(&matches) {
+        Some(transport)
+    }
+    else if let (Some(cert), Some(key)) = (matches.value_of("rpc-tls-cert"), matches.value_of("rpc-tls-key")) {
+        let config = tls_config(std::path::Path::new(cert), std::path::Path::new(key)).expect("can not load rpc TLS certificate");
+        http_rpc.map(|address| Transport::Tls(address, config))
+    }
+    else {
+        http_rpc.map(Transport::Http)
+    };
+
+    if let Some(transport) = transport {
         let store = content_store.clone();
-        let apikey = config.apikey.clone();
+        let tokens = tokens.clone();
         thread::Builder::new().name("http".to_string()).spawn(
-            move || start_api(&address, store, apikey)).expect("can not start http api");
+            move || start_api(transport, store, tokens)).expect("can not start http api");
     }
 
     let mut thread_pool = ThreadPoolBuilder::new().name_prefix("futures ").create().expect("can not start thread pool");
@@ -327,3 +491,34 @@ pub fn main () {
                     content_store.clone(), bitcoin_network != Network::Bitcoin).start(&mut thread_pool);
     thread_pool.run(future::pending::<()>());
 }
+
+#[cfg(unix)]
+fn unix_transport(matches: &ArgMatches) -> Option<Transport> {
+    matches.value_of("rpc-unix-socket").map(|path| {
+        let mode = u32::from_str_radix(matches.value_of("rpc-unix-mode").unwrap(), 8).expect("expecting octal file permissions");
+        Transport::Unix(std::path::PathBuf::from(path), mode)
+    })
+}
+
+#[cfg(not(unix))]
+fn unix_transport(matches: &ArgMatches) -> Option<Transport> {
+    if matches.is_present("rpc-unix-socket") {
+        panic!("unix domain sockets are not supported on this platform");
+    }
+    None
+}
+
+// days from 1970-01-01 to a date given as YYYY-MM-DD
+fn days_since_epoch(date: &str) -> Option<u64> {
+    let parts = date.split('-').map(|p| p.parse::<u64>().ok()).collect::<Option<Vec<_>>>()?;
+    if parts.len() != 3 || parts[0] < 1970 || parts[1] < 1 || parts[1] > 12 || parts[2] < 1 || parts[2] > 31 {
+        return None;
+    }
+    // civil to days, with years starting in March
+    let (y, m, d) = if parts[1] <= 2 { (parts[0] - 1, parts[1] + 9, parts[2]) } else { (parts[0], parts[1] - 3, parts[2]) };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
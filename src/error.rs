@@ -32,7 +32,13 @@ pub enum Error {
     /// DB error
     DB(rusqlite::Error),
     /// script validation error
-    Script(script::Error)
+    Script(script::Error),
+    /// abstract or schema is not valid
+    Schema(String),
+    /// policy rule is not valid
+    Policy(String),
+    /// error answered by the rpc api, code and message
+    Rpc(i64, String)
 }
 
 impl std::error::Error for Error {
@@ -42,7 +48,10 @@ impl std::error::Error for Error {
             Error::Wallet(ref err) => err.description(),
             Error::IO(ref err) => err.description(),
             Error::DB(ref err) => err.description(),
-            Error::Script(ref err) => err.description()
+            Error::Script(ref err) => err.description(),
+            Error::Schema(ref s) => s,
+            Error::Policy(ref s) => s,
+            Error::Rpc(_, ref s) => s
         }
     }
 
@@ -52,7 +61,10 @@ impl std::error::Error for Error {
             Error::Wallet(ref err) => Some(err),
             Error::IO(ref err) => Some(err),
             Error::DB(ref err) => Some(err),
-            Error::Script(ref err) => Some(err)
+            Error::Script(ref err) => Some(err),
+            Error::Schema(_) => None,
+            Error::Policy(_) => None,
+            Error::Rpc(_, _) => None
         }
     }
 }
@@ -67,6 +79,9 @@ impl fmt::Display for Error {
             Error::IO(ref s) => write!(f, "{}", s),
             Error::DB(ref s) =>  write!(f, "{}", s),
             Error::Script(ref s) =>  write!(f, "{}", s),
+            Error::Schema(ref s) => write!(f, "Schema: {}", s),
+            Error::Policy(ref s) => write!(f, "Policy: {}", s),
+            Error::Rpc(code, ref s) => write!(f, "RPC error {}: {}", code, s),
         }
     }
 }
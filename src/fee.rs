@@ -0,0 +1,223 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! fee estimation from recent blocks
+//!
+//! The fee of a transaction is known if it spends outputs of the block itself or of the few blocks before.
+//! A block with too few such transactions contributes its average feerate instead, that is its coinbase
+//! value less the subsidy divided by the size of its transactions.
+
+use bitcoin::{Block, OutPoint};
+use std::collections::{HashMap, VecDeque};
+
+/// blocks kept for estimation
+pub const WINDOW: u32 = 144;
+/// blocks needed for an estimate
+const MIN_BLOCKS: usize = 6;
+/// blocks whose outputs are remembered to compute fees of transactions spending them
+const OUTPUT_BLOCKS: usize = 6;
+/// transactions with known fee needed to use them instead of the block average
+const MIN_SAMPLES: usize = 20;
+/// probability of confirmation within the target
+const SUCCESS: f64 = 0.95;
+
+/// percentiles of feerates recorded for a block
+pub const PERCENTILES: [u32; 5] = [10, 25, 50, 75, 90];
+
+/// feerates of transactions in a block in satoshi/vbyte at PERCENTILES
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockFeerates {
+    pub height: u32,
+    pub percentiles: [u64; 5]
+}
+
+/// estimated feerate to confirm within target blocks
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct FeeEstimate {
+    pub target: u16,
+    pub fee_per_vbyte: u64,
+    /// number of recent blocks the estimate is based on
+    pub blocks: u32,
+    /// median feerates of recent blocks at the 10th, 25th, 50th, 75th and 90th percentile of their transactions
+    pub percentiles: [u64; 5]
+}
+
+pub struct FeeEstimator {
+    blocks: VecDeque<BlockFeerates>,
+    outputs: VecDeque<HashMap<OutPoint, u64>>
+}
+
+fn subsidy(height: u32) -> u64 {
+    let halvings = height / 210_000;
+    if halvings >= 64 { 0 } else { (50 * 100_000_000) >> halvings }
+}
+
+// nearest rank percentile of sorted values, q in 0..1
+fn percentile(sorted: &[u64], q: f64) -> u64 {
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[std::cmp::min(std::cmp::max(rank, 1), sorted.len()) - 1]
+}
+
+impl FeeEstimator {
+    /// estimator from feerates of blocks stored earlier, ordered by height
+    pub fn new(blocks: Vec<BlockFeerates>) -> FeeEstimator {
+        FeeEstimator { blocks: blocks.into_iter().collect(), outputs: VecDeque::new() }
+    }
+
+    /// record feerates of a new block, None if it has no transactions other than the coinbase
+    pub fn block_connected(&mut self, block: &Block, height: u32) -> Option<BlockFeerates> {
+        // forget blocks re-organized out
+        while self.blocks.back().map_or(false, |b| b.height >= height) {
+            self.blocks.pop_back();
+        }
+        let mut outputs = HashMap::new();
+        let mut rates = Vec::new();
+        let mut size = 0u64;
+        for (i, transaction) in block.txdata.iter().enumerate() {
+            let txid = transaction.txid();
+            if i > 0 {
+                let vsize = ((transaction.get_weight() + 3) / 4) as u64;
+                size += vsize;
+                let spent = transaction.input.iter().map(|input|
+                    outputs.get(&input.previous_output).or_else(||
+                        self.outputs.iter().rev().find_map(|o| o.get(&input.previous_output))).cloned())
+                    .collect::<Option<Vec<u64>>>();
+                if let Some(spent) = spent {
+                    let value = transaction.output.iter().map(|o| o.value).sum::<u64>();
+                    rates.push(spent.iter().sum::<u64>().saturating_sub(value) / vsize);
+                }
+            }
+            for (vout, output) in transaction.output.iter().enumerate() {
+                outputs.insert(OutPoint { txid, vout: vout as u32 }, output.value);
+            }
+        }
+        self.outputs.push_back(outputs);
+        if self.outputs.len() > OUTPUT_BLOCKS {
+            self.outputs.pop_front();
+        }
+        if size == 0 {
+            return None;
+        }
+        if rates.len() < MIN_SAMPLES {
+            let coinbase = block.txdata[0].output.iter().map(|o| o.value).sum::<u64>();
+            rates = vec!(coinbase.saturating_sub(subsidy(height)) / size);
+        }
+        rates.sort_unstable();
+        let mut percentiles = [0u64; 5];
+        for (i, p) in PERCENTILES.iter().enumerate() {
+            percentiles[i] = percentile(&rates, *p as f64 / 100.0);
+        }
+        let feerates = BlockFeerates { height, percentiles };
+        self.blocks.push_back(feerates.clone());
+        while self.blocks.len() > WINDOW as usize {
+            self.blocks.pop_front();
+        }
+        Some(feerates)
+    }
+
+    /// feerate that would have confirmed within target blocks with 95% probability in recent blocks,
+    /// None if there are not enough recent blocks
+    pub fn estimate(&self, target: u16) -> Option<FeeEstimate> {
+        if self.blocks.len() < MIN_BLOCKS || target == 0 {
+            return None;
+        }
+        // a transaction is confirmed by a block if it pays at least the lowest feerates included in the block
+        let mut lowest = self.blocks.iter().map(|b| b.percentiles[0]).collect::<Vec<_>>();
+        lowest.sort_unstable();
+        // probability a block confirms for a target to be met: 1 - (1 - p)^target = SUCCESS
+        let p = 1.0 - (1.0 - SUCCESS).powf(1.0 / target as f64);
+        let mut percentiles = [0u64; 5];
+        for (i, median) in percentiles.iter_mut().enumerate() {
+            let mut rates = self.blocks.iter().map(|b| b.percentiles[i]).collect::<Vec<_>>();
+            rates.sort_unstable();
+            *median = percentile(&rates, 0.5);
+        }
+        Some(FeeEstimate {
+            target,
+            fee_per_vbyte: std::cmp::max(percentile(&lowest, p), 1),
+            blocks: self.blocks.len() as u32,
+            percentiles
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::{BlockHeader, Transaction, TxIn, TxOut, Script};
+    use bitcoin_hashes::sha256d;
+
+    fn transaction(inputs: Vec<OutPoint>, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: inputs.into_iter().map(|previous_output|
+                TxIn { previous_output, script_sig: Script::new(), sequence: 0xffffffff, witness: vec!(vec!(0u8; 107)) }).collect(),
+            output: vec!(TxOut { value, script_pubkey: Script::new() })
+        }
+    }
+
+    fn block(txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: BlockHeader { version: 1, prev_blockhash: sha256d::Hash::default(), merkle_root: sha256d::Hash::default(), time: 0, bits: 0, nonce: 0 },
+            txdata
+        }
+    }
+
+    #[test]
+    fn test_estimate () {
+        let mut estimator = FeeEstimator::new(Vec::new());
+        let mut previous = Vec::new();
+        for height in 0..10u32 {
+            let fee_per_vbyte = 10 * (height as u64 + 1);
+            // coinbase is unique by height
+            let mut txdata = vec!(transaction(vec!(OutPoint::null()), subsidy(height) + height as u64));
+            let mut outputs = Vec::new();
+            for (spent, value) in &previous {
+                let mut t = transaction(vec!(*spent), 0);
+                let vsize = ((t.get_weight() + 3) / 4) as u64;
+                t.output[0].value = value - fee_per_vbyte * vsize;
+                outputs.push((OutPoint { txid: t.txid(), vout: 0 }, t.output[0].value));
+                txdata.push(t);
+            }
+            if previous.is_empty() {
+                // spending unknown outputs, so the block average is used
+                for n in 0..MIN_SAMPLES {
+                    let t = transaction(vec!(OutPoint { txid: txdata[0].txid(), vout: n as u32 + 1 }), 1_000_000 - n as u64);
+                    outputs.push((OutPoint { txid: t.txid(), vout: 0 }, t.output[0].value));
+                    txdata.push(t);
+                }
+            }
+            let b = block(txdata);
+            let feerates = estimator.block_connected(&b, height).unwrap();
+            assert_eq!(feerates.percentiles, if height > 0 { [fee_per_vbyte; 5] } else { [0; 5] });
+            previous = outputs;
+            if height < MIN_BLOCKS as u32 - 1 {
+                assert!(estimator.estimate(1).is_none());
+            }
+        }
+        let next = estimator.estimate(1).unwrap();
+        let later = estimator.estimate(100).unwrap();
+        assert_eq!(next.blocks, 10);
+        assert_eq!(next.fee_per_vbyte, 100);
+        assert!(later.fee_per_vbyte < next.fee_per_vbyte);
+        assert_eq!(next.percentiles, later.percentiles);
+
+        // re-org replaces the tip
+        assert!(estimator.block_connected(&block(vec!(transaction(vec!(OutPoint::null()), 0))), 9).is_none());
+        assert_eq!(estimator.estimate(1).unwrap().blocks, 9);
+    }
+}
@@ -42,10 +42,14 @@ extern crate rand;
 extern crate murmel;
 #[macro_use]extern crate log;
 extern crate rusqlite;
+extern crate regex;
+extern crate tokio_rustls;
+extern crate base64;
 
-mod error;
+pub mod error;
 mod text;
-mod ad;
+pub mod ad;
+pub mod adspace;
 mod iblt;
 mod messages;
 mod content;
@@ -54,9 +58,16 @@ mod blockdownload;
 pub mod trunk;
 pub mod sendtx;
 pub mod wallet;
+pub mod fee;
 pub mod api;
+pub mod openrpc;
+pub mod client;
 pub mod find_peers;
 pub mod store;
+pub mod subscription;
+pub mod schema;
+pub mod policy;
+pub mod token;
 pub mod db;
 pub mod updater;
 pub mod p2p_bitcoin;
@@ -38,7 +38,8 @@ impl Command for Envelope {
             Message::PollContent(_) => "poll content",
             Message::ContentIBLT(_, _) => "content iblt",
             Message::Get(_) => "get",
-            Message::Content(_) => "content"
+            Message::Content(_) => "content",
+            Message::Revoke(_) => "revoke"
         }.to_string()
     }
 }
@@ -53,7 +54,8 @@ pub enum Message {
     PollContent(PollContentMessage),
     ContentIBLT(sha256d::Hash, IBLT<ContentKey>),
     Get(Vec<sha256::Hash>),
-    Content(Content)
+    Content(Content),
+    Revoke(RevokeMessage)
 }
 
 impl Version for Message {
@@ -107,6 +109,15 @@ pub struct PollContentMessage {
     pub size: u32
 }
 
+/// withdraw content before the end of its term
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevokeMessage {
+    /// digest of the revoked ad
+    pub id: sha256::Hash,
+    /// DER signature of the digest with the funder key tweaked with the digest
+    pub signature: Vec<u8>
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PollAddressMessage {
     /// min sketch of own id set
@@ -0,0 +1,365 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! OpenRPC description of the rpc api
+//!
+//! Served by the rpc.discover method. The scope a method needs is given as its tag.
+
+use serde_json::{json, Value};
+use crate::token::Scope;
+
+const OPENRPC_VERSION: &str = "1.2.6";
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", name)})
+}
+
+fn string() -> Value {
+    json!({"type": "string"})
+}
+
+fn integer() -> Value {
+    json!({"type": "integer", "minimum": 0})
+}
+
+fn array(items: Value) -> Value {
+    json!({"type": "array", "items": items})
+}
+
+fn paged(items: Value) -> Value {
+    json!({"type": "object", "properties": {
+        "total": integer(),
+        "next": {"type": ["string", "null"]},
+        "items": array(items)
+    }, "required": ["total", "next", "items"]})
+}
+
+fn param(name: &str, required: bool, schema: Value) -> Value {
+    json!({"name": name, "required": required, "schema": schema})
+}
+
+fn passphrase() -> Value {
+    param("passphrase", true, string())
+}
+
+// not needed if the wallet is watch-only
+fn watch_only_passphrase() -> Value {
+    param("passphrase", false, string())
+}
+
+fn fee_per_vbyte() -> Value {
+    param("fee_per_vbyte", false, json!({"type": "integer", "minimum": 1, "maximum": 100}))
+}
+
+// confirmation target instead of fee per vbyte
+fn target() -> Value {
+    param("target", false, json!({"type": "integer", "minimum": 1, "maximum": 65535}))
+}
+
+// a coin as txid:vout
+fn outpoint() -> Value {
+    json!({"type": "string", "pattern": "^[0-9a-f]{64}:[0-9]+$"})
+}
+
+fn inputs() -> Value {
+    param("inputs", false, array(outpoint()))
+}
+
+fn method(name: &str, scope: Option<Scope>, summary: &str, params: Vec<Value>, result: Value) -> Value {
+    let mut method = json!({
+        "name": name,
+        "summary": summary,
+        "paramStructure": "either",
+        "params": params,
+        "result": {"name": "result", "schema": result}
+    });
+    if let Some(scope) = scope {
+        method["tags"] = json!([{"name": scope}]);
+    }
+    method
+}
+
+fn components() -> Value {
+    let listed = json!({"type": "object", "properties": {
+        "id": string(), "cat": string(), "abs": string()
+    }, "required": ["id", "cat", "abs"]});
+    let readable = json!({"type": "object", "properties": {
+        "id": string(), "cat": string(), "abs": string(), "text": string(),
+        "start": integer(), "end": integer(), "publisher": string(),
+        "height": integer(), "term": integer(), "length": integer(), "weight": integer()
+    }, "required": ["id", "cat", "abs", "text", "start", "end", "publisher", "height", "term", "length", "weight"]});
+    let page = json!({"type": "object", "properties": {
+        "sort": {"enum": ["weight", "height", "term", "length"]},
+        "order": {"enum": ["asc", "desc"]},
+        "cursor": {"type": ["string", "null"]},
+        "limit": {"type": "integer", "minimum": 1, "maximum": 1000}
+    }});
+    let rule = json!({"type": "object", "properties": {
+        "target": {"enum": ["digest", "publisher", "category", "abstract"]},
+        "value": string(),
+        "action": {"enum": ["hide", "reject"]}
+    }, "required": ["target", "value", "action"]});
+    let schema = json!({"type": "object", "properties": {
+        "type": {"const": "object"},
+        "properties": {"type": "object", "additionalProperties": {"type": "object", "properties": {
+            "type": {"enum": ["string", "number", "boolean"]}
+        }, "required": ["type"]}},
+        "required": array(string())
+    }, "required": ["type", "properties"]});
+    let event = json!({"type": "object", "properties": {
+        "event": {"enum": ["added", "renewed", "expired", "dropped", "unconfirmed", "revoked"]},
+        "id": string(), "cat": string(), "abs": string()
+    }, "required": ["event", "id", "cat", "abs"]});
+    let balance = json!({"type": "object", "properties": {
+        "balance": integer(), "available": integer()
+    }, "required": ["balance", "available"]});
+    let publisher_stats = json!({"type": "object", "properties": {
+        "publisher": string(), "amount": integer(), "ads": integer(), "earliest": {"type": ["integer", "null"]}
+    }, "required": ["publisher", "amount", "ads", "earliest"]});
+    let prepared = json!({"type": "object", "properties": {
+        "id": string(), "cat": string(), "abs": string(), "text": string()
+    }, "required": ["id", "cat", "abs", "text"]});
+    let funding = json!({"type": "object", "properties": {
+        "id": string(), "txid": string(), "vout": integer(), "value": integer(),
+        "height": {"type": ["integer", "null"]}, "unlock": {"type": ["integer", "null"]}, "matured": {"type": "boolean"}
+    }, "required": ["id", "txid", "vout", "value", "height", "unlock", "matured"]});
+    let unspent = json!({"type": "object", "properties": {
+        "txid": string(), "vout": integer(), "value": integer(), "confirmations": integer(),
+        "csv": {"type": ["integer", "null"]}, "id": {"type": ["string", "null"]},
+        "available": {"type": "boolean"}, "locked": {"type": "boolean"}
+    }, "required": ["txid", "vout", "value", "confirmations", "csv", "id", "available", "locked"]});
+    let wallet_transaction = json!({"type": "object", "properties": {
+        "txid": string(), "kind": {"enum": ["deposit", "withdrawal", "funding", "internal"]},
+        "height": {"type": ["integer", "null"]}, "confirmed": {"type": "boolean"},
+        "delta": {"type": ["integer", "null"]}, "fee": {"type": ["integer", "null"]}, "id": {"type": ["string", "null"]}
+    }, "required": ["txid", "kind", "height", "confirmed", "delta", "fee", "id"]});
+    let fee_estimate = json!({"type": "object", "properties": {
+        "target": integer(), "fee_per_vbyte": integer(), "blocks": integer(),
+        "percentiles": {"type": "array", "items": integer(), "minItems": 5, "maxItems": 5}
+    }, "required": ["target", "fee_per_vbyte", "blocks", "percentiles"]});
+    let offer = json!({"type": "object", "properties": {
+        "id": string(), "amount": integer(), "term": integer(), "price": integer()
+    }, "required": ["id", "amount", "term", "price"]});
+    let rental = json!({"type": "object", "properties": {
+        "id": string(), "offer": string(), "address": string(), "price": integer(), "amount": integer(), "term": integer(),
+        "expiry": integer(), "paid": {"type": ["string", "null"]}, "block": {"type": ["string", "null"]}, "funded": {"type": ["string", "null"]},
+        "stalled": {"type": "boolean"}
+    }, "required": ["id", "offer", "address", "price", "amount", "term", "expiry", "paid", "block", "funded", "stalled"]});
+    let imported = json!({"type": "object", "properties": {
+        "read": integer(), "added": integer(), "unknown": integer(), "failed": integer()
+    }, "required": ["read", "added", "unknown", "failed"]});
+    let token = json!({"type": "object", "properties": {
+        "name": string(), "scopes": array(schema_ref("Scope"))
+    }, "required": ["name", "scopes"]});
+    json!({
+        "schemas": {
+            "ListedAbstract": listed,
+            "Readable": readable,
+            "Page": page,
+            "Rule": rule,
+            "Schema": schema,
+            "Event": event,
+            "Balance": balance,
+            "PublisherStats": publisher_stats,
+            "Prepared": prepared,
+            "Funding": funding,
+            "Unspent": unspent,
+            "WalletTransaction": wallet_transaction,
+            "FeeEstimate": fee_estimate,
+            "Offer": offer,
+            "Rental": rental,
+            "Imported": imported,
+            "Scope": {"enum": ["read", "publish", "wallet", "admin"]},
+            "TokenInfo": token
+        },
+        "errors": {
+            "InvalidParams": {"code": -32602, "message": "invalid parameters"},
+            "Unauthorized": {"code": -32001, "message": "token lacks scope"},
+            "Refused": {"code": -32002, "message": "request refused e.g. insufficient funds"},
+            "WalletError": {"code": -32003, "message": "wallet failed e.g. wrong passphrase"},
+            "InternalError": {"code": -32603, "message": "internal error"}
+        }
+    })
+}
+
+fn methods() -> Vec<Value> {
+    let listed = || array(schema_ref("ListedAbstract"));
+    let page = || param("page", false, schema_ref("Page"));
+    let id = || param("id", true, string());
+    let txid = string;
+    vec!(
+        method("rpc.discover", None, "this description of the api", vec!(),
+            json!({"type": "object"})),
+        method("categories", Some(Scope::Read), "list known categories, a page if page is given", vec!(page()),
+            json!({"oneOf": [array(string()), paged(string())]})),
+        method("list", Some(Scope::Read), "list ids and abstracts of categories, a page of all categories if none given and page is",
+            vec!(param("categories", false, array(string())), page()),
+            json!({"oneOf": [listed(), paged(schema_ref("ListedAbstract"))]})),
+        method("search", Some(Scope::Read), "search ids and abstracts by keywords in category, abstract and text",
+            vec!(param("keywords", true, array(string()))), listed()),
+        method("query", Some(Scope::Read), "list ids and abstracts of a category with structured abstracts satisfying all conditions e.g. rate < 9000",
+            vec!(param("category", true, string()), param("conditions", false, array(string()))), listed()),
+        method("list_policy", Some(Scope::Admin), "list rules of the local policy", vec!(),
+            array(schema_ref("Rule"))),
+        method("add_policy_rule", Some(Scope::Admin), "add a rule to the local policy",
+            vec!(param("target", true, json!({"enum": ["digest", "publisher", "category", "abstract"]})),
+                 param("value", true, string()), param("action", true, json!({"enum": ["hide", "reject"]}))),
+            json!({"type": "boolean"})),
+        method("remove_policy_rule", Some(Scope::Admin), "remove a rule from the local policy, false if there was no such rule",
+            vec!(param("target", true, json!({"enum": ["digest", "publisher", "category", "abstract"]})),
+                 param("value", true, string()), param("action", true, json!({"enum": ["hide", "reject"]}))),
+            json!({"type": "boolean"})),
+        method("export", Some(Scope::Admin), "all content as base64 encoded archive, a snappy compressed stream of CBOR encoded content",
+            vec!(), string()),
+        method("import", Some(Scope::Admin), "add content of an archive written by export, validated as if received from a peer",
+            vec!(param("archive", true, string())), schema_ref("Imported")),
+        method("list_by_publisher", Some(Scope::Read), "list ids and abstracts funded by a publisher key",
+            vec!(param("publisher", true, string())), listed()),
+        method("publisher_stats", Some(Scope::Read), "summary of content funded by a publisher key",
+            vec!(param("publisher", true, string())), schema_ref("PublisherStats")),
+        method("read", Some(Scope::Read), "read content, a page of all content if no id given and page is",
+            vec!(param("ids", false, array(string())), page()),
+            json!({"oneOf": [array(schema_ref("Readable")), paged(schema_ref("Readable"))]})),
+        method("subscribe", Some(Scope::Read), "subscribe to events of content with all keywords in the abstract, of all categories if none given",
+            vec!(param("category", false, string()), param("keywords", false, array(string()))), string()),
+        method("poll", Some(Scope::Read), "poll events of a subscription, waits until there is an event or the timeout passed",
+            vec!(param("subscription", true, string()), param("timeout", false, json!({"type": "integer", "minimum": 0, "maximum": 60}))),
+            array(schema_ref("Event"))),
+        method("unsubscribe", Some(Scope::Read), "cancel a subscription",
+            vec!(param("subscription", true, string())), json!({"type": "boolean"})),
+        method("balance", Some(Scope::Wallet), "wallet balance in satoshis", vec!(),
+            schema_ref("Balance")),
+        method("listunspent", Some(Scope::Wallet), "coins of the wallet, the least confirmed first", vec!(),
+            array(schema_ref("Unspent"))),
+        method("lockunspent", Some(Scope::Wallet), "exclude coins from automatic selection of inputs or return them if unlock, the number of coins changed is answered",
+            vec!(param("outpoints", true, array(outpoint())), param("unlock", false, json!({"type": "boolean"}))), integer()),
+        method("transactions", Some(Scope::Wallet), "transactions of the wallet, unconfirmed first then the latest first", vec!(),
+            array(schema_ref("WalletTransaction"))),
+        method("deposit", Some(Scope::Wallet), "a deposit address of the wallet", vec!(), string()),
+        method("reveal_mnemonic", Some(Scope::Admin), "words of the BIP39 mnemonic of the wallet's key",
+            vec!(passphrase()), string()),
+        method("prepare", Some(Scope::Publish), "prepare a publication, its id is answered",
+            vec!(param("category", true, string()), param("abstract", true, string()), param("content", true, string())), string()),
+        method("set_schema", Some(Scope::Admin), "declare the schema of structured abstracts in a category, remove it if schema is not given",
+            vec!(param("category", true, string()), param("schema", false, schema_ref("Schema"))), json!({"type": "boolean"})),
+        method("read_schema", Some(Scope::Read), "read the schema of a category",
+            vec!(param("category", true, string())), json!({"oneOf": [schema_ref("Schema"), {"type": "null"}]})),
+        method("list_prepared", Some(Scope::Publish), "list ids of prepared publications", vec!(),
+            array(string())),
+        method("read_prepared", Some(Scope::Publish), "read a prepared publication",
+            vec!(id()), schema_ref("Prepared")),
+        method("commitment_address", Some(Scope::Publish), "address to fund a prepared publication from an external wallet, it is published as the payment confirms",
+            vec!(id(), param("publisher", true, string()), param("term", true, json!({"type": "integer", "minimum": 1, "maximum": 4320}))), string()),
+        method("estimatefee", Some(Scope::Read), "estimate the fee to confirm within target blocks, 6 if not given, null if too few blocks were seen yet",
+            vec!(target()), json!({"oneOf": [schema_ref("FeeEstimate"), {"type": "null"}]})),
+        method("withdraw", Some(Scope::Wallet), "withdraw amount satoshis or all if not given, the transaction id or if watch-only the base64 encoded PSBT is answered",
+            vec!(watch_only_passphrase(), param("address", true, string()), fee_per_vbyte(), param("amount", false, integer()), target(), inputs()), string()),
+        method("fund", Some(Scope::Wallet), "fund a prepared publication for term blocks, the transaction id or if watch-only the base64 encoded PSBT is answered",
+            vec!(watch_only_passphrase(), id(), param("amount", true, integer()), param("term", true, json!({"type": "integer", "minimum": 1, "maximum": 65535})), fee_per_vbyte(), target(), inputs()),
+            string()),
+        method("renew", Some(Scope::Wallet), "spend matured funding of a publication into a new funding of term blocks, before it matured coins of the wallet of the same amount extend the publication",
+            vec!(passphrase(), id(), param("term", true, json!({"type": "integer", "minimum": 1, "maximum": 65535})), fee_per_vbyte(), target()),
+            txid()),
+        method("bumpfee", Some(Scope::Wallet), "replace an unconfirmed transaction of the wallet with one paying a higher fee from its change or only output, that is not a commitment",
+            vec!(passphrase(), param("txid", true, string()), fee_per_vbyte(), target()),
+            txid()),
+        method("finalize_and_send", Some(Scope::Wallet), "finalize and send a transaction of the watch-only wallet signed elsewhere",
+            vec!(param("psbt", true, string())), txid()),
+        method("discard_psbt", Some(Scope::Wallet), "forget a transaction of the watch-only wallet waiting to be signed and unlock its inputs, false if none waits with this id",
+            vec!(param("txid", true, string())), json!({"type": "boolean"})),
+        method("revoke", Some(Scope::Wallet), "revoke own funding of a publication before the end of its term, it stays published while co-funded by others",
+            vec!(passphrase(), id()), json!({"type": "boolean"})),
+        method("list_funded", Some(Scope::Wallet), "list funding of own publications", vec!(),
+            array(schema_ref("Funding"))),
+        method("sweep", Some(Scope::Wallet), "sweep matured funding back to the wallet, also as new blocks arrive if auto",
+            vec!(passphrase(), fee_per_vbyte(), param("auto", false, json!({"type": "boolean"})), target()),
+            json!({"type": ["string", "null"]})),
+        method("offer_adspace", Some(Scope::Wallet), "offer to fund ads of advertisers, the id of the publication advertising the offer is answered, not with a watch-only wallet",
+            vec!(passphrase(), param("amount", true, integer()), param("term", true, json!({"type": "integer", "minimum": 1, "maximum": 4320})),
+                 param("price", true, integer()), fee_per_vbyte(), target()), string()),
+        method("cancel_offer", Some(Scope::Wallet), "withdraw an offer of ad space, false if there was no such offer",
+            vec!(id()), json!({"type": "boolean"})),
+        method("list_offers", Some(Scope::Read), "list ad space offered by this node", vec!(),
+            array(schema_ref("Offer"))),
+        method("rent_adspace", Some(Scope::Publish), "rent offered ad space for an ad, funded once the price paid to the address answered has 6 confirmations, dropped if not paid until the block at expiry",
+            vec!(param("offer", true, string()), param("category", true, string()), param("abstract", true, string()), param("content", true, string())),
+            schema_ref("Rental")),
+        method("list_rentals", Some(Scope::Wallet), "list ad space rented, stalled if paid but not funded since the node was restarted, offer ad space again to fund it", vec!(),
+            array(schema_ref("Rental"))),
+        method("create_token", Some(Scope::Admin), "create a token for a client, it can not be retrieved later",
+            vec!(param("name", true, string()), param("scopes", true, array(schema_ref("Scope")))), string()),
+        method("revoke_token", Some(Scope::Admin), "revoke a token, false if there was no token of that name",
+            vec!(param("name", true, string())), json!({"type": "boolean"})),
+        method("list_tokens", Some(Scope::Admin), "list tokens", vec!(),
+            array(schema_ref("TokenInfo")))
+    )
+}
+
+/// the OpenRPC document describing all methods of the api
+pub fn document() -> Value {
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "defiads",
+            "description": "authorize with a token of the scope tagged to the method in the Authorization header: Bearer TOKEN",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "methods": methods(),
+        "components": components()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_document () {
+        let document = document();
+        let methods = document["methods"].as_array().unwrap();
+        let mut names = HashSet::new();
+        for method in methods {
+            assert!(names.insert(method["name"].as_str().unwrap().to_string()));
+            for param in method["params"].as_array().unwrap() {
+                assert!(param["name"].is_string(), "param without name in {}", method["name"]);
+                assert!(param["schema"].is_object(), "param without schema in {}", method["name"]);
+                check_refs(&document, &param["schema"]);
+            }
+            check_refs(&document, &method["result"]["schema"]);
+        }
+        // methods registered with the api, not by its tests
+        let api = include_str!("api.rs");
+        let registered = api[..api.find("#[cfg(test)]").unwrap()].split("add_method_with_meta(\"").skip(1)
+            .map(|s| s[..s.find('"').unwrap()].to_string()).collect::<HashSet<_>>();
+        assert_eq!(names, registered);
+    }
+
+    fn check_refs (document: &Value, schema: &Value) {
+        match schema {
+            Value::Object(map) => {
+                if let Some(Value::String(r)) = map.get("$ref") {
+                    assert!(r.starts_with("#/components/schemas/"), "unexpected reference {}", r);
+                    let name = &r["#/components/schemas/".len()..];
+                    assert!(document["components"]["schemas"].get(name).is_some(), "unknown schema {}", name);
+                }
+                map.values().for_each(|v| check_refs(document, v));
+            },
+            Value::Array(array) => array.iter().for_each(|v| check_refs(document, v)),
+            _ => {}
+        }
+    }
+}
@@ -226,7 +226,8 @@ impl P2PBiadNet {
             dispatcher.add_listener(discovery);
         }
         let updater = Updater::new(p2p_control.clone(), timeout.clone(), self.content_store.clone());
-        dispatcher.add_listener(updater);
+        dispatcher.add_listener(updater.clone());
+        self.content_store.write().unwrap().set_updater(updater);
         let address_pool = AddressPoolMaintainer::new(p2p_control.clone(), self.db.clone());
         dispatcher.add_listener(address_pool);
 
@@ -0,0 +1,183 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! local policy on content stored and relayed
+//!
+//! The policy file lists rules as:
+//! [[rule]]
+//! target = "category"
+//! value = "casino"
+//! action = "reject"
+
+use bitcoin::PublicKey;
+use bitcoin_hashes::sha256;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::error::Error;
+
+/// what a rule applies to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// content id
+    Digest,
+    /// funder key
+    Publisher,
+    /// exact category
+    Category,
+    /// regular expression matching the abstract
+    Abstract
+}
+
+/// what happens to content matching a rule
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// stored and relayed to keep in sync with peers, but not shown through the api
+    Hide,
+    /// neither stored nor relayed, nor shown
+    Reject
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    pub target: Target,
+    pub value: String,
+    pub action: Action
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PolicyFile {
+    #[serde(default)]
+    rule: Vec<Rule>
+}
+
+/// rules of the local policy
+#[derive(Default)]
+pub struct Policy {
+    rules: Vec<(Rule, Option<Regex>)>,
+    path: Option<PathBuf>
+}
+
+impl Policy {
+    /// load policy from a file, an empty policy if the file does not exist yet
+    pub fn load(path: &Path) -> Result<Policy, Error> {
+        let mut policy = Policy { rules: Vec::new(), path: Some(path.to_path_buf()) };
+        if path.exists() {
+            let file = toml::from_str::<PolicyFile>(fs::read_to_string(path)?.as_str())
+                .map_err(|e| Error::Policy(format!("can not parse policy file {}", e)))?;
+            for rule in file.rule {
+                policy.push(rule)?;
+            }
+        }
+        Ok(policy)
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        if let Some(ref path) = self.path {
+            let file = PolicyFile { rule: self.rules() };
+            fs::write(path, toml::to_string(&file).expect("can not serialize policy"))?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, rule: Rule) -> Result<(), Error> {
+        let regex = match rule.target {
+            Target::Digest => {
+                sha256::Hash::from_str(rule.value.as_str()).map_err(|_| Error::Policy(format!("malformed digest {}", rule.value)))?;
+                None
+            },
+            Target::Publisher => {
+                PublicKey::from_str(rule.value.as_str()).map_err(|_| Error::Policy(format!("malformed publisher {}", rule.value)))?;
+                None
+            },
+            Target::Category => None,
+            Target::Abstract => Some(Regex::new(rule.value.as_str()).map_err(|e| Error::Policy(e.to_string()))?)
+        };
+        if !self.rules.iter().any(|(r, _)| *r == rule) {
+            self.rules.push((rule, regex));
+        }
+        Ok(())
+    }
+
+    /// add a rule and save to the policy file
+    pub fn add(&mut self, rule: Rule) -> Result<(), Error> {
+        self.push(rule)?;
+        self.save()
+    }
+
+    /// remove a rule and save to the policy file, returns false if the rule was not known
+    pub fn remove(&mut self, rule: &Rule) -> Result<bool, Error> {
+        let len = self.rules.len();
+        self.rules.retain(|(r, _)| r != rule);
+        if self.rules.len() == len {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn rules(&self) -> Vec<Rule> {
+        self.rules.iter().map(|(r, _)| r.clone()).collect()
+    }
+
+    /// the strictest action of rules matching the content, None if it is accepted
+    pub fn check(&self, digest: &sha256::Hash, publisher: &PublicKey, cat: &str, abs: &str) -> Option<Action> {
+        self.rules.iter().filter(|(rule, regex)|
+            match rule.target {
+                Target::Digest => sha256::Hash::from_str(rule.value.as_str()).ok() == Some(*digest),
+                Target::Publisher => PublicKey::from_str(rule.value.as_str()).ok().as_ref() == Some(publisher),
+                Target::Category => rule.value == cat,
+                Target::Abstract => regex.as_ref().map_or(false, |r| r.is_match(abs))
+            }).map(|(rule, _)| rule.action).max()
+    }
+
+    /// true if the content with this digest is rejected regardless of its other properties
+    pub fn rejects_digest(&self, digest: &sha256::Hash) -> bool {
+        self.rules.iter().any(|(rule, _)| rule.action == Action::Reject && rule.target == Target::Digest &&
+            sha256::Hash::from_str(rule.value.as_str()).ok() == Some(*digest))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin_hashes::Hash;
+
+    #[test]
+    fn test_policy () {
+        let publisher = PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        let digest = sha256::Hash::hash(b"ad");
+        let mut policy = Policy::default();
+        assert!(policy.check(&digest, &publisher, "exchange", "BTC/USD").is_none());
+        policy.add(Rule { target: Target::Abstract, value: "(?i)usd".to_string(), action: Action::Hide }).unwrap();
+        assert_eq!(policy.check(&digest, &publisher, "exchange", "BTC/USD"), Some(Action::Hide));
+        assert!(policy.check(&digest, &publisher, "exchange", "BTC/EUR").is_none());
+        policy.add(Rule { target: Target::Publisher, value: publisher.to_string(), action: Action::Reject }).unwrap();
+        assert_eq!(policy.check(&digest, &publisher, "exchange", "BTC/USD"), Some(Action::Reject));
+        assert!(!policy.rejects_digest(&digest));
+        policy.add(Rule { target: Target::Digest, value: digest.to_string(), action: Action::Reject }).unwrap();
+        assert!(policy.rejects_digest(&digest));
+        assert!(policy.add(Rule { target: Target::Abstract, value: "(".to_string(), action: Action::Hide }).is_err());
+        assert!(policy.add(Rule { target: Target::Publisher, value: "00".to_string(), action: Action::Hide }).is_err());
+        assert_eq!(policy.rules().len(), 3);
+        assert!(!policy.remove(&Rule { target: Target::Category, value: "exchange".to_string(), action: Action::Hide }).unwrap());
+        assert!(policy.remove(&Rule { target: Target::Digest, value: digest.to_string(), action: Action::Reject }).unwrap());
+        assert_eq!(policy.rules().len(), 2);
+    }
+}
@@ -0,0 +1,184 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! schemas of structured abstracts
+//!
+//! A category may declare a schema, a subset of JSON Schema:
+//! {"type":"object","properties":{"pair":{"type":"string"},"rate":{"type":"number"}},"required":["pair"]}
+//! Abstracts of publications in that category must then be JSON objects conforming to it.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::str::FromStr;
+use crate::error::Error;
+
+/// type of a field of a structured abstract
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Boolean
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Property {
+    #[serde(rename = "type")]
+    pub field_type: FieldType
+}
+
+/// declared schema of abstracts in a category
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Schema {
+    #[serde(rename = "type")]
+    pub schema_type: String,
+    pub properties: BTreeMap<String, Property>,
+    #[serde(default)]
+    pub required: Vec<String>
+}
+
+impl FromStr for Schema {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Schema, Error> {
+        let schema = serde_json::from_str::<Schema>(s).map_err(|_| Error::Schema("malformed schema".to_string()))?;
+        if schema.schema_type != "object" {
+            return Err(Error::Schema("schema type must be object".to_string()));
+        }
+        if let Some(r) = schema.required.iter().find(|r| !schema.properties.contains_key(r.as_str())) {
+            return Err(Error::Schema(format!("required field {} is not a property", r)));
+        }
+        Ok(schema)
+    }
+}
+
+impl Schema {
+    /// check that an abstract conforms to this schema
+    pub fn validate(&self, abs: &str) -> Result<(), Error> {
+        let value = serde_json::from_str::<Value>(abs).map_err(|_| Error::Schema("abstract is not JSON".to_string()))?;
+        let object = value.as_object().ok_or_else(|| Error::Schema("abstract is not a JSON object".to_string()))?;
+        if let Some(r) = self.required.iter().find(|r| !object.contains_key(r.as_str())) {
+            return Err(Error::Schema(format!("abstract misses required field {}", r)));
+        }
+        for (name, property) in &self.properties {
+            if let Some(v) = object.get(name) {
+                let ok = match property.field_type {
+                    FieldType::String => v.is_string(),
+                    FieldType::Number => v.is_number(),
+                    FieldType::Boolean => v.is_boolean()
+                };
+                if !ok {
+                    return Err(Error::Schema(format!("field {} of abstract is not a {:?}", name, property.field_type)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operator {
+    Eq, Ne, Lt, Le, Gt, Ge
+}
+
+/// a condition on a field of structured abstracts e.g. rate < 0.5
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    pub operator: Operator,
+    pub value: Value
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Condition, Error> {
+        // longer operators first so <= is not read as <
+        const OPERATORS: [(&str, Operator); 6] = [
+            ("==", Operator::Eq), ("!=", Operator::Ne), ("<=", Operator::Le),
+            (">=", Operator::Ge), ("<", Operator::Lt), (">", Operator::Gt)];
+        for (token, operator) in OPERATORS.iter() {
+            if let Some(pos) = s.find(token) {
+                let field = s[..pos].trim().to_string();
+                let value = s[pos + token.len()..].trim();
+                if field.is_empty() || value.is_empty() {
+                    break;
+                }
+                let value = if value.len() > 1 && value.starts_with('"') && value.ends_with('"') {
+                    Value::String(value[1..value.len()-1].to_string())
+                } else {
+                    // numbers and booleans, anything else is a string
+                    serde_json::from_str::<Value>(value).ok()
+                        .filter(|v| v.is_number() || v.is_boolean())
+                        .unwrap_or_else(|| Value::String(value.to_string()))
+                };
+                return Ok(Condition { field, operator: *operator, value });
+            }
+        }
+        Err(Error::Schema(format!("malformed condition {}", s)))
+    }
+}
+
+impl Condition {
+    /// true if the abstract is a JSON object with the field satisfying this condition
+    pub fn matches(&self, abs: &Value) -> bool {
+        if let Some(v) = abs.get(self.field.as_str()) {
+            let ordering = match (v, &self.value) {
+                (Value::Number(a), Value::Number(b)) => a.as_f64().partial_cmp(&b.as_f64()),
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+                _ => None
+            };
+            if let Some(ordering) = ordering {
+                return match self.operator {
+                    Operator::Eq => ordering == Ordering::Equal,
+                    Operator::Ne => ordering != Ordering::Equal,
+                    Operator::Lt => ordering == Ordering::Less,
+                    Operator::Le => ordering != Ordering::Greater,
+                    Operator::Gt => ordering == Ordering::Greater,
+                    Operator::Ge => ordering != Ordering::Less
+                };
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_schema () {
+        let schema = Schema::from_str(r#"{"type":"object","properties":{"pair":{"type":"string"},"rate":{"type":"number"}},"required":["pair"]}"#).unwrap();
+        assert!(schema.validate(r#"{"pair":"BTC/USD","rate":9000.5}"#).is_ok());
+        assert!(schema.validate(r#"{"pair":"BTC/USD"}"#).is_ok());
+        assert!(schema.validate(r#"{"rate":9000.5}"#).is_err());
+        assert!(schema.validate(r#"{"pair":"BTC/USD","rate":"high"}"#).is_err());
+        assert!(schema.validate("BTC/USD").is_err());
+        assert!(Schema::from_str(r#"{"type":"object","properties":{},"required":["pair"]}"#).is_err());
+
+        let abs = serde_json::from_str::<Value>(r#"{"pair":"BTC/USD","rate":9000.5}"#).unwrap();
+        assert!(Condition::from_str("pair == BTC/USD").unwrap().matches(&abs));
+        assert!(Condition::from_str("pair == \"BTC/USD\"").unwrap().matches(&abs));
+        assert!(Condition::from_str("rate < 10000").unwrap().matches(&abs));
+        assert!(Condition::from_str("rate>=9000.5").unwrap().matches(&abs));
+        assert!(!Condition::from_str("rate > 9000.5").unwrap().matches(&abs));
+        assert!(!Condition::from_str("fee < 1").unwrap().matches(&abs));
+        assert!(Condition::from_str("rate <").is_err());
+    }
+}
@@ -16,23 +16,33 @@
 
 //! store
 
-use bitcoin::{BlockHeader, BitcoinHash, Block, Address, PublicKey, Script, Transaction};
+use bitcoin::{BlockHeader, BitcoinHash, Block, Address, PublicKey, Script, Transaction, OutPoint, TxOut};
+use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin_hashes::{sha256, sha256d, Hash};
-use std::sync::{RwLock, Arc};
+use std::sync::{RwLock, Arc, Mutex};
 
 use crate::error::Error;
 use crate::content::Content;
-use crate::db::{SharedDB, RetrievedContent};
+use crate::db::{SharedDB, TX, RetrievedContent, ListedAbstract, PublisherStats, Page, Paged, Selection};
 use crate::iblt::IBLT;
 use crate::content::ContentKey;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use lru_cache::LruCache;
 use crate::iblt::add_to_min_sketch;
 use crate::trunk::Trunk;
-use crate::wallet::Wallet;
+use crate::wallet::{Wallet, Funding, Unspent, MAX_TERM};
+use crate::adspace::{Offer, Rental, RENT_CONFIRMATIONS, RENT_PAYMENT_WINDOW, MAX_OPEN_RENTALS};
+use crate::fee::{self, FeeEstimator, FeeEstimate};
 use bitcoin::network::message::NetworkMessage;
 use murmel::p2p::{PeerMessageSender, PeerMessage};
 use crate::ad::Ad;
+use crate::schema::{Schema, Condition};
+use crate::policy::{Policy, Rule, Action};
+use crate::subscription::{SharedSubscriptions, Subscriptions, EventKind};
+use crate::messages::{Message, RevokeMessage};
+use secp256k1::{Secp256k1, VerifyOnly, Signature};
 use bitcoin_wallet::context::SecpContext;
 use bitcoin_wallet::proved::ProvedTransaction;
 use bitcoin::{
@@ -44,29 +54,47 @@ use bitcoin::{
 };
 
 const MIN_SKETCH_SIZE: usize = 20;
+// blocks a commitment address is watched for a payment
+const COMMITMENT_WATCH: u32 = 1008;
+// digests of content rejected by policy remembered
+const REJECTED_CACHE_SIZE: usize = 10000;
 
 pub type SharedContentStore = Arc<RwLock<ContentStore>>;
 
 /// the distributed content storage
 pub struct ContentStore {
     ctx: Arc<SecpContext>,
+    secp: Secp256k1<VerifyOnly>,
     trunk: Arc<dyn Trunk + Send + Sync>,
     db: SharedDB,
     storage_limit: u64,
+    // bytes of the storage limit reserved for categories
+    quotas: HashMap<String, u64>,
     iblts: HashMap<u32, IBLT<ContentKey>>,
     min_sketch: Vec<u64>,
     ksequence: Vec<(u64, u64)>,
     n_keys: u32,
     wallet: Wallet,
-    txout: Option<PeerMessageSender<NetworkMessage>>
+    txout: Option<PeerMessageSender<NetworkMessage>>,
+    updater: Option<PeerMessageSender<Message>>,
+    subscriptions: SharedSubscriptions,
+    // passphrase and fee per vbyte to sweep matured funding with, as blocks arrive
+    auto_sweep: Option<(String, u64)>,
+    // passphrase and fee per vbyte to fund rented ad space
+    auto_rent: Option<(String, u64)>,
+    policy: Policy,
+    // content rejected by policy, not to be fetched again from peers
+    rejected: LruCache<sha256::Hash, ()>,
+    fee_estimator: FeeEstimator
 }
 
 impl ContentStore {
     /// new content store
-    pub fn new(db: SharedDB, storage_limit: u64, trunk: Arc<dyn Trunk + Send + Sync>, wallet: Wallet) -> Result<ContentStore, Error> {
+    pub fn new(db: SharedDB, storage_limit: u64, trunk: Arc<dyn Trunk + Send + Sync>, mut wallet: Wallet) -> Result<ContentStore, Error> {
         let mins;
         let ksequence;
         let n_keys;
+        let feerates;
         {
             let mut db = db.lock().unwrap();
             let mut tx = db.transaction();
@@ -74,18 +102,31 @@ impl ContentStore {
             mins = m;
             ksequence = k;
             n_keys = n;
+            feerates = tx.read_feerates()?;
+            for point in tx.read_locked()? {
+                wallet.lock(point);
+            }
         }
         Ok(ContentStore {
             ctx: Arc::new(SecpContext::new()),
+            secp: Secp256k1::verification_only(),
             trunk,
             db,
             storage_limit,
+            quotas: HashMap::new(),
             iblts: HashMap::new(),
             min_sketch: mins,
             ksequence,
             n_keys,
             wallet,
-            txout: None
+            txout: None,
+            updater: None,
+            subscriptions: Arc::new(Mutex::new(Subscriptions::new())),
+            auto_sweep: None,
+            auto_rent: None,
+            policy: Policy::default(),
+            rejected: LruCache::new(REJECTED_CACHE_SIZE),
+            fee_estimator: FeeEstimator::new(feerates)
         })
     }
 
@@ -93,8 +134,148 @@ impl ContentStore {
         self.txout = Some(txout);
     }
 
-    pub fn balance(&self) -> Vec<u64> {
-        vec!(self.wallet.balance(), self.wallet.available_balance(self.trunk.len(), |h| self.trunk.get_height(h)))
+    pub fn set_updater(&mut self, updater: PeerMessageSender<Message>) {
+        self.updater = Some(updater);
+    }
+
+    /// reserve bytes of the storage limit for categories, the rest is shared by all categories
+    pub fn set_quotas(&mut self, quotas: HashMap<String, u64>) -> Result<(), Error> {
+        if quotas.values().sum::<u64>() > self.storage_limit {
+            return Err(Error::Unsupported("category quotas exceed the storage limit"));
+        }
+        self.quotas = quotas;
+        Ok(())
+    }
+
+    pub fn set_policy(&mut self, policy: Policy) -> Result<(), Error> {
+        self.policy = policy;
+        self.apply_policy()
+    }
+
+    pub fn policy_rules(&self) -> Vec<Rule> {
+        self.policy.rules()
+    }
+
+    pub fn add_policy_rule(&mut self, rule: Rule) -> Result<(), Error> {
+        self.policy.add(rule)?;
+        self.apply_policy()
+    }
+
+    pub fn remove_policy_rule(&mut self, rule: &Rule) -> Result<bool, Error> {
+        if self.policy.remove(rule)? {
+            self.apply_policy()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // stored content the policy objects to is hidden, it is kept until it expires
+    // so the store remains in sync with peers
+    fn apply_policy(&mut self) -> Result<(), Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        tx.clear_hidden()?;
+        for (digest, publisher, cat, abs) in tx.list_policy_subjects()? {
+            if self.policy.check(&digest, &publisher, cat.as_str(), abs.as_str()).is_some() {
+                tx.store_hidden(&digest)?;
+            }
+        }
+        tx.commit();
+        self.rejected.clear();
+        Ok(())
+    }
+
+    /// true if content with this digest should not be fetched from peers
+    pub fn is_rejected(&mut self, digest: &sha256::Hash) -> bool {
+        self.rejected.contains_key(digest) || self.policy.rejects_digest(digest)
+    }
+
+    /// true if the content should not be relayed to peers
+    pub fn rejects(&self, content: &Content) -> bool {
+        self.policy.check(&content.ad.digest(), &content.funder, content.ad.cat.as_str(), content.ad.abs.as_str()) == Some(Action::Reject)
+    }
+
+    pub fn subscriptions(&self) -> SharedSubscriptions {
+        self.subscriptions.clone()
+    }
+
+    pub fn balance(&self) -> Balance {
+        Balance { balance: self.wallet.balance(), available: self.wallet.available_balance(self.trunk.len(), |h| self.trunk.get_height(h)) }
+    }
+
+    /// transactions of the wallet, unconfirmed first then the latest first
+    pub fn transactions(&self) -> Result<Vec<WalletTransaction>, Error> {
+        let stored = {
+            let mut db = self.db.lock().unwrap();
+            let tx = db.transaction();
+            tx.read_txouts()?
+        };
+        let scripts = self.wallet.master.get_scripts().map(|(s, _)| s).collect::<HashSet<_>>();
+        let proofs = self.wallet.coins().proofs();
+        let mut known = stored.iter().map(|s| (s.transaction.txid(), s.transaction.clone())).collect::<HashMap<_, _>>();
+        for proof in proofs.values() {
+            let transaction = proof.get_transaction();
+            known.entry(transaction.txid()).or_insert(transaction);
+        }
+        let height = |txid: &sha256d::Hash, block: Option<sha256d::Hash>|
+            block.or_else(|| self.wallet.prove(txid).map(|p| *p.get_block_hash())).and_then(|b| self.trunk.get_height(&b));
+        let mut transactions = stored.iter().map(|s| {
+            let txid = s.transaction.txid();
+            let received = s.transaction.output.iter().filter(|o| scripts.contains(&o.script_pubkey)).map(|o| o.value).sum::<u64>();
+            let sent = s.transaction.output.iter().map(|o| o.value).sum::<u64>();
+            // value of coins of the wallet spent, transactions sent by earlier versions did not store it
+            let spent = match s.spent {
+                Some(ref spent) => Some(spent.iter().map(|o| o.value).sum::<u64>()),
+                None => s.transaction.input.iter().map(|i|
+                    known.get(&i.previous_output.txid).map(|t| t.output.get(i.previous_output.vout as usize)
+                        .filter(|o| scripts.contains(&o.script_pubkey)).map_or(0, |o| o.value)))
+                    .sum::<Option<u64>>()
+            };
+            let kind = if s.funding.is_some() { TransactionKind::Funding }
+                else if spent == Some(0) { TransactionKind::Deposit }
+                else if received == sent { TransactionKind::Internal }
+                else { TransactionKind::Withdrawal };
+            let height = height(&txid, s.confirmed);
+            WalletTransaction {
+                txid: txid.to_string(),
+                kind,
+                height,
+                confirmed: height.is_some(),
+                delta: spent.map(|spent| received as i64 - spent as i64),
+                fee: spent.filter(|spent| *spent > 0).map(|spent| spent.saturating_sub(sent)),
+                id: s.funding.as_ref().map(|(_, id, _)| id.to_string())
+            }
+        }).collect::<Vec<_>>();
+        // received before transactions of the wallet were recorded
+        for proof in proofs.values() {
+            let transaction = proof.get_transaction();
+            let txid = transaction.txid();
+            if stored.iter().all(|s| s.transaction.txid() != txid) {
+                let height = height(&txid, Some(*proof.get_block_hash()));
+                transactions.push(WalletTransaction {
+                    txid: txid.to_string(),
+                    kind: TransactionKind::Deposit,
+                    height,
+                    confirmed: height.is_some(),
+                    delta: Some(transaction.output.iter().filter(|o| scripts.contains(&o.script_pubkey)).map(|o| o.value).sum::<u64>() as i64),
+                    fee: None,
+                    id: None
+                });
+            }
+        }
+        transactions.sort_by_key(|t| std::cmp::Reverse(t.height.unwrap_or(std::u32::MAX)));
+        Ok(transactions)
+    }
+
+    /// fee estimate to confirm within target blocks, None if too few blocks were seen yet
+    pub fn estimate_fee(&self, target: u16) -> Option<FeeEstimate> {
+        self.fee_estimator.estimate(target)
+    }
+
+    /// estimated fee per vbyte to confirm within target blocks
+    pub fn fee_for_target(&self, target: u16) -> Result<u64, Error> {
+        self.fee_estimator.estimate(target).map(|e| e.fee_per_vbyte)
+            .ok_or(Error::Unsupported("no fee estimate yet, give fee_per_vbyte"))
     }
 
     pub fn deposit_address(&mut self) -> Address {
@@ -114,21 +295,49 @@ impl ContentStore {
         tx.list_publication().expect("can not list publications")
     }
 
-    pub fn prepare_publication(&mut self, cat: String, abs: String, content: String) -> sha256::Hash {
+    pub fn prepare_publication(&mut self, cat: String, abs: String, content: String) -> Result<sha256::Hash, Error> {
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
+        if let Some(schema) = tx.read_schema(cat.as_str())? {
+            schema.validate(abs.as_str())?;
+        }
         let id = tx.prepare_publication(&Ad::new(cat, abs, content.as_str())).expect("can not store publication");
         tx.commit();
-        id
+        Ok(id)
     }
 
-    pub fn fund (&mut self, id: &sha256::Hash, term: u16, amount: u64, fee_per_vbyte: u64, passpharse: String) -> Result<(Transaction, PublicKey, u64), Error> {
-        let (transaction, funder,fee) = self.wallet.fund(id, term, passpharse, fee_per_vbyte, amount, self.trunk.clone(),
-            |pk, term| Self::funding_script(pk, term.unwrap()))?;
+    /// declare the schema of abstracts in a category, or remove it if None
+    pub fn set_schema(&mut self, cat: &str, schema: Option<Schema>) -> Result<(), Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        if let Some(schema) = schema {
+            tx.store_schema(cat, &schema)?;
+        }
+        else {
+            tx.delete_schema(cat)?;
+        }
+        tx.commit();
+        Ok(())
+    }
+
+    pub fn read_schema(&self, cat: &str) -> Result<Option<Schema>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.read_schema(cat)
+    }
+
+    pub fn fund (&mut self, id: &sha256::Hash, term: u16, amount: u64, fee_per_vbyte: u64, inputs: Option<Vec<OutPoint>>, passpharse: String) -> Result<(Transaction, PublicKey, u64), Error> {
+        let (contract_address, funder) = self.wallet.commit(id, term, |pk, term| Self::funding_script(pk, term.unwrap()));
+        let (transaction, spent, fee) = self.wallet.fund(&contract_address, passpharse, fee_per_vbyte, amount, inputs, self.trunk.clone())?;
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
         tx.store_account(&self.wallet.master.get((1,0)).unwrap())?;
-        tx.store_txout(&transaction, Some((&funder, id, term))).expect("can not store outgoing transaction");
+        if self.wallet.is_watch_only() {
+            Self::hold(&mut self.wallet, &mut tx, &transaction, &spent, Some((&funder, id, term)))?;
+            tx.commit();
+            return Ok((transaction, funder, fee));
+        }
+        tx.store_txout(&transaction, Some((&funder, id, term)), &spent).expect("can not store outgoing transaction");
         tx.commit();
         if let Some(ref txout) = self.txout {
             txout.send(PeerMessage::Outgoing(NetworkMessage::Tx(transaction.clone())));
@@ -137,6 +346,179 @@ impl ContentStore {
         Ok((transaction, funder, fee))
     }
 
+    pub fn renew (&mut self, id: &sha256::Hash, term: u16, fee_per_vbyte: u64, passpharse: String) -> Result<(Transaction, PublicKey, u64), Error> {
+        let (transaction, spent, funder, fee) = self.wallet.renew(id, term, passpharse, fee_per_vbyte, self.trunk.clone(),
+            |pk, term| Self::funding_script(pk, term.unwrap()))?;
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        tx.store_account(&self.wallet.master.get((1,0)).unwrap())?;
+        tx.store_txout(&transaction, Some((&funder, id, term)), &spent).expect("can not store outgoing transaction");
+        tx.commit();
+        if let Some(ref txout) = self.txout {
+            txout.send(PeerMessage::Outgoing(NetworkMessage::Tx(transaction.clone())));
+        }
+        info!("Renewing publication {} with transaction {}", id, transaction.txid());
+        Ok((transaction, funder, fee))
+    }
+
+    pub fn list_unspent(&self) -> Vec<Unspent> {
+        self.wallet.unspent(self.trunk.clone())
+    }
+
+    /// exclude coins from or return them to automatic selection of inputs, answers the number of coins changed
+    pub fn lock_coins(&mut self, points: &[OutPoint], lock: bool) -> Result<usize, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let mut changed = 0;
+        for point in points {
+            if lock {
+                if self.wallet.lock(*point) {
+                    tx.store_locked(point)?;
+                    changed += 1;
+                }
+            } else if self.wallet.unlock(point) {
+                tx.delete_locked(point)?;
+                changed += 1;
+            }
+        }
+        tx.commit();
+        Ok(changed)
+    }
+
+    pub fn list_funded(&self) -> Vec<Funding> {
+        self.wallet.funded(self.trunk.clone())
+    }
+
+    pub fn sweep (&mut self, passpharse: String, fee_per_vbyte: u64) -> Result<(Transaction, u64), Error> {
+        let (transaction, spent, fee) = self.wallet.sweep(passpharse, fee_per_vbyte, self.trunk.clone())?;
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        tx.store_account(&self.wallet.master.get((0,1)).unwrap())?;
+        tx.store_txout(&transaction, None, &spent).expect("can not store outgoing transaction");
+        tx.commit();
+        if let Some(ref txout) = self.txout {
+            txout.send(PeerMessage::Outgoing(NetworkMessage::Tx(transaction.clone())));
+        }
+        info!("Swept matured funding with transaction {}", transaction.txid());
+        Ok((transaction, fee))
+    }
+
+    /// sweep matured funding whenever a block is connected, the passphrase is only kept in memory
+    pub fn set_auto_sweep(&mut self, auto_sweep: Option<(String, u64)>) -> Result<(), Error> {
+        if let Some((ref passphrase, _)) = auto_sweep {
+            self.wallet.verify_passphrase(passphrase.as_str())?;
+        }
+        self.auto_sweep = auto_sweep;
+        Ok(())
+    }
+
+    /// offer to fund ads of advertisers, returns the id of the publication advertising the offer.
+    /// Rented ad space is funded with the passphrase given with the latest offer, so a watch-only wallet can not offer.
+    pub fn offer_adspace(&mut self, amount: u64, term: u16, price: u64, passphrase: String, fee_per_vbyte: u64) -> Result<sha256::Hash, Error> {
+        if term == 0 || term > MAX_TERM {
+            return Err(Error::Unsupported("term is out of range"));
+        }
+        if amount == 0 || price == 0 {
+            return Err(Error::Unsupported("amount and price must be positive"));
+        }
+        if self.wallet.is_watch_only() {
+            return Err(Error::Unsupported("a watch-only wallet can not fund rented ad space"));
+        }
+        self.wallet.verify_passphrase(passphrase.as_str())?;
+        let ad = Offer::ad(amount, term, price);
+        let id = self.prepare_publication(ad.cat, ad.abs, ad.content.as_string().expect("can not decompress offer"))?;
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        tx.store_offer(&Offer { id: id.to_string(), amount, term, price })?;
+        tx.commit();
+        self.auto_rent = Some((passphrase, fee_per_vbyte));
+        info!("Offering {} satoshis for {} blocks at {} satoshis with publication {}", amount, term, price, id);
+        Ok(id)
+    }
+
+    /// withdraw an offer, ad space rented earlier is still funded as paid
+    pub fn cancel_offer(&mut self, id: &sha256::Hash) -> Result<bool, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let deleted = tx.delete_offer(id.to_string().as_str())? > 0;
+        tx.commit();
+        Ok(deleted)
+    }
+
+    pub fn list_offers(&self) -> Result<Vec<Offer>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.read_offers()
+    }
+
+    /// rent offered ad space for an ad, answers the address to pay the price to within RENT_PAYMENT_WINDOW blocks
+    pub fn rent_adspace(&mut self, offer: &sha256::Hash, cat: String, abs: String, content: String) -> Result<Rental, Error> {
+        let offer = self.list_offers()?.into_iter().find(|o| o.id == offer.to_string())
+            .ok_or(Error::Unsupported("unknown offer"))?;
+        let open = self.list_rentals()?.iter().filter(|r| r.offer == offer.id && r.paid.is_none()).count();
+        if open >= MAX_OPEN_RENTALS {
+            return Err(Error::Unsupported("too many rentals of the offer wait for payment"));
+        }
+        let id = self.prepare_publication(cat, abs, content)?;
+        if self.list_rentals()?.iter().any(|r| r.id == id.to_string()) {
+            return Err(Error::Unsupported("the publication already rents ad space"));
+        }
+        let address = self.deposit_address();
+        let expiry = self.trunk.len().saturating_sub(1) + RENT_PAYMENT_WINDOW;
+        let rental = Rental { id: id.to_string(), offer: offer.id, address: address.to_string(), price: offer.price,
+            amount: offer.amount, term: offer.term, expiry, paid: None, block: None, funded: None, stalled: false };
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        tx.store_account(self.wallet.master.get((0,0)).unwrap())?;
+        tx.store_rental(&rental)?;
+        tx.commit();
+        info!("Publication {} rents ad space of offer {} once {} satoshis are paid to {}", id, rental.offer, rental.price, rental.address);
+        Ok(rental)
+    }
+
+    pub fn list_rentals(&self) -> Result<Vec<Rental>, Error> {
+        let rentals = {
+            let mut db = self.db.lock().unwrap();
+            let tx = db.transaction();
+            tx.read_rentals()?
+        };
+        let height = self.trunk.len().saturating_sub(1);
+        Ok(rentals.into_iter().map(|r| Rental { stalled: self.auto_rent.is_none() && self.is_rent_final(&r, height), .. r }).collect())
+    }
+
+    // payment of rent has enough confirmations at height and the rented ad space is not yet funded
+    fn is_rent_final(&self, rental: &Rental, height: u32) -> bool {
+        rental.funded.is_none() && rental.block.as_ref()
+            .and_then(|b| b.parse::<sha256d::Hash>().ok())
+            .and_then(|b| self.trunk.get_height(&b))
+            .map_or(false, |h| height + 1 >= h + RENT_CONFIRMATIONS)
+    }
+
+    // fund rented ad space paid for with enough confirmations
+    fn fund_rentals(&mut self, height: u32) -> Result<(), Error> {
+        let (passphrase, fee_per_vbyte) = if let Some(ref auto_rent) = self.auto_rent { auto_rent.clone() } else { return Ok(()) };
+        let rentals = self.list_rentals()?.into_iter().filter(|r| self.is_rent_final(r, height)).collect::<Vec<_>>();
+        for mut rental in rentals {
+            let id = rental.id.parse::<sha256::Hash>().expect("stored rental id not hex");
+            match self.fund(&id, rental.term, rental.amount, fee_per_vbyte, None, passphrase.clone()) {
+                Ok((transaction, _, _)) => {
+                    info!("Funding rented publication {} with transaction {}", id, transaction.txid());
+                    rental.funded = Some(transaction.txid().to_string());
+                    let mut db = self.db.lock().unwrap();
+                    let mut tx = db.transaction();
+                    tx.store_rental(&rental)?;
+                    tx.commit();
+                },
+                Err(e) => warn!("failed to fund rented publication {} {:?}", id, e)
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_matured_funding(&self) -> bool {
+        self.wallet.has_matured_funding(self.trunk.clone())
+    }
+
     pub fn funding_script(tweaked: &PublicKey, term: u16) -> Script {
         Builder::new()
             .push_int(term as i64)
@@ -151,12 +533,37 @@ impl ContentStore {
         Address::p2wsh(&Self::funding_script(tweaked, term), Network::Bitcoin)
     }
 
-    pub fn withdraw (&mut self, passpharse: String, address: Address, fee_per_vbyte: u64, amount: Option<u64>) -> Result<(Transaction, u64), Error> {
-        let (transaction, fee) = self.wallet.withdraw(passpharse, address, fee_per_vbyte, amount, self.trunk.clone())?;
+    /// address to pay from an external wallet to fund a prepared publication by publisher for term blocks.
+    /// The publication is published as the payment confirms.
+    pub fn commitment_address (&mut self, id: &sha256::Hash, publisher: &PublicKey, term: u16) -> Result<Address, Error> {
+        if term == 0 || term > MAX_TERM {
+            return Err(Error::Unsupported("term is out of range"));
+        }
+        if self.read_prepared(id).is_none() {
+            return Err(Error::Unsupported("unknown publication"));
+        }
+        let mut tweaked = *publisher;
+        self.ctx.tweak_exp_add(&mut tweaked, &id[..])?;
+        let address = Address::p2wsh(&Self::funding_script(&tweaked, term), self.wallet.master.master_public().network);
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        tx.store_commitment(&address.script_pubkey(), id, publisher, term, self.trunk.len() + COMMITMENT_WATCH)?;
+        tx.commit();
+        info!("Watching for funding of publication {} at {}", id, address);
+        Ok(address)
+    }
+
+    pub fn withdraw (&mut self, passpharse: String, address: Address, fee_per_vbyte: u64, amount: Option<u64>, inputs: Option<Vec<OutPoint>>) -> Result<(Transaction, u64), Error> {
+        let (transaction, spent, fee) = self.wallet.withdraw(passpharse, address, fee_per_vbyte, amount, inputs, self.trunk.clone())?;
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
         tx.store_account(&self.wallet.master.get((0,1)).unwrap())?;
-        tx.store_txout(&transaction, None).expect("can not store outgoing transaction");
+        if self.wallet.is_watch_only() {
+            Self::hold(&mut self.wallet, &mut tx, &transaction, &spent, None)?;
+            tx.commit();
+            return Ok((transaction, fee));
+        }
+        tx.store_txout(&transaction, None, &spent).expect("can not store outgoing transaction");
         tx.commit();
         if let Some(ref txout) = self.txout {
             txout.send(PeerMessage::Outgoing(NetworkMessage::Tx(transaction.clone())));
@@ -165,6 +572,100 @@ impl ContentStore {
         Ok((transaction, fee))
     }
 
+    /// words of the BIP39 mnemonic of the wallet's key
+    pub fn reveal_mnemonic(&self, passphrase: &str) -> Result<String, Error> {
+        Ok(self.wallet.mnemonic(passphrase)?.to_string())
+    }
+
+    /// true if transactions of the wallet are signed elsewhere
+    pub fn is_watch_only(&self) -> bool {
+        self.wallet.is_watch_only()
+    }
+
+    // keep an unsigned transaction of a watch-only wallet until it is signed, its inputs are locked meanwhile
+    fn hold(wallet: &mut Wallet, tx: &mut TX, transaction: &Transaction, spent: &[TxOut], funding: Option<(&PublicKey, &sha256::Hash, u16)>) -> Result<(), Error> {
+        let psbt = wallet.psbt(transaction, spent)?;
+        tx.store_psbt(&psbt, funding)?;
+        for input in &transaction.input {
+            wallet.lock(input.previous_output);
+            tx.store_locked(&input.previous_output)?;
+        }
+        info!("Transaction {} waits to be signed", transaction.txid());
+        Ok(())
+    }
+
+    /// a transaction of the watch-only wallet waiting to be signed
+    pub fn read_psbt(&self, txid: &sha256d::Hash) -> Result<Option<PartiallySignedTransaction>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        Ok(tx.read_psbt(txid)?.map(|(psbt, _)| psbt))
+    }
+
+    /// finalize a transaction of the watch-only wallet signed elsewhere and send it
+    pub fn finalize_and_send(&mut self, psbt: PartiallySignedTransaction) -> Result<Transaction, Error> {
+        let txid = psbt.global.unsigned_tx.txid();
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let (mut pending, funding) = tx.read_psbt(&txid)?
+            .ok_or(Error::Unsupported("no transaction of this wallet waits to be signed with this id"))?;
+        let spent = pending.inputs.iter().map(|i| i.witness_utxo.clone())
+            .collect::<Option<Vec<TxOut>>>().expect("stored psbt without spent outputs");
+        pending.merge(psbt).map_err(|_| Error::Unsupported("psbt does not match the transaction waiting to be signed"))?;
+        let transaction = self.wallet.finalize(pending, &spent)?;
+        tx.delete_psbt(&txid)?;
+        for input in &transaction.input {
+            self.wallet.unlock(&input.previous_output);
+            tx.delete_locked(&input.previous_output)?;
+        }
+        tx.store_txout(&transaction, funding.as_ref().map(|(p, id, term)| (p, id, *term)), &spent).expect("can not store outgoing transaction");
+        tx.commit();
+        if let Some(ref txout) = self.txout {
+            txout.send(PeerMessage::Outgoing(NetworkMessage::Tx(transaction.clone())));
+        }
+        info!("Sending signed transaction {}", transaction.txid());
+        info!("Wallet balance: {} satoshis {} available", self.wallet.balance(), self.wallet.available_balance(self.trunk.len(), |h| self.trunk.get_height(h)));
+        Ok(transaction)
+    }
+
+    /// forget a transaction of the watch-only wallet waiting to be signed and return its inputs to selection,
+    /// false if no transaction waits with this id
+    pub fn discard_psbt(&mut self, txid: &sha256d::Hash) -> Result<bool, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let (pending, _) = if let Some(pending) = tx.read_psbt(txid)? { pending } else { return Ok(false) };
+        tx.delete_psbt(txid)?;
+        for input in &pending.global.unsigned_tx.input {
+            self.wallet.unlock(&input.previous_output);
+            tx.delete_locked(&input.previous_output)?;
+        }
+        tx.commit();
+        info!("Discarded transaction {} waiting to be signed", txid);
+        Ok(true)
+    }
+
+    /// replace an unconfirmed outgoing transaction with one paying a higher fee, keeping its publication
+    pub fn bump_fee (&mut self, txid: &sha256d::Hash, fee_per_vbyte: u64, passpharse: String) -> Result<(Transaction, u64), Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let (replaced, funding, spent) = tx.read_unconfirmed_txout(txid)?
+            .ok_or(Error::Unsupported("no unconfirmed transaction of this wallet with this id"))?;
+        let spent = spent.ok_or(Error::Unsupported("outputs spent by the transaction are not known"))?;
+        if tx.read_unconfirmed()?.iter().any(|(t, _)| t.input.iter().any(|i| i.previous_output.txid == *txid)) {
+            return Err(Error::Unsupported("an unconfirmed transaction spends from this transaction"));
+        }
+        let (transaction, fee) = self.wallet.bump_fee(passpharse, &replaced, &spent, fee_per_vbyte)?;
+        tx.delete_txout(txid)?;
+        tx.store_txout(&transaction, funding.as_ref().map(|(p, id, term)| (p, id, *term)), &spent)?;
+        let coins = tx.read_coins(&mut self.wallet.master)?;
+        self.wallet.set_coins(coins);
+        tx.commit();
+        if let Some(ref txout) = self.txout {
+            txout.send(PeerMessage::Outgoing(NetworkMessage::Tx(transaction.clone())));
+        }
+        info!("Replaced transaction {} with {} paying {} satoshis fee", txid, transaction.txid(), fee);
+        Ok((transaction, fee))
+    }
+
     pub fn get_nkeys (&self) -> u32 {
         self.n_keys
     }
@@ -188,7 +689,7 @@ impl ContentStore {
 
     pub fn block_connected(&mut self, block: &Block, height: u32) -> Result<(), Error> {
         debug!("processing block {} {}", height, block.header.bitcoin_hash());
-        let newly_confirmed_publication;
+        let mut newly_confirmed_publication;
         {
             let mut db = self.db.lock().unwrap();
             let mut tx = db.transaction();
@@ -203,13 +704,77 @@ impl ContentStore {
 
             newly_confirmed_publication.iter().for_each(|(_,_,id,_,_)| info!("Our publication {} is confirmed.", id));
 
-            if self.wallet.process(block) {
+            // publications funded from outside the wallet through a commitment address
+            for (script, id, publisher, term) in tx.read_commitments()? {
+                if let Some((tix, _)) = block.txdata.iter().enumerate().find(|(_, t)| t.output.iter().any(|o| o.script_pubkey == script)) {
+                    info!("Commitment to publication {} is confirmed.", id);
+                    newly_confirmed_publication.push((tix, publisher, id, tx.read_publication(&id)?, term));
+                    tx.confirm_commitment(&script, &block.bitcoin_hash(), height + term as u32)?;
+                }
+            }
+
+            // our transactions double spent by the block, such as those replaced by a higher fee
+            let spent_in_block = block.txdata.iter().flat_map(|t| t.input.iter().map(|i| i.previous_output)).collect::<HashSet<_>>();
+            let conflicts = tx.read_unconfirmed()?.iter()
+                .filter(|(t, _)| !block.txdata.iter().any(|c| c.txid() == t.txid()) && t.input.iter().any(|i| spent_in_block.contains(&i.previous_output)))
+                .map(|(t, _)| t.txid()).collect::<Vec<_>>();
+
+            if self.wallet.process(block) || !conflicts.is_empty() {
                 tx.store_coins(&self.wallet.coins())?;
+                for txid in &conflicts {
+                    info!("Our transaction {} is double spent by the block", txid);
+                    tx.delete_txout(txid)?;
+                }
+                if !conflicts.is_empty() {
+                    let coins = tx.read_coins(&mut self.wallet.master)?;
+                    self.wallet.set_coins(coins);
+                }
                 info!("New wallet balance {} satoshis {} available", self.wallet.balance(), self.wallet.available_balance(self.trunk.len(), |h| self.trunk.get_height(h)));
             }
+
+            // locks of coins spent by the block are no longer needed
+            let spent_locked = self.wallet.locked().iter().filter(|p| spent_in_block.contains(p)).cloned().collect::<Vec<_>>();
+            for point in &spent_locked {
+                self.wallet.unlock(point);
+                tx.delete_locked(point)?;
+            }
+            // record transactions of the wallet for its history, also those received
+            let own = tx.read_unconfirmed()?.iter().map(|(t, _)| t.txid()).collect::<HashSet<_>>();
+            let scripts = self.wallet.master.get_scripts().map(|(s, _)| s).collect::<HashSet<_>>();
+            for transaction in &block.txdata {
+                if own.contains(&transaction.txid()) || transaction.output.iter().any(|o| scripts.contains(&o.script_pubkey)) {
+                    tx.confirm_txout(transaction, &block.header.bitcoin_hash())?;
+                }
+            }
+            // payments for rented ad space, rentals not paid in time are dropped
+            for mut rental in tx.read_rentals()?.into_iter().filter(|r| r.paid.is_none()) {
+                let script = rental.address.parse::<Address>().expect("stored rental address malformed").script_pubkey();
+                if let Some(payment) = block.txdata.iter()
+                    .find(|t| t.output.iter().filter(|o| o.script_pubkey == script).map(|o| o.value).sum::<u64>() >= rental.price) {
+                    info!("Rent of publication {} is paid with transaction {}", rental.id, payment.txid());
+                    rental.paid = Some(payment.txid().to_string());
+                    rental.block = Some(block.header.bitcoin_hash().to_string());
+                    tx.store_rental(&rental)?;
+                }
+            }
+            tx.delete_unpaid_rentals(height)?;
+            if let Some(feerates) = self.fee_estimator.block_connected(block, height) {
+                tx.store_feerates(&feerates)?;
+                tx.delete_feerates_before(height.saturating_sub(fee::WINDOW))?;
+            }
             tx.store_processed(&block.header.bitcoin_hash())?;
             tx.commit();
         }
+        if let Err(e) = self.fund_rentals(height) {
+            warn!("failed to fund rented ad space {:?}", e);
+        }
+        if let Some((passphrase, fee_per_vbyte)) = self.auto_sweep.clone() {
+            if self.wallet.has_matured_funding(self.trunk.clone()) {
+                if let Err(e) = self.sweep(passphrase, fee_per_vbyte) {
+                    warn!("failed to sweep matured funding {:?}", e);
+                }
+            }
+        }
         for (tix, funder, id, ad, term) in newly_confirmed_publication {
             if let Some(ad) = ad {
                 let funding = ProvedTransaction::new(block, tix);
@@ -225,17 +790,17 @@ impl ContentStore {
     /// add a header to the tip of the chain
     pub fn add_header(&mut self, height: u32, header: &BlockHeader) -> Result<(), Error> {
         info!("new chain tip at height {} {}", height, header.bitcoin_hash());
-        let mut deleted_some = false;
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
-        for key in &mut tx.delete_expired(height)? {
-            debug!("delete expired content {}", sha256::Hash::from_slice(&key.digest[..]).unwrap());
-            for (_, i) in &mut self.iblts {
-                i.delete(key);
-                deleted_some = true;
-            }
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let (deleted, changed) = tx.delete_expired(height)?;
+        for deleted in &deleted {
+            debug!("delete expired content {}", deleted.id);
+            subscriptions.notify(EventKind::Expired, &deleted.id, &deleted.cat, &deleted.abs);
         }
-        if deleted_some {
+        // expired co-funding changes keys of content even if no content expired
+        if !deleted.is_empty() || changed > 0 {
+            self.iblts.clear();
             let (m, k, n) = tx.compute_content_sketch(MIN_SKETCH_SIZE)?;
             self.min_sketch = m;
             self.ksequence = k;
@@ -248,18 +813,20 @@ impl ContentStore {
     /// unwind the tip
     pub fn unwind_tip(&mut self, header: &BlockHeader) -> Result<(), Error> {
         info!("unwind tip {}", header.bitcoin_hash());
-        let mut deleted_some = false;
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
         tx.store_processed(&header.prev_blockhash)?;
-        for key in &mut tx.delete_confirmed(&header.bitcoin_hash())? {
-            debug!("delete un-confirmed content {}", sha256::Hash::from_slice(&key.digest[..]).unwrap());
-            for (_, i) in &mut self.iblts {
-                i.delete(key);
-                deleted_some = true;
-            }
+        tx.unconfirm_commitments(&header.bitcoin_hash())?;
+        tx.unconfirm_rentals(&header.bitcoin_hash(), self.trunk.len().saturating_sub(1) + RENT_PAYMENT_WINDOW)?;
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let (deleted, changed) = tx.delete_confirmed(&header.bitcoin_hash())?;
+        for deleted in &deleted {
+            debug!("delete un-confirmed content {}", deleted.id);
+            subscriptions.notify(EventKind::Unconfirmed, &deleted.id, &deleted.cat, &deleted.abs);
         }
-        if deleted_some {
+        // un-confirmed co-funding changes keys of content even if no content was deleted
+        if !deleted.is_empty() || changed > 0 {
+            self.iblts.clear();
             let (m, k, n) = tx.compute_content_sketch(MIN_SKETCH_SIZE)?;
             self.min_sketch = m;
             self.ksequence = k;
@@ -274,14 +841,14 @@ impl ContentStore {
         let mut deleted_some = false;
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
-        for key in &mut tx.truncate_content(self.storage_limit)? {
-            debug!("delete content exceeding memory limit {}", sha256::Hash::from_slice(&key.digest[..]).unwrap());
-            for (_, i) in &mut self.iblts {
-                i.delete(key);
-                deleted_some = true;
-            }
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        for deleted in &tx.truncate_content(self.storage_limit, &self.quotas)? {
+            debug!("delete content exceeding memory limit {}", deleted.id);
+            subscriptions.notify(EventKind::Dropped, &deleted.id, &deleted.cat, &deleted.abs);
+            deleted_some = true;
         }
         if deleted_some {
+            self.iblts.clear();
             let (m, k, n) = tx.compute_content_sketch(MIN_SKETCH_SIZE)?;
             self.min_sketch = m;
             self.ksequence = k;
@@ -291,12 +858,83 @@ impl ContentStore {
         return Ok(())
     }
 
+    /// revoke own funding of a publication and tell peers
+    pub fn revoke(&mut self, id: &sha256::Hash, passpharse: String) -> Result<(), Error> {
+        for signature in self.wallet.sign_revocations(id, passpharse)? {
+            let revocation = RevokeMessage { id: *id, signature };
+            if !self.revoke_content(&revocation)? {
+                debug!("revoked funding of publication {} was not in our store", id);
+            }
+            if let Some(ref updater) = self.updater {
+                updater.send(PeerMessage::Outgoing(Message::Revoke(revocation)));
+            }
+        }
+        info!("Revoked publication {}", id);
+        Ok(())
+    }
+
+    /// get revocations of content or of its funding by its key
+    pub fn get_revocations(&self, key: &ContentKey) -> Result<Vec<RevokeMessage>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        Ok(tx.read_tombstones(key)?.into_iter().map(|(id, signature)| RevokeMessage { id, signature }).collect())
+    }
+
+    /// remove the funding of content signed by its funder, co-funding takes its place,
+    /// returns true if funding was removed
+    pub fn revoke_content(&mut self, revocation: &RevokeMessage) -> Result<bool, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let funders = tx.read_content_funders(&revocation.id)?;
+        if funders.is_empty() {
+            debug!("ignore revocation of {}: unknown content", revocation.id);
+            return Ok(false);
+        }
+        let signature = if let Ok(signature) = Signature::from_der(revocation.signature.as_slice()) { signature } else {
+            debug!("reject revocation of {}: malformed signature", revocation.id);
+            return Ok(false);
+        };
+        let message = secp256k1::Message::from_slice(&revocation.id[..]).unwrap();
+        let signer = funders.into_iter().find(|(funder, _)| {
+            let mut tweaked = *funder;
+            self.ctx.tweak_exp_add(&mut tweaked, &revocation.id[..]).is_ok() &&
+                self.secp.verify(&message, &signature, &tweaked.key).is_ok()
+        });
+        let (funder, expiry) = if let Some(signer) = signer { signer } else {
+            debug!("reject revocation of {}: not signed by a funder", revocation.id);
+            return Ok(false);
+        };
+        if tx.read_tombstone(&revocation.id, &funder)?.is_some() {
+            debug!("ignore revocation of {} by {}: already revoked", revocation.id, funder);
+            return Ok(false);
+        }
+        tx.store_tombstone(&revocation.id, &funder, revocation.signature.as_slice(), expiry)?;
+        if let Some(deleted) = tx.delete_funding(&revocation.id, &funder)? {
+            self.subscriptions.lock().unwrap().notify(EventKind::Revoked, &deleted.id, &deleted.cat, &deleted.abs);
+        }
+        self.iblts.clear();
+        let (m, k, n) = tx.compute_content_sketch(MIN_SKETCH_SIZE)?;
+        self.min_sketch = m;
+        self.ksequence = k;
+        self.n_keys = n;
+        tx.commit();
+        debug!("revoked funding of content {} by {}", revocation.id, funder);
+        Ok(true)
+    }
+
     pub fn get_content(&self, digest: &sha256::Hash) -> Result<Option<Content>, Error> {
         let mut db = self.db.lock().unwrap();
         let tx = db.transaction();
         Ok(tx.read_content(digest)?)
     }
 
+    /// get funding or co-funding of content by its key, as content
+    pub fn get_funding(&self, key: &ContentKey) -> Result<Option<Content>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.read_funding(key)
+    }
+
     /// add content
     pub fn add_content(&mut self, content: &Content) -> Result<bool, Error> {
         // is the block on trunk the proof refers to
@@ -311,26 +949,76 @@ impl ContentStore {
                         // only use version 2 transactions
                         if t.version as u32 >= 2 {
                             let digest = content.ad.digest();
+                            let revoked = {
+                                let mut db = self.db.lock().unwrap();
+                                let tx = db.transaction();
+                                tx.read_tombstone(&digest, &content.funder)?.is_some()
+                            };
+                            if revoked {
+                                debug!("reject content {}: funding by {} revoked", digest, content.funder);
+                                return Ok(false);
+                            }
                             // expected commitment script to this ad
                             let mut tweaked = content.funder.clone();
                             self.ctx.tweak_exp_add(&mut tweaked, &digest[..]).unwrap();
 
                             let commitment = Self::funding_address(&tweaked, content.term).script_pubkey();
-                            if let Some((_, o)) = t.output.iter().enumerate().find(|(_, o)| o.script_pubkey == commitment) {
+                            let amount = t.output.iter().filter(|o| o.script_pubkey == commitment).map(|o| o.value).sum::<u64>();
+                            if amount > 0 {
                                 // ok there is a commitment to this ad
-                                debug!("add content {}", &digest);
-                                let key = ContentKey::new(&digest[..]);
-                                for (_, i) in &mut self.iblts {
-                                    i.insert(&key);
+                                let action = self.policy.check(&digest, &content.funder, content.ad.cat.as_str(), content.ad.abs.as_str());
+                                if action == Some(Action::Reject) {
+                                    debug!("reject content {}: local policy", digest);
+                                    self.rejected.insert(digest, ());
+                                    self.rejected.insert(sha256::Hash::from_slice(&ContentKey::funding(&digest, &content.funder).digest[..]).unwrap(), ());
+                                    return Ok(false);
+                                }
+                                let (known_until, publisher) = {
+                                    let mut db = self.db.lock().unwrap();
+                                    let tx = db.transaction();
+                                    (tx.read_content_expiry(&digest)?, tx.read_content_publisher(&digest)?)
+                                };
+                                if publisher.map_or(false, |p| p != content.funder) {
+                                    return self.add_cofunding(content, height, &header.bitcoin_hash(), amount);
+                                }
+                                if let Some(known_until) = known_until {
+                                    // a renewal only extends content already known
+                                    if height + content.term as u32 <= known_until {
+                                        debug!("ignore content {}: already known until block {}", &digest, known_until);
+                                        return Ok(false);
+                                    }
+                                    debug!("extend content {} until block {}", &digest, height + content.term as u32);
+                                }
+                                else {
+                                    debug!("add content {}", &digest);
+                                    for key in &[ContentKey::new(&digest[..]), ContentKey::funding(&digest, &content.funder)] {
+                                        for i in self.iblts.values_mut() {
+                                            i.insert(key);
+                                        }
+                                        add_to_min_sketch(&mut self.min_sketch, key, &self.ksequence);
+                                        self.n_keys += 1;
+                                    }
                                 }
-                                add_to_min_sketch(&mut self.min_sketch, &key, &self.ksequence);
-                                self.n_keys += 1;
                                 {
                                     let mut db = self.db.lock().unwrap();
                                     let mut tx = db.transaction();
-                                    tx.store_content(height, &header.bitcoin_hash(),content, o.value)?;
+                                    tx.store_content(height, &header.bitcoin_hash(),content, amount)?;
+                                    if action == Some(Action::Hide) {
+                                        debug!("hide content {}: local policy", digest);
+                                        tx.store_hidden(&digest)?;
+                                    }
                                     tx.commit();
                                 }
+                                if action.is_none() {
+                                    let event = if known_until.is_some() { EventKind::Renewed } else { EventKind::Added };
+                                    self.subscriptions.lock().unwrap().notify(event, &digest.to_string(), &content.ad.cat, &content.ad.abs);
+                                }
+                                if known_until.is_some() {
+                                    // peers knowing the content would not ask for the renewal
+                                    if let Some(ref updater) = self.updater {
+                                        updater.send(PeerMessage::Outgoing(Message::Content(content.clone())));
+                                    }
+                                }
                                 return Ok(true)
                             }
                             else {
@@ -359,18 +1047,154 @@ impl ContentStore {
         Ok(false)
     }
 
+    // funding of known content by a funder other than its publisher adds to its weight
+    fn add_cofunding(&mut self, content: &Content, height: u32, block_id: &sha256d::Hash, amount: u64) -> Result<bool, Error> {
+        let digest = content.ad.digest();
+        {
+            let mut db = self.db.lock().unwrap();
+            let mut tx = db.transaction();
+            if let Some(known_until) = tx.read_cofunding_expiry(&digest, &content.funder)? {
+                if height + content.term as u32 <= known_until {
+                    debug!("ignore co-funding of content {} by {}: already known until block {}", digest, content.funder, known_until);
+                    return Ok(false);
+                }
+            }
+            else {
+                let key = ContentKey::funding(&digest, &content.funder);
+                for i in self.iblts.values_mut() {
+                    i.insert(&key);
+                }
+                add_to_min_sketch(&mut self.min_sketch, &key, &self.ksequence);
+                self.n_keys += 1;
+            }
+            debug!("add co-funding of content {} by {}", digest, content.funder);
+            tx.store_cofunding(height, block_id, content, amount)?;
+            tx.commit();
+        }
+        // peers knowing the content would not ask for it again
+        if let Some(ref updater) = self.updater {
+            updater.send(PeerMessage::Outgoing(Message::Content(content.clone())));
+        }
+        Ok(true)
+    }
+
+    /// write all content and co-funding of it as snappy compressed stream of CBOR encoded content
+    pub fn export<W: Write>(&self, writer: W) -> Result<usize, Error> {
+        let contents = {
+            let mut db = self.db.lock().unwrap();
+            let tx = db.transaction();
+            tx.read_all_contents()?
+        };
+        let mut compressor = snap::Writer::new(writer);
+        for content in &contents {
+            serde_cbor::to_writer(&mut compressor, content)?;
+        }
+        compressor.flush()?;
+        Ok(contents.len())
+    }
+
+    /// read content written by export, each is validated as if received from a peer
+    pub fn import<R: Read>(&mut self, reader: R) -> Result<Imported, Error> {
+        let mut imported = Imported { read: 0, added: 0, unknown: 0, failed: 0 };
+        // content is decoded from a complete CBOR value so a malformed one does not stop reading the next
+        for value in serde_cbor::Deserializer::from_reader(snap::Reader::new(reader)).into_iter::<serde_cbor::Value>() {
+            imported.read += 1;
+            let content = match value {
+                Ok(value) => serde_cbor::value::from_value::<Content>(value),
+                Err(e) => {
+                    // the rest of a corrupted stream can not be read
+                    warn!("can not read archive after {} content {:?}", imported.read - 1, e);
+                    imported.failed += 1;
+                    break;
+                }
+            };
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    debug!("skip malformed content {:?}", e);
+                    imported.failed += 1;
+                    continue;
+                }
+            };
+            if self.trunk.get_height(content.funding.get_block_hash()).is_none() {
+                imported.unknown += 1;
+                continue;
+            }
+            match self.add_content(&content) {
+                Ok(true) => imported.added += 1,
+                Ok(false) => {},
+                Err(e) => {
+                    debug!("failed to import content {} {:?}", content.ad.digest(), e);
+                    imported.failed += 1;
+                }
+            }
+        }
+        info!("Imported {} of {} content, {} funded in blocks not yet known, {} failed", imported.added, imported.read, imported.unknown, imported.failed);
+        Ok(imported)
+    }
+
     pub fn list_categories(&self) -> Result<Vec<String>, Error> {
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
         Ok(tx.list_categories()?)
     }
 
-    pub fn list_abstracts(&self, cats: Vec<String>) -> Result<Vec<Vec<String>>, Error> {
+    pub fn list_abstracts(&self, cats: Vec<String>) -> Result<Vec<ListedAbstract>, Error> {
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
         Ok(tx.list_abstracts(cats)?)
     }
 
+    pub fn list_categories_page(&self, page: &Page) -> Result<Paged<String>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.list_categories_page(page)
+    }
+
+    pub fn list_abstracts_page(&self, selection: Selection, page: &Page) -> Result<Paged<ListedAbstract>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let paged = tx.retrieve_contents_page(selection, page)?;
+        Ok(Paged { total: paged.total, next: paged.next,
+            items: paged.items.into_iter().map(|r| ListedAbstract { id: r.id, cat: r.cat, abs: r.abs }).collect() })
+    }
+
+    pub fn read_contents_page(&self, selection: Selection, page: &Page) -> Result<Paged<Readable>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let paged = tx.retrieve_contents_page(selection, page)?;
+        Ok(Paged { total: paged.total, next: paged.next,
+            items: paged.items.iter().map(|r| Readable::new(r, self.trunk.clone())).collect() })
+    }
+
+    /// abstracts of a category that are JSON objects satisfying all conditions
+    pub fn query(&self, cat: String, conditions: Vec<Condition>) -> Result<Vec<ListedAbstract>, Error> {
+        Ok(self.list_abstracts(vec!(cat))?.into_iter().filter(|r|
+            if let Ok(abs) = serde_json::from_str::<serde_json::Value>(r.abs.as_str()) {
+                abs.is_object() && conditions.iter().all(|c| c.matches(&abs))
+            } else {
+                false
+            }).collect())
+    }
+
+    pub fn search(&self, keywords: Vec<String>) -> Result<Vec<ListedAbstract>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.search(keywords)
+    }
+
+    pub fn list_by_publisher(&self, publisher: &PublicKey) -> Result<Vec<ListedAbstract>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.list_by_publisher(publisher)
+    }
+
+    pub fn publisher_stats(&self, publisher: &PublicKey) -> Result<PublisherStats, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.publisher_stats(publisher)
+    }
+
     pub fn read_contents(&self, ids: Vec<String>) -> Result<Vec<Readable>, Error> {
         let mut db = self.db.lock().unwrap();
         let mut tx = db.transaction();
@@ -378,7 +1202,59 @@ impl ContentStore {
     }
 }
 
-#[derive(Serialize, Clone)]
+/// wallet balance in satoshis
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Balance {
+    /// confirmed balance
+    pub balance: u64,
+    /// available to fund publications, lower than balance if some is committed to funding
+    pub available: u64
+}
+
+/// what a wallet transaction did
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    /// received from others
+    Deposit,
+    /// sent to others
+    Withdrawal,
+    /// funded a publication, also renewal of funding
+    Funding,
+    /// between addresses of the wallet, such as sweeping matured funding
+    Internal
+}
+
+/// a transaction of the wallet
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct WalletTransaction {
+    pub txid: String,
+    pub kind: TransactionKind,
+    /// height of the block confirming it
+    pub height: Option<u32>,
+    pub confirmed: bool,
+    /// change of the balance in satoshis, None if outputs it spent are not known
+    pub delta: Option<i64>,
+    /// fee paid in satoshis if sent by this wallet
+    pub fee: Option<u64>,
+    /// publication funded
+    pub id: Option<String>
+}
+
+/// result of an import of content
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Imported {
+    /// content read
+    pub read: usize,
+    /// content valid and not yet known
+    pub added: usize,
+    /// content funded in blocks not yet known
+    pub unknown: usize,
+    /// content malformed or failed to store
+    pub failed: usize
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Readable {
     pub id: String,
     pub cat: String,
@@ -420,20 +1296,24 @@ impl Readable {
 
 #[cfg(test)]
 mod test {
-    use super::ContentStore;
+    use super::{ContentStore, Balance, Imported, TransactionKind};
+    use crate::content::Content;
+    use crate::adspace::{RENT_PAYMENT_WINDOW, MAX_OPEN_RENTALS};
+    use bitcoin_wallet::proved::ProvedTransaction;
+    use std::io::Write;
+    use crate::content::ContentKey;
+    use crate::messages::RevokeMessage;
     use crate::db::DB;
     use crate::wallet::Wallet;
-    use bitcoin::{network::constants::Network, blockdata::opcodes::all, BlockHeader, Block, BitcoinHash, util::bip32::ExtendedPubKey, Address, Transaction, TxIn, OutPoint, TxOut};
-    use std::{
-        sync::{Arc, Mutex},
-        str::FromStr
-    };
+    use bitcoin::{network::constants::Network, blockdata::opcodes::all, BlockHeader, Block, BitcoinHash, Address, Transaction, TxIn, OutPoint, TxOut};
+    use std::sync::{Arc, Mutex};
     use bitcoin_hashes::sha256d;
     use crate::trunk::Trunk;
     use bitcoin::blockdata::constants::genesis_block;
     use std::time::{SystemTime, UNIX_EPOCH};
     use bitcoin::util::hash::MerkleRoot;
-    use bitcoin_wallet::account::{Account, AccountAddressType, Unlocker};
+    use bitcoin_wallet::account::{Account, AccountAddressType, Unlocker, MasterAccount, MasterKeyEntropy};
+    use bitcoin_wallet::coins::Coins;
     use bitcoin::blockdata::script::Builder;
 
     const NEW_COINS:u64 = 5000000000;
@@ -447,6 +1327,10 @@ mod test {
         fn extend(&self, header: &BlockHeader) {
             self.trunk.lock().unwrap().push(header.clone());
         }
+
+        fn unwind(&self) -> BlockHeader {
+            self.trunk.lock().unwrap().pop().unwrap()
+        }
     }
 
     impl Trunk for TestTrunk {
@@ -481,25 +1365,44 @@ mod test {
         }
     }
 
-    fn new_store (trunk: Arc<TestTrunk>) -> ContentStore {
+    fn new_master () -> MasterAccount {
+        let mut master = MasterAccount::new(MasterKeyEntropy::Low, Network::Testnet, PASSPHRASE).unwrap();
+        let mut unlocker = Unlocker::new_for_master(&master, PASSPHRASE).unwrap();
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 0, 10).unwrap());
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 1, 10).unwrap());
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WSH(4711), 1, 0, 0).unwrap());
+        master
+    }
+
+    fn store_with (trunk: Arc<TestTrunk>, master: MasterAccount) -> ContentStore {
         let mut memdb = DB::memory().unwrap();
         {
             let mut tx = memdb.transaction();
             tx.create_tables();
             tx.commit();
         }
-        let mut wallet = Wallet::from_encrypted(
-            hex::decode("0e05ba48bb0fdc7285dc9498202aeee5e1777ac4f55072b30f15f6a8632ad0f3fde1c41d9e162dbe5d3153282eaebd081cf3b3312336fc56f5dd18a2df6ea48c1cdd11a1ed11281cd2e0f864f02e5bed5ab03326ed24e43b8a184acff9cb4e730db484e33f2b24295a97b2ca87871a69384eb64d4160ce8b3e8b4d90234040970e531d4333a8979dbe533c2b2668bf43b6607b2d24c5b42765ebfdd075fd173c").unwrap().as_slice(),
-            ExtendedPubKey::from_str("tpubD6NzVbkrYhZ4XKz4vgwBmnnVmA7EgWhnXvimQ4krq94yUgcSSbroi4uC1xbZ3UGMxG9M2utmaPjdpMrWW2uKRY9Mj4DZWrrY8M4pry8shsK").unwrap(),
-            1567260002);
-        let mut unlocker = Unlocker::new_for_master(&wallet.master, PASSPHRASE).unwrap();
-        wallet.master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 0, 10).unwrap());
-        wallet.master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 1, 10).unwrap());
-        wallet.master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WSH(4711), 1, 0, 0).unwrap());
-
+        let wallet = Wallet::from_storage(Coins::new(), master);
         ContentStore::new(Arc::new(Mutex::new(memdb)), 1024*1024, trunk, wallet).unwrap()
     }
 
+    fn new_store (trunk: Arc<TestTrunk>) -> ContentStore {
+        store_with(trunk, new_master())
+    }
+
+    // the same accounts without keys
+    fn new_watch_only_store (trunk: Arc<TestTrunk>) -> ContentStore {
+        let master = new_master();
+        let mut watch_only = MasterAccount::watch_only(master.master_public().clone(), 0);
+        for (a, s) in &[(0, 0), (0, 1), (1, 0)] {
+            let account = master.get((*a, *s)).unwrap();
+            let mut copy = Account::new_from_storage(account.address_type(), *a, *s, account.master_public().clone(),
+                                                     Vec::new(), 0, account.look_ahead(), Network::Testnet);
+            copy.do_look_ahead(None).unwrap();
+            watch_only.add_account(copy);
+        }
+        store_with(trunk, watch_only)
+    }
+
     fn new_block (prev: &sha256d::Hash) -> Block {
         Block {
             header :BlockHeader {
@@ -542,6 +1445,12 @@ mod test {
         block
     }
 
+    fn connect(store: &mut ContentStore, trunk: &TestTrunk, block: &Block, height: u32) {
+        trunk.extend(&block.header);
+        store.add_header(height, &block.header).unwrap();
+        store.block_connected(block, height).unwrap();
+    }
+
 
     #[test]
     pub fn test () {
@@ -560,20 +1469,20 @@ mod test {
         store.add_header(1, &next.header).unwrap();
         store.block_connected(&next, 1).unwrap();
 
-        assert_eq!(store.balance(), vec!(NEW_COINS, NEW_COINS));
+        assert_eq!(store.balance(), Balance { balance: NEW_COINS, available: NEW_COINS });
 
         let burn = Address::p2shwsh(&Builder::new().push_opcode(all::OP_VERIFY).into_script(), Network::Testnet);
-        let (burn_half, _) = store.withdraw(PASSPHRASE.to_string(), burn.clone(), 1, Some(NEW_COINS/2)).unwrap();
+        let (burn_half, _) = store.withdraw(PASSPHRASE.to_string(), burn.clone(), 1, Some(NEW_COINS/2), None).unwrap();
 
         let mut next = mine(&store, 2, &miner);
         add_tx(&mut next, burn_half);
         trunk.extend(&next.header);
         store.add_header(2, &next.header).unwrap();
         store.block_connected(&next, 2).unwrap();
-        assert_eq!(store.balance(), vec!(NEW_COINS + NEW_COINS/2, NEW_COINS + NEW_COINS/2));
+        assert_eq!(store.balance(), Balance { balance: NEW_COINS + NEW_COINS/2, available: NEW_COINS + NEW_COINS/2 });
 
-        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string());
-        let (fundit, _, _) = store.fund(&id, 1, NEW_COINS,5, PASSPHRASE.to_string()).unwrap();
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        let (fundit, _, _) = store.fund(&id, 1, NEW_COINS,5, None, PASSPHRASE.to_string()).unwrap();
 
         let mut next = mine(&store, 3, &miner);
         add_tx(&mut next, fundit);
@@ -588,12 +1497,422 @@ mod test {
         store.block_connected(&next, 4).unwrap();
         assert!(store.list_categories().unwrap().is_empty());
 
-        let (burn_all, _) = store.withdraw(PASSPHRASE.to_string(), burn, 1, None).unwrap();
+        let (burn_all, _) = store.withdraw(PASSPHRASE.to_string(), burn, 1, None, None).unwrap();
         let mut next = mine(&store, 5, &miner);
         add_tx(&mut next, burn_all);
         trunk.extend(&next.header);
         store.add_header(5, &next.header).unwrap();
         store.block_connected(&next, 5).unwrap();
-        assert_eq!(store.balance(), vec!(NEW_COINS, NEW_COINS));
+        assert_eq!(store.balance(), Balance { balance: NEW_COINS, available: NEW_COINS });
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_transactions () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        let own = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        let deposit = mine(&store, 1, &miner);
+        connect(&mut store, &trunk, &deposit, 1);
+
+        let burn = Address::p2shwsh(&Builder::new().push_opcode(all::OP_VERIFY).into_script(), Network::Testnet);
+        let (withdrawal, withdrawal_fee) = store.withdraw(PASSPHRASE.to_string(), burn, 1, Some(NEW_COINS/4), None).unwrap();
+        let mut next = mine(&store, 2, &miner);
+        add_tx(&mut next, withdrawal.clone());
+        connect(&mut store, &trunk, &next, 2);
+        let (internal, internal_fee) = store.withdraw(PASSPHRASE.to_string(), own, 1, Some(NEW_COINS/4), None).unwrap();
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        let (funding, _, funding_fee) = store.fund(&id, 5, NEW_COINS/4, 1, None, PASSPHRASE.to_string()).unwrap();
+
+        let transactions = store.transactions().unwrap();
+        let find = |txid: sha256d::Hash| transactions.iter().find(|t| t.txid == txid.to_string()).unwrap();
+        let deposit = find(deposit.txdata[0].txid());
+        assert_eq!((deposit.kind, deposit.height, deposit.delta, deposit.fee), (TransactionKind::Deposit, Some(1), Some(NEW_COINS as i64), None));
+        // the fee is paid from the amount withdrawn
+        let withdrawal = find(withdrawal.txid());
+        assert_eq!((withdrawal.kind, withdrawal.height, withdrawal.delta, withdrawal.fee),
+                   (TransactionKind::Withdrawal, Some(2), Some(-((NEW_COINS/4) as i64)), Some(withdrawal_fee)));
+        let internal = find(internal.txid());
+        assert_eq!((internal.kind, internal.confirmed, internal.delta, internal.fee),
+                   (TransactionKind::Internal, false, Some(-(internal_fee as i64)), Some(internal_fee)));
+        let funding = find(funding.txid());
+        assert_eq!((funding.kind, funding.delta, funding.fee, funding.id.clone()),
+                   (TransactionKind::Funding, Some(-(funding_fee as i64)), Some(funding_fee), Some(id.to_string())));
+        // unconfirmed first
+        assert!(transactions.iter().take(2).all(|t| !t.confirmed));
+    }
+
+    #[test]
+    pub fn test_discard_psbt () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_watch_only_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        let next = mine(&store, 1, &miner);
+        connect(&mut store, &trunk, &next, 1);
+
+        let (held, _) = store.withdraw(String::new(), miner.clone(), 5, Some(NEW_COINS/2), None).unwrap();
+        assert!(store.read_psbt(&held.txid()).unwrap().is_some());
+        assert!(store.list_unspent().iter().all(|u| u.locked));
+        assert!(store.discard_psbt(&held.txid()).unwrap());
+        assert!(!store.discard_psbt(&held.txid()).unwrap());
+        assert!(store.read_psbt(&held.txid()).unwrap().is_none());
+        assert!(store.list_unspent().iter().all(|u| !u.locked));
+        assert!(store.db.lock().unwrap().transaction().read_locked().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_sweep () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        let next = mine(&store, 1, &miner);
+        connect(&mut store, &trunk, &next, 1);
+
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        let (fundit, _, fee) = store.fund(&id, 2, NEW_COINS/2, 5, None, PASSPHRASE.to_string()).unwrap();
+        let funded = store.list_funded();
+        assert_eq!(funded.len(), 1);
+        assert_eq!((funded[0].id.as_str(), funded[0].value, funded[0].height, funded[0].matured),
+                   (hex::encode(&id[..]).as_str(), NEW_COINS/2 - fee, None, false));
+        assert!(store.sweep(PASSPHRASE.to_string(), 5).is_err());
+
+        let mut next = mine(&store, 2, &miner);
+        add_tx(&mut next, fundit);
+        connect(&mut store, &trunk, &next, 2);
+        let funded = store.list_funded();
+        assert_eq!((funded[0].height, funded[0].unlock, funded[0].matured), (Some(2), Some(4), false));
+        assert!(store.sweep(PASSPHRASE.to_string(), 5).is_err());
+
+        assert!(store.set_auto_sweep(Some(("wrong".to_string(), 5))).is_err());
+        store.set_auto_sweep(Some((PASSPHRASE.to_string(), 5))).unwrap();
+        let next = mine(&store, 3, &miner);
+        connect(&mut store, &trunk, &next, 3);
+        // matured with the block and swept automatically
+        assert!(store.list_funded().is_empty());
+        let sweep = store.db.lock().unwrap().transaction().read_unconfirmed().unwrap().into_iter()
+            .map(|(t, _)| t).find(|t| t.input.len() == 1 && t.output.len() == 1).unwrap();
+        assert!(sweep.output[0].value < NEW_COINS/2 && sweep.output[0].value > NEW_COINS/2 - 10000);
+        let mut next = mine(&store, 4, &miner);
+        add_tx(&mut next, sweep);
+        connect(&mut store, &trunk, &next, 4);
+        assert!(store.list_funded().is_empty());
+        assert!(!store.has_matured_funding());
+    }
+
+    #[test]
+    pub fn test_commitment () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        let publisher = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().public;
+        connect(&mut store, &trunk, &genesis, 0);
+        let next = mine(&store, 1, &miner);
+        connect(&mut store, &trunk, &next, 1);
+
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        let address = store.commitment_address(&id, &publisher, 5).unwrap();
+        // paid from an external wallet
+        let payment = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec!(TxIn { previous_output: OutPoint { txid: sha256d::Hash::default(), vout: 1 }, script_sig: Builder::new().into_script(), sequence: 0xffffffff, witness: Vec::new() }),
+            output: vec!(TxOut { value: 100000, script_pubkey: address.script_pubkey() })
+        };
+        let commitments = |store: &ContentStore| store.db.lock().unwrap().transaction().read_commitments().unwrap().len();
+        assert_eq!(commitments(&store), 1);
+        let mut next = mine(&store, 2, &miner);
+        add_tx(&mut next, payment.clone());
+        connect(&mut store, &trunk, &next, 2);
+        assert_eq!(commitments(&store), 0);
+        assert_eq!(store.read_contents(vec!(id.to_string())).unwrap()[0].publisher, publisher.to_string());
+
+        // watched again as the block is unwound, and published again as the payment confirms in an other block
+        store.unwind_tip(&trunk.unwind()).unwrap();
+        assert!(store.list_categories().unwrap().is_empty());
+        assert_eq!(commitments(&store), 1);
+        let mut next = mine(&store, 2, &miner);
+        next.header.nonce = 1;
+        add_tx(&mut next, payment);
+        connect(&mut store, &trunk, &next, 2);
+        assert_eq!(commitments(&store), 0);
+        assert!(store.list_categories().unwrap().contains(&"/foo/what".to_string()));
+    }
+
+    #[test]
+    pub fn test_lock_coins () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        let next = mine(&store, 1, &miner);
+        let coin = OutPoint { txid: next.txdata[0].txid(), vout: 0 };
+        connect(&mut store, &trunk, &next, 1);
+
+        assert_eq!(store.lock_coins(&[coin, coin], true).unwrap(), 1);
+        assert!(store.withdraw(PASSPHRASE.to_string(), miner.clone(), 5, Some(NEW_COINS/2), None).is_err());
+        let (spend, _) = store.withdraw(PASSPHRASE.to_string(), miner.clone(), 5, Some(NEW_COINS/2), Some(vec!(coin))).unwrap();
+        assert_eq!(store.db.lock().unwrap().transaction().read_locked().unwrap(), vec!(coin));
+
+        let mut next = mine(&store, 2, &miner);
+        add_tx(&mut next, spend);
+        connect(&mut store, &trunk, &next, 2);
+        assert!(store.db.lock().unwrap().transaction().read_locked().unwrap().is_empty());
+        assert!(store.list_unspent().iter().all(|u| !u.locked));
+    }
+
+    #[test]
+    pub fn test_bump_fee () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        for height in 1..3 {
+            let next = mine(&store, height, &miner);
+            connect(&mut store, &trunk, &next, height);
+        }
+
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        // without change the fee would be taken from the commitment
+        let (all_in, _, _) = store.fund(&id, 5, NEW_COINS, 5, None, PASSPHRASE.to_string()).unwrap();
+        assert_eq!(all_in.output.len(), 1);
+        assert!(store.bump_fee(&all_in.txid(), 20, PASSPHRASE.to_string()).is_err());
+
+        let (fundit, funder, fee) = store.fund(&id, 5, NEW_COINS/2, 5, None, PASSPHRASE.to_string()).unwrap();
+        let (replacement, higher_fee) = store.bump_fee(&fundit.txid(), 20, PASSPHRASE.to_string()).unwrap();
+        assert!(higher_fee > fee);
+        let commitment = |t: &Transaction| t.output.iter().find(|o| o.value == NEW_COINS/2 - fee).map(|o| o.value);
+        assert_eq!(commitment(&replacement), commitment(&fundit));
+        let change = |t: &Transaction| t.output.iter().find(|o| o.value != NEW_COINS/2 - fee).unwrap().value;
+        assert_eq!(change(&fundit) - change(&replacement), higher_fee - fee);
+        {
+            let mut db = store.db.lock().unwrap();
+            let tx = db.transaction();
+            assert!(tx.read_unconfirmed_txout(&fundit.txid()).unwrap().is_none());
+            let (_, funding, _) = tx.read_unconfirmed_txout(&replacement.txid()).unwrap().unwrap();
+            assert_eq!(funding, Some((funder, id, 5)));
+        }
+
+        let mut next = mine(&store, 3, &miner);
+        add_tx(&mut next, replacement);
+        connect(&mut store, &trunk, &next, 3);
+        assert!(store.list_categories().unwrap().contains(&"/foo/what".to_string()));
+    }
+
+    #[test]
+    pub fn test_renew () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        let next = mine(&store, 1, &miner);
+        connect(&mut store, &trunk, &next, 1);
+
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        assert!(store.renew(&id, 5, 5, PASSPHRASE.to_string()).is_err());
+        let (fundit, _, fee) = store.fund(&id, 5, NEW_COINS/2, 5, None, PASSPHRASE.to_string()).unwrap();
+        let mut next = mine(&store, 2, &miner);
+        add_tx(&mut next, fundit);
+        connect(&mut store, &trunk, &next, 2);
+        let expiry = || store.db.lock().unwrap().transaction().read_content_expiry(&id).unwrap();
+        assert_eq!(expiry(), Some(7));
+
+        // not yet matured, the wallet pays for the renewal
+        let (renewal, funder, renewal_fee) = store.renew(&id, 5, 5, PASSPHRASE.to_string()).unwrap();
+        assert!(renewal.output.iter().any(|o| o.value == NEW_COINS/2 - fee - renewal_fee));
+        let mut next = mine(&store, 3, &miner);
+        add_tx(&mut next, renewal);
+        connect(&mut store, &trunk, &next, 3);
+        assert_eq!(store.db.lock().unwrap().transaction().read_cofunding_expiry(&id, &funder).unwrap(), Some(8));
+
+        for height in 4..8 {
+            let next = mine(&store, height, &miner);
+            connect(&mut store, &trunk, &next, height);
+        }
+        // the renewal took the place of the expired funding
+        let expiry = || store.db.lock().unwrap().transaction().read_content_expiry(&id).unwrap();
+        assert_eq!(expiry(), Some(8));
+        assert!(store.list_categories().unwrap().contains(&"/foo/what".to_string()));
+        let next = mine(&store, 8, &miner);
+        connect(&mut store, &trunk, &next, 8);
+        assert!(store.list_categories().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_revoke () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        for height in 1..3 {
+            let next = mine(&store, height, &miner);
+            connect(&mut store, &trunk, &next, height);
+        }
+
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        let (fundit, _, _) = store.fund(&id, 5, NEW_COINS/2, 5, None, PASSPHRASE.to_string()).unwrap();
+        let (cofundit, _, _) = store.fund(&id, 6, NEW_COINS/2, 5, None, PASSPHRASE.to_string()).unwrap();
+        let mut next = mine(&store, 3, &miner);
+        add_tx(&mut next, fundit);
+        connect(&mut store, &trunk, &next, 3);
+        let mut next = mine(&store, 4, &miner);
+        add_tx(&mut next, cofundit);
+        connect(&mut store, &trunk, &next, 4);
+        let content = store.get_content(&id).unwrap().unwrap();
+        let cofunding = store.db.lock().unwrap().transaction().read_cofundings(&id).unwrap().remove(0);
+        assert_eq!(store.get_funding(&ContentKey::funding(&id, &cofunding.funder)).unwrap().unwrap().term, 6);
+        // digest of the content and each of its funding
+        assert_eq!(store.n_keys, 3);
+
+        // revocation of the publisher's funding leaves the co-funding in its place
+        let signatures = store.wallet.sign_revocations(&id, PASSPHRASE.to_string()).unwrap();
+        assert_eq!(signatures.len(), 2);
+        assert!(store.revoke_content(&RevokeMessage { id, signature: signatures[0].clone() }).unwrap());
+        assert!(!store.revoke_content(&RevokeMessage { id, signature: signatures[0].clone() }).unwrap());
+        assert_eq!(store.get_content(&id).unwrap().unwrap().funder, cofunding.funder);
+        assert!(store.list_categories().unwrap().contains(&"/foo/what".to_string()));
+        assert_eq!(store.n_keys, 2);
+        assert_eq!(store.get_revocations(&ContentKey::funding(&id, &content.funder)).unwrap().len(), 1);
+        assert!(store.get_revocations(&ContentKey::funding(&id, &cofunding.funder)).unwrap().is_empty());
+        assert!(!store.add_content(&content).unwrap());
+
+        assert!(store.revoke_content(&RevokeMessage { id, signature: signatures[1].clone() }).unwrap());
+        assert!(store.list_categories().unwrap().is_empty());
+        assert_eq!(store.get_revocations(&ContentKey::new(&id[..])).unwrap().len(), 2);
+        assert!(!store.add_content(&cofunding).unwrap());
+    }
+
+    #[test]
+    pub fn test_rent () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        for height in 1..3 {
+            let next = mine(&store, height, &miner);
+            connect(&mut store, &trunk, &next, height);
+        }
+
+        let offer = store.offer_adspace(NEW_COINS/2, 5, 10000, PASSPHRASE.to_string(), 5).unwrap();
+        let rental = store.rent_adspace(&offer, "/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        // paid by the advertiser
+        let payment = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec!(TxIn { previous_output: OutPoint { txid: sha256d::Hash::default(), vout: 1 }, script_sig: Builder::new().into_script(), sequence: 0xffffffff, witness: Vec::new() }),
+            output: vec!(TxOut { value: 10000, script_pubkey: rental.address.parse::<Address>().unwrap().script_pubkey() })
+        };
+        let mut next = mine(&store, 3, &miner);
+        add_tx(&mut next, payment.clone());
+        connect(&mut store, &trunk, &next, 3);
+        let rented = store.list_rentals().unwrap().remove(0);
+        assert_eq!((rented.paid, rented.block, rented.funded), (Some(payment.txid().to_string()), Some(next.bitcoin_hash().to_string()), None));
+
+        // waited for again as the block is unwound
+        store.unwind_tip(&trunk.unwind()).unwrap();
+        assert!(store.list_rentals().unwrap()[0].paid.is_none());
+        let mut next = mine(&store, 3, &miner);
+        next.header.nonce = 1;
+        add_tx(&mut next, payment);
+        connect(&mut store, &trunk, &next, 3);
+
+        // as if the node was restarted
+        store.auto_rent = None;
+        for height in 4..9 {
+            let next = mine(&store, height, &miner);
+            connect(&mut store, &trunk, &next, height);
+            let rented = store.list_rentals().unwrap().remove(0);
+            // would be funded with the sixth confirmation
+            assert_eq!((rented.funded, rented.stalled), (None, height == 8));
+        }
+
+        // funded with the next block after the offer is renewed
+        assert_eq!(store.offer_adspace(NEW_COINS/2, 5, 10000, PASSPHRASE.to_string(), 5).unwrap(), offer);
+        assert!(!store.list_rentals().unwrap()[0].stalled);
+        let next = mine(&store, 9, &miner);
+        connect(&mut store, &trunk, &next, 9);
+        assert!(store.list_rentals().unwrap()[0].funded.is_some());
+
+        // a limited number of rentals wait for payment, those not paid in time are dropped
+        for i in 0..MAX_OPEN_RENTALS {
+            let rental = store.rent_adspace(&offer, "/foo/what".to_string(), format!("{}", i), "<head></head>".to_string()).unwrap();
+            assert_eq!(rental.expiry, 9 + RENT_PAYMENT_WINDOW);
+        }
+        assert!(store.rent_adspace(&offer, "/foo/what".to_string(), "more".to_string(), "<head></head>".to_string()).is_err());
+        for height in 10..=9 + RENT_PAYMENT_WINDOW {
+            let next = mine(&store, height, &miner);
+            connect(&mut store, &trunk, &next, height);
+            assert_eq!(store.list_rentals().unwrap().len(), if height < 9 + RENT_PAYMENT_WINDOW { MAX_OPEN_RENTALS + 1 } else { 1 });
+        }
+        assert!(store.rent_adspace(&offer, "/foo/what".to_string(), "more".to_string(), "<head></head>".to_string()).is_ok());
+    }
+
+    #[test]
+    pub fn test_rent_watch_only () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_watch_only_store(trunk.clone());
+        connect(&mut store, &trunk, &genesis_block(Network::Testnet), 0);
+        // rented ad space could not be funded without keys
+        assert!(store.offer_adspace(NEW_COINS/2, 5, 10000, String::new(), 5).is_err());
+        assert!(store.list_offers().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_export_import () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut store = new_store(trunk.clone());
+        let genesis = genesis_block(Network::Testnet);
+        let miner = store.wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        connect(&mut store, &trunk, &genesis, 0);
+        let next = mine(&store, 1, &miner);
+        connect(&mut store, &trunk, &next, 1);
+
+        let id = store.prepare_publication("/foo/what".to_string(), "index.html".to_string(), "<head></head>".to_string()).unwrap();
+        let (fundit, _, _) = store.fund(&id, 5, NEW_COINS/2, 5, None, PASSPHRASE.to_string()).unwrap();
+        let mut next = mine(&store, 2, &miner);
+        add_tx(&mut next, fundit);
+        connect(&mut store, &trunk, &next, 2);
+        let mut archive = Vec::new();
+        assert_eq!(store.export(&mut archive).unwrap(), 1);
+
+        let mut other = new_store(trunk.clone());
+        assert_eq!(other.import(archive.as_slice()).unwrap(), Imported { read: 1, added: 1, unknown: 0, failed: 0 });
+        assert_eq!(other.get_content(&id).unwrap().unwrap().funder, store.get_content(&id).unwrap().unwrap().funder);
+
+        // malformed content and content of an unknown block do not stop the import
+        let content = store.get_content(&id).unwrap().unwrap();
+        let unknown = Content { funding: ProvedTransaction::new(&mine(&store, 3, &miner), 0), .. content.clone() };
+        let mut archive = Vec::new();
+        {
+            let mut compressor = snap::Writer::new(&mut archive);
+            serde_cbor::to_writer(&mut compressor, &"malformed").unwrap();
+            serde_cbor::to_writer(&mut compressor, &unknown).unwrap();
+            serde_cbor::to_writer(&mut compressor, &content).unwrap();
+            compressor.flush().unwrap();
+        }
+        let mut other = new_store(trunk.clone());
+        assert_eq!(other.import(archive.as_slice()).unwrap(), Imported { read: 3, added: 1, unknown: 1, failed: 1 });
+    }
+}
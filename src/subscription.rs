@@ -0,0 +1,179 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! subscriptions to content changes
+
+use jsonrpc_http_server::jsonrpc_core::futures::sync::oneshot;
+use rand::{thread_rng, RngCore};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// events kept for a subscriber that does not poll
+const MAX_QUEUED_EVENTS: usize = 1000;
+// forget subscribers that did not poll for this long
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(10*60);
+
+pub type SharedSubscriptions = Arc<Mutex<Subscriptions>>;
+
+/// what happened to a content
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// content was added to the store
+    Added,
+    /// funding term of stored content was extended
+    Renewed,
+    /// funding term of content ended
+    Expired,
+    /// content was dropped to stay within the storage limit
+    Dropped,
+    /// funding of content was un-confirmed by a re-org
+    Unconfirmed,
+    /// content was revoked by its funder
+    Revoked
+}
+
+/// a change of stored content delivered to subscribers
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Event {
+    pub event: EventKind,
+    pub id: String,
+    pub cat: String,
+    pub abs: String
+}
+
+/// selects events of interest
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// category to match, any if None
+    pub cat: Option<String>,
+    /// all of these must appear in the abstract (case insensitive)
+    pub keywords: Vec<String>
+}
+
+impl Filter {
+    pub fn new(cat: Option<String>, keywords: Vec<String>) -> Filter {
+        Filter { cat, keywords: keywords.iter().map(|k| k.to_lowercase()).collect() }
+    }
+
+    pub fn matches(&self, cat: &str, abs: &str) -> bool {
+        if let Some(ref c) = self.cat {
+            if c != cat {
+                return false;
+            }
+        }
+        let abs = abs.to_lowercase();
+        self.keywords.iter().all(|k| abs.contains(k.as_str()))
+    }
+}
+
+struct Subscription {
+    filter: Filter,
+    events: VecDeque<Event>,
+    waiter: Option<oneshot::Sender<()>>,
+    last_poll: SystemTime
+}
+
+/// registered subscribers and their undelivered events
+pub struct Subscriptions {
+    subscriptions: HashMap<u64, Subscription>
+}
+
+impl Default for Subscriptions {
+    fn default() -> Subscriptions {
+        Subscriptions::new()
+    }
+}
+
+impl Subscriptions {
+    pub fn new() -> Subscriptions {
+        Subscriptions { subscriptions: HashMap::new() }
+    }
+
+    /// register a new subscriber, returns its id
+    pub fn subscribe(&mut self, filter: Filter) -> u64 {
+        let mut id = thread_rng().next_u64();
+        while self.subscriptions.contains_key(&id) {
+            id = thread_rng().next_u64();
+        }
+        self.subscriptions.insert(id, Subscription { filter, events: VecDeque::new(), waiter: None, last_poll: SystemTime::now() });
+        id
+    }
+
+    /// remove a subscriber, returns false if it was not known
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        self.subscriptions.remove(&id).is_some()
+    }
+
+    /// take events queued for a subscriber, None if the subscriber is not known
+    pub fn take_events(&mut self, id: u64) -> Option<Vec<Event>> {
+        if let Some(subscription) = self.subscriptions.get_mut(&id) {
+            subscription.last_poll = SystemTime::now();
+            return Some(subscription.events.drain(..).collect());
+        }
+        None
+    }
+
+    /// get notified once the next event is queued for a subscriber
+    pub fn wait(&mut self, id: u64) -> Option<oneshot::Receiver<()>> {
+        if let Some(subscription) = self.subscriptions.get_mut(&id) {
+            let (sender, receiver) = oneshot::channel();
+            subscription.waiter = Some(sender);
+            return Some(receiver);
+        }
+        None
+    }
+
+    /// queue an event for all subscribers interested
+    pub fn notify(&mut self, event: EventKind, id: &str, cat: &str, abs: &str) {
+        let now = SystemTime::now();
+        self.subscriptions.retain(|_, s|
+            s.waiter.as_ref().map_or(false, |w| !w.is_canceled()) || now.duration_since(s.last_poll).unwrap_or_default() < SUBSCRIPTION_TIMEOUT);
+        for (sid, subscription) in self.subscriptions.iter_mut().filter(|(_, s)| s.filter.matches(cat, abs)) {
+            debug!("notify subscription {} of {:?} {}", sid, event, id);
+            if subscription.events.len() >= MAX_QUEUED_EVENTS {
+                subscription.events.pop_front();
+            }
+            subscription.events.push_back(Event { event, id: id.to_string(), cat: cat.to_string(), abs: abs.to_string() });
+            if let Some(waiter) = subscription.waiter.take() {
+                waiter.send(()).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_subscriptions () {
+        let mut subscriptions = Subscriptions::new();
+        let all = subscriptions.subscribe(Filter::new(None, Vec::new()));
+        let some = subscriptions.subscribe(Filter::new(Some("exchange".to_string()), vec!("BTC".to_string())));
+        subscriptions.notify(EventKind::Added, "1", "exchange", "btc/usd");
+        subscriptions.notify(EventKind::Added, "2", "exchange", "eth/usd");
+        subscriptions.notify(EventKind::Expired, "3", "watchtower", "btc");
+        assert_eq!(subscriptions.take_events(all).unwrap().len(), 3);
+        let events = subscriptions.take_events(some).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, "1");
+        assert!(subscriptions.take_events(some).unwrap().is_empty());
+        assert!(subscriptions.unsubscribe(some));
+        assert!(subscriptions.take_events(some).is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//
+// Copyright 2019 Tamas Blummer
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! api access tokens of clients
+//!
+//! Only the hash of a token is stored. The apikey of the config is the admin token with all scopes.
+
+use bitcoin_hashes::{sha256, Hash};
+use bitcoin_hashes::hex::ToHex;
+use rand::{thread_rng, RngCore};
+use crate::db::SharedDB;
+use crate::error::Error;
+
+/// what a token permits
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// browse and subscribe to content
+    Read,
+    /// prepare publications
+    Publish,
+    /// move coins, fund, renew and revoke publications
+    Wallet,
+    /// manage tokens, policy and schemas, reveal the words of the wallet
+    Admin
+}
+
+pub const ALL_SCOPES: [Scope; 4] = [Scope::Read, Scope::Publish, Scope::Wallet, Scope::Admin];
+
+/// a token as listed, without its secret
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct TokenInfo {
+    pub name: String,
+    pub scopes: Vec<Scope>
+}
+
+/// the tokens clients authorize with
+pub struct Tokens {
+    db: SharedDB,
+    admin: sha256::Hash
+}
+
+impl Tokens {
+    pub fn new(db: SharedDB, admin_token: &str) -> Tokens {
+        Tokens { db, admin: sha256::Hash::hash(admin_token.as_bytes()) }
+    }
+
+    /// scopes a token permits, empty if the token is not known
+    pub fn scopes(&self, token: &str) -> Vec<Scope> {
+        let hash = sha256::Hash::hash(token.as_bytes());
+        if hash == self.admin {
+            return ALL_SCOPES.to_vec();
+        }
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.read_token_scopes(&hash).unwrap_or_default().unwrap_or_default()
+    }
+
+    /// create a new token, returns its secret, that is not stored
+    pub fn create(&self, name: &str, scopes: Vec<Scope>) -> Result<String, Error> {
+        let mut secret = [0u8; 16];
+        thread_rng().fill_bytes(&mut secret);
+        let token = secret.to_hex();
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        if tx.list_tokens()?.iter().any(|t| t.name == name) {
+            return Err(Error::Unsupported("a token with this name already exists"));
+        }
+        tx.store_token(name, &sha256::Hash::hash(token.as_bytes()), &scopes)?;
+        tx.commit();
+        Ok(token)
+    }
+
+    /// revoke a token, returns false if there was no token of this name
+    pub fn revoke(&self, name: &str) -> Result<bool, Error> {
+        let mut db = self.db.lock().unwrap();
+        let mut tx = db.transaction();
+        let deleted = tx.delete_token(name)? > 0;
+        tx.commit();
+        Ok(deleted)
+    }
+
+    pub fn list(&self) -> Result<Vec<TokenInfo>, Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction();
+        tx.list_tokens()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::DB;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_tokens () {
+        let mut db = DB::memory().unwrap();
+        {
+            let mut tx = db.transaction();
+            tx.create_tables();
+            tx.commit();
+        }
+        let tokens = Tokens::new(Arc::new(Mutex::new(db)), "admin");
+        assert_eq!(tokens.scopes("admin"), ALL_SCOPES.to_vec());
+        let token = tokens.create("browser", vec!(Scope::Read)).unwrap();
+        assert!(tokens.create("browser", vec!(Scope::Wallet)).is_err());
+        assert_eq!(tokens.scopes(token.as_str()), vec!(Scope::Read));
+        assert!(tokens.scopes("other").is_empty());
+        assert_eq!(tokens.list().unwrap(), vec!(TokenInfo { name: "browser".to_string(), scopes: vec!(Scope::Read) }));
+        assert!(tokens.revoke("browser").unwrap());
+        assert!(!tokens.revoke("browser").unwrap());
+        assert!(tokens.scopes(token.as_str()).is_empty());
+    }
+}
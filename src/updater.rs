@@ -30,6 +30,7 @@ use murmel::p2p::PeerId;
 use std::collections::HashMap;
 use crate::iblt::estimate_diff_size;
 use crate::iblt::IBLTEntry;
+use crate::content::ContentKey;
 use std::time::SystemTime;
 
 const MINIMUM_IBLT_SIZE: u32 = 100;
@@ -124,8 +125,23 @@ impl Updater {
                                         for entry in iblt.into_iter() {
                                             if let Ok(entry) = entry {
                                                 match entry {
-                                                    IBLTEntry::Inserted(key) =>
-                                                        request.push(sha256::Hash::from_slice(&key.digest[..]).unwrap()),
+                                                    IBLTEntry::Inserted(key) => {
+                                                        let id = sha256::Hash::from_slice(&key.digest[..]).unwrap();
+                                                        // do not fetch what we know is revoked, rather tell the peer
+                                                        let revocations = store.get_revocations(&key).expect("can not read revocations");
+                                                        if !revocations.is_empty() {
+                                                            for revocation in revocations {
+                                                                debug!("sending revocation of {} to peer={}", revocation.id, pid);
+                                                                self.p2p.send_network(pid, Message::Revoke(revocation));
+                                                            }
+                                                        }
+                                                        else if store.is_rejected(&id) {
+                                                            debug!("not asking for content {} rejected by local policy", id);
+                                                        }
+                                                        else {
+                                                            request.push(id);
+                                                        }
+                                                    },
                                                     _ => {}
                                                 };
                                             }
@@ -161,11 +177,33 @@ impl Updater {
                                     store.truncate_to_limit().expect("failed to truncate db to maz size");
                                 }
                             },
+                            Message::Revoke(revocation) => {
+                                debug!("received revocation of {} from peer={}", revocation.id, pid);
+                                let mut store = self.store.write().unwrap();
+                                match store.revoke_content(&revocation) {
+                                    Ok(true) => {
+                                        for peer in self.p2p.peers() {
+                                            if peer != pid {
+                                                self.p2p.send_network(peer, Message::Revoke(revocation.clone()));
+                                            }
+                                        }
+                                    },
+                                    Ok(false) => {},
+                                    Err(e) => debug!("failed to process revocation of {} from peer={} {:?}", revocation.id, pid, e)
+                                }
+                            },
                             Message::Get(ids) => {
                                 debug!("received {} get requests from peer={}", ids.len(), pid);
                                 let store = self.store.read().unwrap();
                                 for id in &ids {
-                                    if let Some(content) = store.get_content(id).expect("can not read content") {
+                                    // an id is the digest of content or the key of its funding
+                                    let content = if let Some(content) = store.get_content(id).expect("can not read content") { Some(content) }
+                                        else { store.get_funding(&ContentKey::new(&id[..])).expect("can not read funding") };
+                                    if let Some(content) = content {
+                                        if store.rejects(&content) {
+                                            debug!("not delivering content {} rejected by local policy to peer={}", id, pid);
+                                            continue;
+                                        }
                                         debug!("delivering content {} to peer={}", id, pid);
                                         self.p2p.send_network(pid, Message::Content(content));
                                     }
@@ -177,6 +215,14 @@ impl Updater {
                             _ => {  }
                         }
                     },
+                    PeerMessage::Outgoing(Message::Revoke(revocation)) => {
+                        debug!("broadcasting revocation of {}", revocation.id);
+                        self.p2p.broadcast(Message::Revoke(revocation));
+                    },
+                    PeerMessage::Outgoing(Message::Content(content)) => {
+                        debug!("broadcasting co-funding of {}", content.ad.digest());
+                        self.p2p.broadcast(Message::Content(content));
+                    },
                     _ => {}
                 }
             }
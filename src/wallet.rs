@@ -14,17 +14,21 @@
 // limitations under the License.
 //
 use bitcoin::network::constants::Network;
-use bitcoin_hashes::{sha256, sha256d};
-use bitcoin_wallet::account::{MasterAccount, Unlocker, AccountAddressType, Account};
-use bitcoin::util::bip32::ExtendedPubKey;
-use bitcoin::{Block, Transaction, Address, TxIn, Script, TxOut, SigHashType, PublicKey};
+use bitcoin_hashes::{sha256, sha256d, hash160, Hash};
+use bitcoin_wallet::account::{MasterAccount, Unlocker, AccountAddressType, Account, KeyDerivation, Seed};
+use bitcoin::util::bip32::{ExtendedPubKey, Fingerprint, DerivationPath, ChildNumber};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::blockdata::script::Builder;
+use bitcoin::{Block, Transaction, Address, TxIn, Script, TxOut, SigHashType, PublicKey, OutPoint};
 use bitcoin_wallet::proved::ProvedTransaction;
-use bitcoin_wallet::coins::{Coins};
+use bitcoin_wallet::coins::{Coins, Coin};
 use crate::error::Error;
 use rand::{RngCore, thread_rng};
+use rand::seq::SliceRandom;
 use bitcoin::consensus::serialize;
 use crate::trunk::Trunk;
 use std::sync::Arc;
+use std::collections::{HashSet, HashMap};
 use bitcoin_wallet::mnemonic::Mnemonic;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -33,12 +37,54 @@ const KEY_PURPOSE: u32 = 0xb1ad;
 const DUST :u64 = 546;
 const MAX_FEE_PER_VBYTE: u64 = 100;
 const MIN_FEE_PER_VBYTE: u64 = 1;
-const MAX_TERM:u16 = 6*24*30; // approx. one month.
+pub const MAX_TERM:u16 = 6*24*30; // approx. one month.
 const RBF:u32 = 0xffffffff - 2;
 
+/// a coin funding a publication
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Funding {
+    pub id: String,
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    /// height of confirmation
+    pub height: Option<u32>,
+    /// height the coin can be spent at
+    pub unlock: Option<u32>,
+    pub matured: bool
+}
+
+/// a coin of the wallet
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Unspent {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    /// 0 if unconfirmed
+    pub confirmations: u32,
+    /// blocks after confirmation until the coin can be spent
+    pub csv: Option<u16>,
+    /// publication funded by the coin
+    pub id: Option<String>,
+    /// can be spent now
+    pub available: bool,
+    /// excluded from automatic selection of inputs
+    pub locked: bool
+}
+
 pub struct Wallet {
     coins: Coins,
-    pub master: MasterAccount
+    locked: HashSet<OutPoint>,
+    pub master: MasterAccount,
+    /// BIP39 mnemonic encrypted with the passphrase, empty if not known
+    mnemonic: Vec<u8>
+}
+
+const WATCH_ONLY: Error = Error::Unsupported("watch-only wallet can not sign");
+
+// script of a version 0 pay to witness public key hash output
+fn p2wpkh_script(public: &PublicKey) -> Script {
+    Builder::new().push_int(0).push_slice(&hash160::Hash::hash(public.to_bytes().as_slice())[..]).into_script()
 }
 
 impl Wallet {
@@ -75,6 +121,80 @@ impl Wallet {
         self.coins.available_balance(height, height_for_block)
     }
 
+    /// exclude a coin from automatic selection of inputs, false if it was already locked
+    pub fn lock(&mut self, point: OutPoint) -> bool {
+        self.locked.insert(point)
+    }
+
+    /// false if the coin was not locked
+    pub fn unlock(&mut self, point: &OutPoint) -> bool {
+        self.locked.remove(point)
+    }
+
+    pub fn locked(&self) -> &HashSet<OutPoint> {
+        &self.locked
+    }
+
+    /// coins of the wallet, ordered by confirmations
+    pub fn unspent(&self, trunk: Arc<dyn Trunk>) -> Vec<Unspent> {
+        let height = trunk.len();
+        let proofs = self.coins.proofs();
+        let mut unspent = self.coins.confirmed().iter().map(|c| (c, true))
+            .chain(self.coins.unconfirmed().iter().map(|c| (c, false)))
+            .map(|((point, coin), confirmed)| {
+                let confirmed = if confirmed { proofs.get(&point.txid).and_then(|p| trunk.get_height(p.get_block_hash())) } else { None };
+                let confirmations = confirmed.map_or(0, |c| height - c + 1);
+                Unspent {
+                    txid: point.txid.to_string(),
+                    vout: point.vout,
+                    value: coin.output.value,
+                    confirmations,
+                    csv: coin.derivation.csv,
+                    id: coin.derivation.tweak.as_ref().map(hex::encode),
+                    available: confirmed.map_or(false, |c| height >= c + coin.derivation.csv.unwrap_or(0) as u32),
+                    locked: self.locked.contains(point)
+                }
+            }).collect::<Vec<_>>();
+        unspent.sort_by_key(|u| u.confirmations);
+        unspent
+    }
+
+    // the given coins if all are available, otherwise coins of sufficient amount not locked, smallest first
+    fn choose_inputs(&self, amount: u64, height: u32, trunk: &Arc<dyn Trunk>, inputs: Option<Vec<OutPoint>>) -> Result<Vec<(OutPoint, Coin, u32)>, Error> {
+        let mut available = self.coins.available_coins(height, |h| trunk.get_height(h));
+        if self.is_watch_only() {
+            // keys of funding are tweaked, signers elsewhere would not know how to derive them
+            available.retain(|(_, c, _)| c.derivation.tweak.is_none());
+        }
+        if let Some(inputs) = inputs {
+            if inputs.iter().collect::<HashSet<_>>().len() != inputs.len() {
+                return Err(Error::Unsupported("an input is given more than once"));
+            }
+            return inputs.iter().map(|point| available.iter().find(|(p, _, _)| p == point).cloned()
+                .ok_or(Error::Unsupported("input is not an available coin of the wallet"))).collect();
+        }
+        let mut have = available.into_iter().filter(|(p, _, _)| !self.locked.contains(p)).collect::<Vec<_>>();
+        have.sort_by_key(|(_, c, _)| c.output.value);
+        let mut sum = 0u64;
+        let mut chosen = Vec::new();
+        for coin in have {
+            if sum >= amount {
+                break;
+            }
+            sum += coin.1.output.value;
+            chosen.push(coin);
+        }
+        if sum > amount {
+            // drop some if possible
+            let mut change = sum - amount;
+            while let Some(index) = chosen.iter().position(|(_, c, _)| c.output.value <= change) {
+                change -= chosen.remove(index).1.output.value;
+            }
+        }
+        chosen.shuffle(&mut thread_rng());
+        Ok(chosen)
+    }
+
     pub fn unwind_tip(&mut self, block_hash: &sha256d::Hash) {
         self.coins.unwind_tip(block_hash)
     }
@@ -91,27 +211,24 @@ impl Wallet {
         self.coins.proofs().get(txid)
     }
 
-    pub fn fund<W> (&mut self, id: &sha256::Hash, mut term: u16, passpharse: String, mut fee_per_vbyte: u64, amount: u64, trunk: Arc<dyn Trunk>, scripter: W) -> Result<(Transaction, PublicKey, u64), Error>
+    /// a new key committing to a publication for term blocks, answers its address and the funder's public key
+    pub fn commit<W> (&mut self, id: &sha256::Hash, term: u16, scripter: W) -> (Address, PublicKey)
         where W: FnOnce(&PublicKey, Option<u16>) -> Script {
-        let network = self.master.master_public().network;
-        let mut unlocker = Unlocker::new(
-            self.master.encrypted(), passpharse.as_str(),
-            network, Some(self.master.master_public()))?;
+        let commit_account = self.master.get_mut((1, 0)).unwrap();
+        let kix = commit_account.add_script_key(scripter, Some(&id[..]), Some(std::cmp::min(MAX_TERM, term))).expect("can not commit to ad");
+        (commit_account.get_key(kix).unwrap().address.clone(), commit_account.compute_base_public_key(kix).expect("can not compute base public key"))
+    }
+
+    /// fund a commitment with amount, using the given coins as inputs if any.
+    /// The transaction is not signed if the wallet is watch-only.
+    pub fn fund (&mut self, contract_address: &Address, passpharse: String, mut fee_per_vbyte: u64, amount: u64, inputs: Option<Vec<OutPoint>>, trunk: Arc<dyn Trunk>) -> Result<(Transaction, Vec<TxOut>, u64), Error> {
+        let mut unlocker = self.unlocker_unless_watch_only(passpharse.as_str())?;
         fee_per_vbyte = std::cmp::min(MAX_FEE_PER_VBYTE, std::cmp::max(MIN_FEE_PER_VBYTE, fee_per_vbyte));
-        term = std::cmp::min(MAX_TERM, term);
         let mut fee = 0;
         let change_address = self.master.get_mut((0,1)).unwrap().next_key().unwrap().address.clone();
         let height = trunk.len();
-        let coins = self.coins.choose_inputs(amount, height, |h| trunk.get_height(h));
+        let coins = self.choose_inputs(amount, height, &trunk, inputs)?;
         let total_input = coins.iter().map(|(_,c,_)|c.output.value).sum::<u64>();
-        let contract_address;
-        let funder;
-        {
-            let commit_account = self.master.get_mut((1, 0)).unwrap();
-            let kix = commit_account.add_script_key(scripter, Some(&id[..]), Some(term)).expect("can not commit to ad");
-            contract_address = commit_account.get_key(kix).unwrap().address.clone();
-            funder = commit_account.compute_base_public_key(kix).expect("can not compute base public key");
-        }
         if amount > total_input {
             return Err(Error::Unsupported("insufficient funds"));
         }
@@ -146,6 +263,172 @@ impl Wallet {
                     script_pubkey: change_address.script_pubkey()
                 });
             }
+            if let Some(ref mut unlocker) = unlocker {
+                if self.master.sign(&mut tx, SigHashType::All,
+                                    &|point| {
+                                        coins.iter().find(|(o, _, _)| *o == *point).map(|(_, c, _)| c.output.clone())
+                                    }, unlocker)?
+                    != tx.input.len () {
+                    error!("could not sign all inputs of our transaction {:?} {}", tx, hex::encode(serialize(&tx)));
+                    return Err(Error::Unsupported("could not sign for all inputs"));
+                }
+            }
+            else {
+                self.sign_placeholder(&mut tx, &coins);
+            }
+            if fee == 0 {
+                fee = (tx.get_weight() as u64 * fee_per_vbyte + 3)/4;
+            }
+            else {
+                debug!("compiled transaction to withdraw {} fee {}", amount, fee);
+                #[cfg(feature="bitcoinconsensus")]
+                if unlocker.is_some() {
+                    match tx.verify(|o| coins.iter().find_map(|(p, c, _)| if *p == *o { Some(c.output.clone()) } else { None })) {
+                        Ok(()) => {},
+                        Err(e) => {
+                            error!("our transaction does not verify {:?} {}", tx, hex::encode(serialize(&tx)));
+                            return Err(Error::Script(e))
+                        }
+                    }
+                }
+                break;
+            }
+        }
+        if unlocker.is_some() {
+            self.coins.process_unconfirmed_transaction(&mut self.master, &tx);
+        } else {
+            Self::remove_signatures(&mut tx);
+        }
+        Ok((tx, coins.into_iter().map(|(_, c, _)| c.output).collect(), fee))
+    }
+
+    /// spend matured funding of a publication into a new commitment to the same publication,
+    /// before the funding matures commit coins of the wallet of the same amount, that take its place as it expires
+    pub fn renew<W> (&mut self, id: &sha256::Hash, term: u16, passpharse: String, fee_per_vbyte: u64, trunk: Arc<dyn Trunk>, scripter: W) -> Result<(Transaction, Vec<TxOut>, PublicKey, u64), Error>
+        where W: FnOnce(&PublicKey, Option<u16>) -> Script {
+        if self.is_watch_only() {
+            return Err(WATCH_ONLY);
+        }
+        let height = trunk.len();
+        let coins = self.matured_funding(height, &trunk).into_iter()
+            .filter(|(_, c, _)| c.derivation.tweak.as_ref().map_or(false, |t| t.as_slice() == &id[..]))
+            .collect::<Vec<_>>();
+        if coins.is_empty() {
+            let pending = self.funded(trunk.clone()).iter()
+                .filter(|f| f.height.is_some() && !f.matured && f.id == hex::encode(&id[..]))
+                .map(|f| f.value).sum::<u64>();
+            if pending == 0 {
+                return Err(Error::Unsupported("no funding for this publication"));
+            }
+            let (contract_address, funder) = self.commit(id, term, scripter);
+            let (tx, spent, fee) = self.fund(&contract_address, passpharse, fee_per_vbyte, pending, None, trunk)?;
+            return Ok((tx, spent, funder, fee));
+        }
+        let (contract_address, funder) = self.commit(id, term, scripter);
+        let (tx, spent, fee) = self.spend_all(passpharse, &coins, height, &contract_address, fee_per_vbyte)?;
+        Ok((tx, spent, funder, fee))
+    }
+
+    /// funding of publications by this wallet
+    pub fn funded(&self, trunk: Arc<dyn Trunk>) -> Vec<Funding> {
+        let height = trunk.len();
+        let proofs = self.coins.proofs();
+        let mut funded = self.coins.confirmed().iter().chain(self.coins.unconfirmed().iter())
+            .filter_map(|(point, coin)| {
+                if let (Some(ref tweak), Some(csv)) = (&coin.derivation.tweak, coin.derivation.csv) {
+                    let confirmed = proofs.get(&point.txid).and_then(|p| trunk.get_height(p.get_block_hash()));
+                    let unlock = confirmed.map(|h| h + csv as u32);
+                    Some(Funding {
+                        id: hex::encode(tweak),
+                        txid: point.txid.to_string(),
+                        vout: point.vout,
+                        value: coin.output.value,
+                        height: confirmed,
+                        unlock,
+                        matured: unlock.map_or(false, |u| height >= u)
+                    })
+                }
+                else { None }
+            }).collect::<Vec<_>>();
+        funded.sort_by_key(|f| f.unlock);
+        funded
+    }
+
+    /// sign the digest of a funded publication with the key of each of its commitments
+    pub fn sign_revocations(&self, id: &sha256::Hash, passpharse: String) -> Result<Vec<Vec<u8>>, Error> {
+        let mut unlocker = self.unlocker(passpharse.as_str())?;
+        let account = self.master.get((1,0)).unwrap();
+        let mut signatures = Vec::new();
+        for (kix, _, tweak, _) in account.get_scripts()
+            .filter(|(_, _, t, _)| t.as_ref().map_or(false, |t| t.as_slice() == &id[..])) {
+            let key = unlocker.unlock(account.address_type(), 1, 0, kix, tweak)?;
+            signatures.push(unlocker.context().sign(&id[..], &key)?.serialize_der().to_vec());
+        }
+        if signatures.is_empty() {
+            return Err(Error::Unsupported("publication was not funded by this wallet"));
+        }
+        Ok(signatures)
+    }
+
+    /// check if the passphrase unlocks the wallet
+    pub fn verify_passphrase(&self, passpharse: &str) -> Result<(), Error> {
+        Unlocker::new_for_master(&self.master, passpharse)?;
+        Ok(())
+    }
+
+    /// true if there is funding that could be swept
+    pub fn has_matured_funding(&self, trunk: Arc<dyn Trunk>) -> bool {
+        !self.matured_funding(trunk.len(), &trunk).is_empty()
+    }
+
+    /// spend all matured funding to a change address of the wallet
+    pub fn sweep (&mut self, passpharse: String, fee_per_vbyte: u64, trunk: Arc<dyn Trunk>) -> Result<(Transaction, Vec<TxOut>, u64), Error> {
+        let height = trunk.len();
+        let coins = self.matured_funding(height, &trunk);
+        if coins.is_empty() {
+            return Err(Error::Unsupported("no matured funding"));
+        }
+        let change_address = self.master.get_mut((0,1)).unwrap().next_key().unwrap().address.clone();
+        self.spend_all(passpharse, &coins, height, &change_address, fee_per_vbyte)
+    }
+
+    fn matured_funding(&self, height: u32, trunk: &Arc<dyn Trunk>) -> Vec<(OutPoint, Coin, u32)> {
+        self.coins.available_coins(height, |h| trunk.get_height(h)).into_iter()
+            .filter(|(_, c, _)| c.derivation.csv.is_some() && c.derivation.tweak.is_some())
+            .collect()
+    }
+
+    // spend all coins to a single output paying the fee from it
+    fn spend_all (&mut self, passpharse: String, coins: &[(OutPoint, Coin, u32)], height: u32, address: &Address, mut fee_per_vbyte: u64) -> Result<(Transaction, Vec<TxOut>, u64), Error> {
+        let mut unlocker = self.unlocker(passpharse.as_str())?;
+        fee_per_vbyte = std::cmp::min(MAX_FEE_PER_VBYTE, std::cmp::max(MIN_FEE_PER_VBYTE, fee_per_vbyte));
+        let amount = coins.iter().map(|(_,c,_)|c.output.value).sum::<u64>();
+        let mut fee = 0;
+        let mut tx = Transaction {
+            input: coins.iter().map(|(point, coin, h)|
+                TxIn {
+                    previous_output: *point,
+                    script_sig: Script::new(),
+                    sequence: if let Some(csv) = coin.derivation.csv {
+                        std::cmp::min(csv as u32, height - *h)
+                    }else{RBF},
+                    witness: vec![]
+                }).collect(),
+            output: Vec::new(),
+            version: 2,
+            lock_time: 0
+        };
+        loop {
+            tx.output.clear();
+            if amount - fee > DUST {
+                tx.output.push(TxOut {
+                    value: amount - fee,
+                    script_pubkey: address.script_pubkey()
+                });
+            }
+            else {
+                return Err(Error::Unsupported("spent amount is less than the fees needed (+DUST limit)"));
+            }
             if self.master.sign(&mut tx, SigHashType::All,
                                 &|point| {
                                     coins.iter().find(|(o, _, _)| *o == *point).map(|(_, c, _)| c.output.clone())
@@ -158,7 +441,7 @@ impl Wallet {
                 fee = (tx.get_weight() as u64 * fee_per_vbyte + 3)/4;
             }
             else {
-                debug!("compiled transaction to withdraw {} fee {}", amount, fee);
+                debug!("compiled transaction to spend {} fee {}", amount, fee);
                 #[cfg(feature="bitcoinconsensus")]
                 {
                     match tx.verify(|o| coins.iter().find_map(|(p, c, _)| if *p == *o { Some(c.output.clone()) } else { None })) {
@@ -173,21 +456,19 @@ impl Wallet {
             }
         }
         self.coins.process_unconfirmed_transaction(&mut self.master, &tx);
-        Ok((tx, funder, fee))
+        Ok((tx, coins.iter().map(|(_, c, _)| c.output.clone()).collect(), fee))
     }
 
-    pub fn withdraw (&mut self, passpharse: String, address: Address, mut fee_per_vbyte: u64, amount: Option<u64>, trunk: Arc<dyn Trunk>) -> Result<(Transaction, u64), Error> {
-        let network = self.master.master_public().network;
-        let mut unlocker = Unlocker::new(
-            self.master.encrypted(), passpharse.as_str(),
-            network, Some(self.master.master_public()))?;
+    /// withdraw amount or all of the given coins or if none given all available and not locked.
+    /// The transaction is not signed if the wallet is watch-only.
+    pub fn withdraw (&mut self, passpharse: String, address: Address, mut fee_per_vbyte: u64, amount: Option<u64>, inputs: Option<Vec<OutPoint>>, trunk: Arc<dyn Trunk>) -> Result<(Transaction, Vec<TxOut>, u64), Error> {
+        let mut unlocker = self.unlocker_unless_watch_only(passpharse.as_str())?;
         let height = trunk.len();
-        let balance = self.available_balance(height, |h| trunk.get_height(h));
-        let amount = amount.unwrap_or(balance);
+        let coins = self.choose_inputs(amount.unwrap_or(std::u64::MAX), height, &trunk, inputs)?;
+        let amount = amount.unwrap_or(coins.iter().map(|(_,c,_)|c.output.value).sum::<u64>());
         fee_per_vbyte = std::cmp::min(MAX_FEE_PER_VBYTE, std::cmp::max(MIN_FEE_PER_VBYTE, fee_per_vbyte));
         let mut fee = 0;
         let change_address = self.master.get_mut((0,1)).unwrap().next_key().unwrap().address.clone();
-        let coins = self.coins.choose_inputs(amount, height, |h| trunk.get_height(h));
         let total_input = coins.iter().map(|(_,c,_)|c.output.value).sum::<u64>();
         if amount > total_input {
             return Err(Error::Unsupported("insufficient funds"));
@@ -223,13 +504,18 @@ impl Wallet {
                     script_pubkey: change_address.script_pubkey()
                 });
             }
-            if self.master.sign(&mut tx, SigHashType::All,
-                                &|point| {
-                                    coins.iter().find(|(o, _, _)| *o == *point).map(|(_, c, _)| c.output.clone())
-                                }, &mut unlocker)?
-                != tx.input.len () {
-                error!("could not sign all inputs of our transaction {:?} {}", tx, hex::encode(serialize(&tx)));
-                return Err(Error::Unsupported("could not sign for all inputs"));
+            if let Some(ref mut unlocker) = unlocker {
+                if self.master.sign(&mut tx, SigHashType::All,
+                                    &|point| {
+                                        coins.iter().find(|(o, _, _)| *o == *point).map(|(_, c, _)| c.output.clone())
+                                    }, unlocker)?
+                    != tx.input.len () {
+                    error!("could not sign all inputs of our transaction {:?} {}", tx, hex::encode(serialize(&tx)));
+                    return Err(Error::Unsupported("could not sign for all inputs"));
+                }
+            }
+            else {
+                self.sign_placeholder(&mut tx, &coins);
             }
             if fee == 0 {
                 fee = (tx.get_weight() as u64 * fee_per_vbyte + 3)/4;
@@ -237,7 +523,7 @@ impl Wallet {
             else {
                 debug!("compiled transaction to withdraw {} fee {}", amount, fee);
                 #[cfg(feature="bitcoinconsensus")]
-                {
+                if unlocker.is_some() {
                     match tx.verify(|o| coins.iter().find_map(|(p, c, _)| if *p == *o { Some(c.output.clone()) } else { None })) {
                         Ok(()) => {},
                         Err(e) => {
@@ -249,10 +535,196 @@ impl Wallet {
                 break;
             }
         }
-        self.coins.process_unconfirmed_transaction(&mut self.master, &tx);
+        if unlocker.is_some() {
+            self.coins.process_unconfirmed_transaction(&mut self.master, &tx);
+        } else {
+            Self::remove_signatures(&mut tx);
+        }
+        Ok((tx, coins.into_iter().map(|(_, c, _)| c.output).collect(), fee))
+    }
+
+    /// replace an unconfirmed transaction of this wallet with one paying fee_per_vbyte, spent are the
+    /// outputs spent by its inputs. The higher fee is paid from the change or from the only output.
+    /// The caller has to reload coins once the replacement is stored.
+    pub fn bump_fee (&self, passpharse: String, transaction: &Transaction, spent: &[TxOut], mut fee_per_vbyte: u64) -> Result<(Transaction, u64), Error> {
+        let mut unlocker = self.unlocker(passpharse.as_str())?;
+        if spent.len() != transaction.input.len() {
+            return Err(Error::Unsupported("spent outputs do not match inputs of the transaction"));
+        }
+        if transaction.input.iter().any(|i| i.sequence > RBF) {
+            return Err(Error::Unsupported("transaction does not signal replaceability"));
+        }
+        fee_per_vbyte = std::cmp::min(MAX_FEE_PER_VBYTE, std::cmp::max(MIN_FEE_PER_VBYTE, fee_per_vbyte));
+        let old_fee = spent.iter().map(|o| o.value).sum::<u64>() - transaction.output.iter().map(|o| o.value).sum::<u64>();
+        let change = self.master.get((0,1)).unwrap();
+        let commitments = self.master.get((1,0)).unwrap();
+        let payer = if transaction.output.len() == 1 { Some(0) } else {
+            transaction.output.iter().position(|o| change.instantiated().iter().any(|k| k.address.script_pubkey() == o.script_pubkey))
+        }.ok_or(Error::Unsupported("transaction has no change to pay a higher fee from"))?;
+        // the commitment of a funding or renewal is not reduced, that would fund less than requested
+        if commitments.instantiated().iter().any(|k| k.address.script_pubkey() == transaction.output[payer].script_pubkey) {
+            return Err(Error::Unsupported("funding has no change to pay a higher fee from"));
+        }
+        // a replacement has to pay for its own relay in addition to the replaced fee
+        let vsize = (transaction.get_weight() as u64 + 3) / 4;
+        let fee = std::cmp::max(vsize * fee_per_vbyte, old_fee + vsize * MIN_FEE_PER_VBYTE);
+        if transaction.output[payer].value < fee - old_fee + DUST {
+            return Err(Error::Unsupported("output is less than the higher fee (+DUST limit)"));
+        }
+        let mut tx = transaction.clone();
+        tx.output[payer].value -= fee - old_fee;
+        for input in tx.input.iter_mut() {
+            input.script_sig = Script::new();
+            input.witness.clear();
+        }
+        if self.master.sign(&mut tx, SigHashType::All,
+                            &|point| {
+                                transaction.input.iter().position(|i| i.previous_output == *point).map(|ix| spent[ix].clone())
+                            }, &mut unlocker)?
+            != tx.input.len () {
+            error!("could not sign all inputs of our transaction {:?} {}", tx, hex::encode(serialize(&tx)));
+            return Err(Error::Unsupported("could not sign for all inputs"));
+        }
+        debug!("compiled replacement of {} fee {}", transaction.txid(), fee);
+        #[cfg(feature="bitcoinconsensus")]
+        {
+            match tx.verify(|o| transaction.input.iter().position(|i| i.previous_output == *o).map(|ix| spent[ix].clone())) {
+                Ok(()) => {},
+                Err(e) => {
+                    error!("our transaction does not verify {:?} {}", tx, hex::encode(serialize(&tx)));
+                    return Err(Error::Script(e))
+                }
+            }
+        }
         Ok((tx, fee))
     }
 
+    /// replace coins with those reloaded from storage
+    pub fn set_coins(&mut self, coins: Coins) {
+        self.coins = coins;
+    }
+
+    /// true if the wallet has no keys to sign with
+    pub fn is_watch_only(&self) -> bool {
+        self.master.encrypted().is_empty()
+    }
+
+    fn unlocker(&self, passpharse: &str) -> Result<Unlocker, Error> {
+        if self.is_watch_only() {
+            return Err(WATCH_ONLY);
+        }
+        Ok(Unlocker::new_for_master(&self.master, passpharse)?)
+    }
+
+    fn unlocker_unless_watch_only(&self, passpharse: &str) -> Result<Option<Unlocker>, Error> {
+        if self.is_watch_only() {
+            return Ok(None);
+        }
+        Ok(Some(self.unlocker(passpharse)?))
+    }
+
+    // placeholders of the size of signatures, to compute the fee of a transaction signed elsewhere
+    fn sign_placeholder(&self, tx: &mut Transaction, coins: &[(OutPoint, Coin, u32)]) {
+        for input in tx.input.iter_mut() {
+            if let Some((_, coin, _)) = coins.iter().find(|(o, _, _)| *o == input.previous_output) {
+                let d = &coin.derivation;
+                match self.master.get((d.account, d.sub)).unwrap().address_type() {
+                    AccountAddressType::P2PKH => {
+                        input.script_sig = Builder::new().push_slice(&[0u8; 72]).push_slice(&[0u8; 33]).into_script();
+                    },
+                    AccountAddressType::P2SHWPKH => {
+                        input.script_sig = Builder::new().push_slice(&[0u8; 22]).into_script();
+                        input.witness = vec!(vec!(0u8; 72), vec!(0u8; 33));
+                    },
+                    _ => {
+                        input.witness = vec!(vec!(0u8; 72), vec!(0u8; 33));
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove_signatures(tx: &mut Transaction) {
+        for input in tx.input.iter_mut() {
+            input.script_sig = Script::new();
+            input.witness.clear();
+        }
+    }
+
+    // fingerprint of the master key and path of a key of the wallet
+    fn key_path(&self, d: &KeyDerivation) -> (Fingerprint, DerivationPath) {
+        let account = self.master.get((d.account, d.sub)).unwrap();
+        let coin_type = if account.network() == Network::Bitcoin { 0 } else { 1 };
+        (self.master.master_public().fingerprint(), DerivationPath::from(vec!(
+            ChildNumber::Hardened { index: account.address_type().as_u32() },
+            ChildNumber::Hardened { index: coin_type },
+            ChildNumber::Hardened { index: d.account },
+            ChildNumber::Normal { index: d.sub },
+            ChildNumber::Normal { index: d.kix })))
+    }
+
+    /// partially signed transaction (BIP174) to be signed elsewhere, spent are the outputs spent by its inputs
+    pub fn psbt(&self, tx: &Transaction, spent: &[TxOut]) -> Result<PartiallySignedTransaction, Error> {
+        let scripts = self.master.get_scripts().collect::<HashMap<_, _>>();
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone())
+            .map_err(|_| Error::Unsupported("transaction is signed"))?;
+        for (input, output) in psbt.inputs.iter_mut().zip(spent.iter()) {
+            let d = scripts.get(&output.script_pubkey).ok_or(Error::Unsupported("input does not spend a coin of the wallet"))?;
+            let key = self.master.get((d.account, d.sub)).unwrap().get_key(d.kix).unwrap();
+            if key.address.script_pubkey().is_p2sh() {
+                input.redeem_script = Some(p2wpkh_script(&key.public));
+            }
+            input.witness_utxo = Some(output.clone());
+            input.sighash_type = Some(SigHashType::All);
+            input.hd_keypaths.insert(key.public, self.key_path(d));
+        }
+        for (output, txout) in psbt.outputs.iter_mut().zip(tx.output.iter()) {
+            // change
+            if let Some(d) = scripts.get(&txout.script_pubkey).filter(|d| d.tweak.is_none()) {
+                let key = self.master.get((d.account, d.sub)).unwrap().get_key(d.kix).unwrap();
+                output.hd_keypaths.insert(key.public, self.key_path(d));
+            }
+        }
+        Ok(psbt)
+    }
+
+    /// finalize a partially signed transaction of this wallet signed elsewhere, spent are the outputs spent by its inputs
+    pub fn finalize(&mut self, mut psbt: PartiallySignedTransaction, spent: &[TxOut]) -> Result<Transaction, Error> {
+        if psbt.inputs.len() != spent.len() {
+            return Err(Error::Unsupported("spent outputs do not match inputs of the transaction"));
+        }
+        for (input, output) in psbt.inputs.iter_mut().zip(spent.iter()) {
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                continue;
+            }
+            let (public, signature) = input.partial_sigs.iter().next().map(|(k, s)| (*k, s.clone()))
+                .ok_or(Error::Unsupported("input is not signed"))?;
+            if output.script_pubkey.is_p2pkh() {
+                input.final_script_sig = Some(Builder::new().push_slice(signature.as_slice()).push_key(&public).into_script());
+            } else {
+                if output.script_pubkey.is_p2sh() {
+                    let redeem_script = p2wpkh_script(&public);
+                    input.final_script_sig = Some(Builder::new().push_slice(redeem_script.as_bytes()).into_script());
+                }
+                input.final_script_witness = Some(vec!(signature, public.to_bytes()));
+            }
+            input.partial_sigs.clear();
+        }
+        let tx = psbt.extract_tx();
+        #[cfg(feature="bitcoinconsensus")]
+        {
+            match tx.verify(|o| tx.input.iter().position(|i| i.previous_output == *o).map(|ix| spent[ix].clone())) {
+                Ok(()) => {},
+                Err(e) => {
+                    error!("signed transaction does not verify {:?} {}", tx, hex::encode(serialize(&tx)));
+                    return Err(Error::Script(e))
+                }
+            }
+        }
+        self.coins.process_unconfirmed_transaction(&mut self.master, &tx);
+        Ok(tx)
+    }
+
     pub fn from_storage(coins: Coins, mut master: MasterAccount) -> Wallet {
         for (_, coin) in coins.confirmed() {
             let ref d = coin.derivation;
@@ -262,12 +734,51 @@ impl Wallet {
             let ref d = coin.derivation;
             master.get_mut((d.account, d.sub)).unwrap().do_look_ahead(Some(d.kix)).expect("can not look ahead of storage");
         }
-        Wallet { coins: coins, master }
+        Wallet { coins, locked: HashSet::new(), master, mnemonic: Vec::new() }
     }
 
     pub fn from_encrypted(encrypted: &[u8], public_master_key: ExtendedPubKey, birth: u64) -> Wallet {
         let master = MasterAccount::from_encrypted(encrypted, public_master_key, birth);
-        Wallet { coins: Coins::new(), master}
+        Wallet { coins: Coins::new(), locked: HashSet::new(), master, mnemonic: Vec::new() }
+    }
+
+    /// wallet of a BIP39 mnemonic, the mnemonic is also stored encrypted with the passphrase
+    pub fn from_mnemonic(bitcoin_network: Network, mnemonic: &Mnemonic, birth: u64, passphrase: &str) -> Result<Wallet, Error> {
+        let mut master = MasterAccount::from_mnemonic(mnemonic, birth, bitcoin_network, passphrase, None)?;
+        let mut unlocker = Unlocker::new_for_master(&master, passphrase)?;
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2SHWPKH, 0, 0, KEY_LOOK_AHEAD)?);
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2SHWPKH, 0, 1, KEY_LOOK_AHEAD)?);
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WSH(KEY_PURPOSE), 1, 0, 0)?);
+        let mnemonic = Seed(mnemonic.to_string().into_bytes()).encrypt(passphrase)?;
+        Ok(Wallet { master, coins: Coins::new(), locked: HashSet::new(), mnemonic })
+    }
+
+    /// the mnemonic stored encrypted, empty if not known
+    pub fn encrypted_mnemonic(&self) -> &Vec<u8> {
+        &self.mnemonic
+    }
+
+    pub fn set_encrypted_mnemonic(&mut self, encrypted: Vec<u8>) {
+        self.mnemonic = encrypted;
+    }
+
+    /// the BIP39 mnemonic of the wallet's key
+    pub fn mnemonic(&self, passphrase: &str) -> Result<Mnemonic, Error> {
+        if self.is_watch_only() {
+            return Err(WATCH_ONLY);
+        }
+        self.verify_passphrase(passphrase)?;
+        if self.mnemonic.is_empty() {
+            return Err(Error::Unsupported("the mnemonic of this wallet was not stored"));
+        }
+        let words = String::from_utf8(Seed::decrypt(self.mnemonic.as_slice(), passphrase)?.0)
+            .map_err(|_| Error::Unsupported("stored mnemonic is malformed"))?;
+        let mnemonic = Mnemonic::from_str(words.as_str())?;
+        let network = self.master.master_public().network;
+        if MasterAccount::from_mnemonic(&mnemonic, self.master.birth(), network, passphrase, None)?.master_public() != self.master.master_public() {
+            return Err(Error::Unsupported("stored mnemonic is not that of the wallet"));
+        }
+        Ok(mnemonic)
     }
 
     pub fn new(bitcoin_network: Network) -> Wallet {
@@ -275,29 +786,13 @@ impl Wallet {
         eprintln!("============================= Initializing bitcoin wallet =================================");
         eprintln!("The randomly generated key for your wallet will be stored ENCRYPTED in the config-file");
         eprintln!();
-        eprint!("Set wallet encryption password (minimum length 8):");
-        let mut password = String::new();
-        std::io::stdin().read_line(&mut password).expect("expecting a password");
-        password.remove(password.len()-1); // remove EOL
-        assert!(password.len() >= 8, "Password should have at least 8 characters");
+        let password = read_password();
         let mut entropy = [0u8;16];
         thread_rng().fill_bytes(&mut entropy);
         let mnemonic = Mnemonic::new(&entropy).expect("can not create mnemonic");
-        let mut master = MasterAccount::from_mnemonic(&mnemonic, SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                                                bitcoin_network, password.as_str(), None).expect("can not generate wallet");
-        let mut unlocker = Unlocker::new(master.encrypted().as_slice(),
-                                         password.as_str(), bitcoin_network,
-                                         Some(&master.master_public())).expect("Internal error in wallet generation");
-        let receiver = Account::new(&mut unlocker, AccountAddressType::P2SHWPKH, 0, 0, KEY_LOOK_AHEAD)
-            .expect("can not create receiver account");
-        master.add_account(receiver);
-        let change = Account::new(&mut unlocker, AccountAddressType::P2SHWPKH, 0, 1, KEY_LOOK_AHEAD)
-            .expect("can not create change account");
-        master.add_account(change);
-        let commitments = Account::new(&mut unlocker, AccountAddressType::P2WSH(KEY_PURPOSE), 1, 0, 0)
-            .expect("can not create commitments account");
-        master.add_account(commitments);
-        let receiver = master.get((0,0)).unwrap().get_key(0).unwrap().address.clone();
+        let wallet = Self::from_mnemonic(bitcoin_network, &mnemonic, SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                                         password.as_str()).expect("can not generate wallet");
+        let receiver = wallet.master.get((0,0)).unwrap().get_key(0).unwrap().address.clone();
         eprintln!();
         eprintln!("You will need the encryption password to use the funds with defiads.");
         eprintln!();
@@ -321,28 +816,54 @@ impl Wallet {
         assert_eq!(answer, "yes", "expecting yes");
         eprintln!("===========================================================================================");
         eprintln!();
-        Wallet {
-            master,
-            coins: Coins::new()
-        }
+        wallet
     }
+
+    /// restore a wallet from the words of its key, birth is the time it was created or earlier
+    pub fn restore(bitcoin_network: Network, birth: u64) -> Wallet {
+        eprintln!();
+        eprintln!("============================= Restoring bitcoin wallet ====================================");
+        eprint!("Enter the words of your key separated by space:");
+        let mut words = String::new();
+        std::io::stdin().read_line(&mut words).expect("expecting words");
+        let mnemonic = Mnemonic::from_str(words.split_whitespace().collect::<Vec<_>>().join(" ").as_str()).expect("can not read words");
+        eprintln!("The key will be stored ENCRYPTED in the config-file");
+        eprintln!();
+        let password = read_password();
+        let wallet = Self::from_mnemonic(bitcoin_network, &mnemonic, birth, password.as_str()).expect("can not restore wallet");
+        eprintln!();
+        eprintln!("The first receiver address of the restored key (BIP44 keypath: m/49'/0'/0/0): {}",
+                  wallet.master.get((0,0)).unwrap().get_key(0).unwrap().address);
+        eprintln!("===========================================================================================");
+        eprintln!();
+        wallet
+    }
+}
+
+fn read_password() -> String {
+    eprint!("Set wallet encryption password (minimum length 8):");
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password).expect("expecting a password");
+    password.remove(password.len()-1); // remove EOL
+    assert!(password.len() >= 8, "Password should have at least 8 characters");
+    password
 }
 
 #[cfg(test)]
 mod test {
     use crate::wallet::Wallet;
-    use bitcoin::{network::constants::Network, blockdata::opcodes::all, BlockHeader, Block, BitcoinHash, util::bip32::ExtendedPubKey, Address, Transaction, TxIn, OutPoint, TxOut, PublicKey};
-    use std::{
-        sync::{Arc, Mutex},
-        str::FromStr
-    };
+    use bitcoin::{network::constants::Network, blockdata::opcodes::all, BlockHeader, Block, BitcoinHash, Address, Transaction, TxIn, OutPoint, TxOut, PublicKey};
+    use std::sync::{Arc, Mutex};
     use bitcoin_hashes::{sha256, sha256d};
     use crate::trunk::Trunk;
     use bitcoin::blockdata::constants::genesis_block;
     use std::time::{SystemTime, UNIX_EPOCH};
     use bitcoin::util::hash::MerkleRoot;
-    use bitcoin_wallet::account::{Account, AccountAddressType, Unlocker};
+    use bitcoin_wallet::account::{Account, AccountAddressType, Unlocker, MasterAccount, MasterKeyEntropy};
+    use bitcoin_wallet::coins::Coins;
+    use bitcoin_wallet::mnemonic::Mnemonic;
     use bitcoin::blockdata::script::Builder;
+    use bitcoin::SigHashType;
     use crate::store::ContentStore;
 
     const NEW_COINS:u64 = 1000000000;
@@ -391,10 +912,8 @@ mod test {
     }
 
     fn new_wallet () -> Wallet {
-        let mut wallet = Wallet::from_encrypted(
-            hex::decode("0e05ba48bb0fdc7285dc9498202aeee5e1777ac4f55072b30f15f6a8632ad0f3fde1c41d9e162dbe5d3153282eaebd081cf3b3312336fc56f5dd18a2df6ea48c1cdd11a1ed11281cd2e0f864f02e5bed5ab03326ed24e43b8a184acff9cb4e730db484e33f2b24295a97b2ca87871a69384eb64d4160ce8b3e8b4d90234040970e531d4333a8979dbe533c2b2668bf43b6607b2d24c5b42765ebfdd075fd173c").unwrap().as_slice(),
-            ExtendedPubKey::from_str("tpubD6NzVbkrYhZ4XKz4vgwBmnnVmA7EgWhnXvimQ4krq94yUgcSSbroi4uC1xbZ3UGMxG9M2utmaPjdpMrWW2uKRY9Mj4DZWrrY8M4pry8shsK").unwrap(),
-            1567260002);
+        let mut wallet = Wallet::from_storage(Coins::new(),
+            MasterAccount::new(MasterKeyEntropy::Low, Network::Testnet, PASSPHRASE).unwrap());
         let mut unlocker = Unlocker::new_for_master(&wallet.master, PASSPHRASE).unwrap();
         wallet.master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 0, 10).unwrap());
         wallet.master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 1, 10).unwrap());
@@ -463,7 +982,7 @@ mod test {
         assert_eq!(wallet.balance(), NEW_COINS);
 
         let burn = Address::p2shwsh(&Builder::new().push_opcode(all::OP_VERIFY).into_script(), Network::Testnet);
-        let (burn_half, _) = wallet.withdraw(PASSPHRASE.to_string(), burn, 1, Some(NEW_COINS/2), trunk.clone()).unwrap();
+        let (burn_half, _, _) = wallet.withdraw(PASSPHRASE.to_string(), burn, 1, Some(NEW_COINS/2), None, trunk.clone()).unwrap();
 
         let mut next = mine(&next.bitcoin_hash(), 2, &miner);
         add_tx(&mut next, burn_half);
@@ -471,10 +990,11 @@ mod test {
         wallet.process(&next);
         assert_eq!(wallet.balance(), NEW_COINS + NEW_COINS/2);
 
-        let (fund, _, fee) = wallet.fund(&sha256::Hash::default(), 1, PASSPHRASE.to_string(), 5, NEW_COINS/10, trunk.clone(),
+        let (commitment, _) = wallet.commit(&sha256::Hash::default(), 1,
             |pk: &PublicKey, term: Option<u16>| {
                 ContentStore::funding_script(pk, term.unwrap())
-            }).unwrap();
+            });
+        let (fund, _, fee) = wallet.fund(&commitment, PASSPHRASE.to_string(), 5, NEW_COINS/10, None, trunk.clone()).unwrap();
 
         let mut next = mine(&next.bitcoin_hash(), 3, &miner);
         add_tx(&mut next, fund);
@@ -489,4 +1009,115 @@ mod test {
         assert_eq!(wallet.balance(), 3*NEW_COINS + NEW_COINS/2 - fee);
         assert_eq!(wallet.available_balance(4, |h| trunk.get_height(h)), 3*NEW_COINS + NEW_COINS/2 - fee);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_inputs () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut wallet = new_wallet();
+        let genesis = genesis_block(Network::Testnet);
+        let miner = wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        trunk.extend(&genesis.header);
+        wallet.process(&genesis);
+        let next = mine(&genesis.bitcoin_hash(), 1, &miner);
+        trunk.extend(&next.header);
+        wallet.process(&next);
+
+        let burn = Address::p2shwsh(&Builder::new().push_opcode(all::OP_VERIFY).into_script(), Network::Testnet);
+        let (burn_half, _, _) = wallet.withdraw(PASSPHRASE.to_string(), burn.clone(), 1, Some(NEW_COINS/2), None, trunk.clone()).unwrap();
+        let small = OutPoint { txid: burn_half.txid(), vout: burn_half.output.iter().position(|o| o.script_pubkey != burn.script_pubkey()).unwrap() as u32 };
+        let mut next = mine(&next.bitcoin_hash(), 2, &miner);
+        let large = OutPoint { txid: next.txdata[0].txid(), vout: 0 };
+        add_tx(&mut next, burn_half);
+        trunk.extend(&next.header);
+        wallet.process(&next);
+
+        // the smallest sufficient coin is chosen
+        let (commitment, _) = wallet.commit(&sha256::Hash::default(), 1,
+            |pk: &PublicKey, term: Option<u16>| ContentStore::funding_script(pk, term.unwrap()));
+        let chosen = wallet.choose_inputs(NEW_COINS/10, 3, &(trunk.clone() as Arc<dyn Trunk>), None).unwrap();
+        assert_eq!(chosen.iter().map(|(p, _, _)| *p).collect::<Vec<_>>(), vec!(small));
+
+        // a locked coin is not chosen, but may be given
+        assert!(wallet.lock(small));
+        assert!(!wallet.lock(small));
+        assert!(wallet.unspent(trunk.clone()).iter().any(|u| u.locked && u.txid == small.txid.to_string()));
+        assert!(wallet.fund(&commitment, PASSPHRASE.to_string(), 5, NEW_COINS/10, Some(vec!(small, small)), trunk.clone()).is_err());
+        assert!(wallet.fund(&commitment, PASSPHRASE.to_string(), 5, NEW_COINS/10, Some(vec!(OutPoint::default())), trunk.clone()).is_err());
+        let (fund, _, _) = wallet.fund(&commitment, PASSPHRASE.to_string(), 5, NEW_COINS/10, None, trunk.clone()).unwrap();
+        assert_eq!(fund.input.iter().map(|i| i.previous_output).collect::<Vec<_>>(), vec!(large));
+        let (fund, spent, _) = wallet.fund(&commitment, PASSPHRASE.to_string(), 5, NEW_COINS/10, Some(vec!(small)), trunk.clone()).unwrap();
+        assert_eq!(fund.input.iter().map(|i| i.previous_output).collect::<Vec<_>>(), vec!(small));
+        assert_eq!(spent.len(), 1);
+        // no longer available once spent
+        assert!(wallet.fund(&commitment, PASSPHRASE.to_string(), 5, NEW_COINS/10, Some(vec!(small)), trunk.clone()).is_err());
+        assert!(wallet.unlock(&small));
+    }
+
+    #[test]
+    pub fn test_psbt () {
+        let trunk = Arc::new(
+            TestTrunk{trunk: Arc::new(Mutex::new(Vec::new()))});
+        let mut master = MasterAccount::new(MasterKeyEntropy::Low, Network::Testnet, PASSPHRASE).unwrap();
+        let mut unlocker = Unlocker::new_for_master(&master, PASSPHRASE).unwrap();
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 0, 10).unwrap());
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WPKH, 0, 1, 10).unwrap());
+        master.add_account(Account::new(&mut unlocker, AccountAddressType::P2WSH(4711), 1, 0, 0).unwrap());
+        // the same accounts without keys
+        let mut watch_only = MasterAccount::watch_only(master.master_public().clone(), 0);
+        for (a, s) in &[(0, 0), (0, 1), (1, 0)] {
+            let account = master.get((*a, *s)).unwrap();
+            let mut copy = Account::new_from_storage(account.address_type(), *a, *s, account.master_public().clone(),
+                                                     Vec::new(), 0, account.look_ahead(), Network::Testnet);
+            copy.do_look_ahead(None).unwrap();
+            watch_only.add_account(copy);
+        }
+        let signer = Wallet::from_storage(Coins::new(), master);
+        let mut wallet = Wallet::from_storage(Coins::new(), watch_only);
+        assert!(wallet.is_watch_only() && !signer.is_watch_only());
+
+        let genesis = genesis_block(Network::Testnet);
+        let miner = wallet.master.get_mut((0,0)).unwrap().next_key().unwrap().address.clone();
+        trunk.extend(&genesis.header);
+        wallet.process(&genesis);
+        let next = mine(&genesis.bitcoin_hash(), 1, &miner);
+        trunk.extend(&next.header);
+        wallet.process(&next);
+        assert_eq!(wallet.balance(), NEW_COINS);
+
+        let burn = Address::p2shwsh(&Builder::new().push_opcode(all::OP_VERIFY).into_script(), Network::Testnet);
+        let (unsigned, spent, fee) = wallet.withdraw(String::new(), burn, 1, Some(NEW_COINS/2), None, trunk.clone()).unwrap();
+        assert!(unsigned.input.iter().all(|i| i.witness.is_empty()));
+        assert!(fee > 0);
+        assert!(wallet.bump_fee(String::new(), &unsigned, &spent, 5).is_err());
+
+        let mut psbt = wallet.psbt(&unsigned, &spent).unwrap();
+        assert_eq!(psbt.inputs[0].witness_utxo, Some(spent[0].clone()));
+        assert!(wallet.finalize(psbt.clone(), &spent).is_err());
+
+        // sign elsewhere
+        let mut signed = unsigned.clone();
+        let mut unlocker = Unlocker::new_for_master(&signer.master, PASSPHRASE).unwrap();
+        assert_eq!(signer.master.sign(&mut signed, SigHashType::All, &|_| Some(spent[0].clone()), &mut unlocker).unwrap(), 1);
+        let key = *psbt.inputs[0].hd_keypaths.keys().next().unwrap();
+        psbt.inputs[0].partial_sigs.insert(key, signed.input[0].witness[0].clone());
+
+        let finalized = wallet.finalize(psbt, &spent).unwrap();
+        assert_eq!(finalized, signed);
+        assert_eq!(finalized.txid(), unsigned.txid());
+    }
+
+    #[test]
+    pub fn test_mnemonic () {
+        let mnemonic = Mnemonic::new(&[7u8; 16]).unwrap();
+        let wallet = Wallet::from_mnemonic(Network::Testnet, &mnemonic, 1567260002, PASSPHRASE).unwrap();
+        assert_eq!(wallet.mnemonic(PASSPHRASE).unwrap(), mnemonic);
+        assert!(wallet.mnemonic("wrong").is_err());
+        // the same key as restored from the words
+        let restored = Wallet::from_mnemonic(Network::Testnet, &Mnemonic::from_str(mnemonic.to_string().as_str()).unwrap(), 0, "other passphrase").unwrap();
+        assert_eq!(restored.master_public(), wallet.master_public());
+        assert_eq!(restored.master.get((0,0)).unwrap().get_key(0).unwrap().address, wallet.master.get((0,0)).unwrap().get_key(0).unwrap().address);
+        let watch_only = Wallet::from_storage(Coins::new(), MasterAccount::watch_only(wallet.master_public().clone(), 0));
+        assert!(watch_only.mnemonic(PASSPHRASE).is_err());
+    }
+}